@@ -0,0 +1,269 @@
+//! `rat-client bench`: load a server with concurrent sessions and/or a
+//! steady rate of executes, then report latency percentiles and errors.
+
+use crate::{connect, create_session, negotiate, stop_session, Auth, PROTOCOL_HEADER, PROTOCOL_VERSION};
+use anyhow::Result;
+use clap::Args as ClapArgs;
+use futures::{SinkExt, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(ClapArgs, Debug)]
+pub(crate) struct BenchArgs {
+    /// Server URL
+    url: String,
+
+    /// Concurrent shell sessions to hold open, each typing a probe line
+    /// once a second and timing its echo
+    #[arg(short = 'n', long, default_value_t = 0)]
+    sessions: usize,
+
+    /// Executes to fire per second
+    #[arg(short = 'r', long, default_value_t = 0)]
+    rate: u32,
+
+    /// Seconds to run for
+    #[arg(short, long, default_value_t = 10)]
+    duration: u64,
+
+    /// Command each execute runs
+    #[arg(long, default_value = "true")]
+    command: String,
+
+    /// Seconds before a single operation counts as failed
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+
+    /// Don't ask the server to compress shell output
+    #[arg(long)]
+    no_compression: bool,
+
+    #[command(flatten)]
+    auth: Auth,
+}
+
+/// Latencies and errors of one kind of operation
+#[derive(Default)]
+struct Samples {
+    latencies: Vec<Duration>,
+    errors: BTreeMap<String, usize>,
+}
+
+#[derive(Clone, Default)]
+struct Recorder {
+    operations: Arc<Mutex<BTreeMap<&'static str, Samples>>>,
+}
+
+impl Recorder {
+    /// Time `operation` and record its latency, or its error
+    async fn time<T, F>(&self, name: &'static str, timeout: Duration, operation: F) -> Option<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let start = Instant::now();
+        let result = match tokio::time::timeout(timeout, operation).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow::anyhow!("timed out after {:?}", timeout)),
+        };
+        let mut operations = self.operations.lock().unwrap();
+        let samples = operations.entry(name).or_default();
+        match result {
+            Ok(value) => {
+                samples.latencies.push(start.elapsed());
+                Some(value)
+            }
+            Err(e) => {
+                *samples.errors.entry(e.to_string()).or_default() += 1;
+                None
+            }
+        }
+    }
+
+    fn report(&self, elapsed: Duration) {
+        let operations = self.operations.lock().unwrap();
+        println!(
+            "{:<10} {:>8} {:>8} {:>7} {:>9} {:>9} {:>9} {:>9}",
+            "operation", "ok", "errors", "err %", "p50", "p90", "p99", "max"
+        );
+        for (name, samples) in operations.iter() {
+            let mut latencies = samples.latencies.clone();
+            latencies.sort();
+            let errors: usize = samples.errors.values().sum();
+            let total = latencies.len() + errors;
+            println!(
+                "{:<10} {:>8} {:>8} {:>6.1}% {:>9} {:>9} {:>9} {:>9}",
+                name,
+                latencies.len(),
+                errors,
+                100.0 * errors as f64 / total.max(1) as f64,
+                format_ms(percentile(&latencies, 50.0)),
+                format_ms(percentile(&latencies, 90.0)),
+                format_ms(percentile(&latencies, 99.0)),
+                format_ms(latencies.last().copied()),
+            );
+        }
+        for (name, samples) in operations.iter() {
+            for (error, count) in &samples.errors {
+                println!("  {} x{}: {}", name, count, error);
+            }
+        }
+        if let Some(executes) = operations.get("execute") {
+            let completed = executes.latencies.len() as f64;
+            println!("\nexecute throughput: {:.1}/s", completed / elapsed.as_secs_f64());
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted latencies
+fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn format_ms(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.1}ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+/// Hold one session open until `deadline`, timing a typed probe's echo
+/// once a second
+async fn run_session(
+    client: reqwest::Client,
+    api: String,
+    args: Arc<BenchArgs>,
+    recorder: Recorder,
+    deadline: Instant,
+) {
+    let timeout = Duration::from_secs(args.timeout);
+    let Some(session) = recorder.time("create", timeout, create_session(&client, &api)).await else {
+        return;
+    };
+    let connected = recorder
+        .time("attach", timeout, connect(&session.ws_url, !args.no_compression, &args.auth))
+        .await;
+
+    if let Some((socket, mut inflater)) = connected {
+        let (mut ws_tx, mut ws_rx) = socket.split();
+        let mut probe = 0u64;
+        while Instant::now() < deadline {
+            probe += 1;
+            let marker = format!("rat-bench-{}", probe);
+            let round_trip = async {
+                ws_tx.send(Message::Binary(format!("# {}\n", marker).into_bytes())).await?;
+                let mut output = String::new();
+                while !output.contains(&marker) {
+                    match ws_rx.next().await {
+                        Some(Ok(Message::Binary(data))) => {
+                            let data = match inflater.as_mut() {
+                                Some(inflater) => inflater.inflate(&data)?,
+                                None => data,
+                            };
+                            output.push_str(&String::from_utf8_lossy(&data));
+                        }
+                        Some(Ok(Message::Close(_))) | None => anyhow::bail!("socket closed"),
+                        Some(Err(e)) => return Err(e.into()),
+                        Some(Ok(_)) => {}
+                    }
+                }
+                Ok::<_, anyhow::Error>(())
+            };
+            if recorder.time("echo", timeout, round_trip).await.is_none() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_secs(1).min(deadline.saturating_duration_since(Instant::now()))).await;
+        }
+    }
+
+    recorder.time("stop", timeout, stop_session(&client, &api, &session.session_id)).await;
+}
+
+async fn execute(client: reqwest::Client, api: String, command: String) -> Result<()> {
+    let response = client
+        .post(format!("{}/execute", api))
+        .header(PROTOCOL_HEADER, PROTOCOL_VERSION)
+        .json(&serde_json::json!({ "command": command }))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("HTTP {}: {}", status, response.text().await.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Fire `rate` executes per second until `deadline`, without waiting for
+/// earlier ones, so a slow server shows up as latency rather than as a
+/// lower request rate
+async fn run_executes(
+    client: reqwest::Client,
+    api: String,
+    args: Arc<BenchArgs>,
+    recorder: Recorder,
+    deadline: Instant,
+) {
+    let timeout = Duration::from_secs(args.timeout);
+    let mut ticker = tokio::time::interval(Duration::from_secs(1) / args.rate);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Burst);
+    let mut in_flight = Vec::new();
+    while Instant::now() < deadline {
+        ticker.tick().await;
+        let request = execute(client.clone(), api.clone(), args.command.clone());
+        let recorder = recorder.clone();
+        in_flight.push(tokio::spawn(async move {
+            recorder.time("execute", timeout, request).await;
+        }));
+    }
+    for task in in_flight {
+        let _ = task.await;
+    }
+}
+
+pub(crate) async fn run(args: BenchArgs) -> Result<()> {
+    if args.sessions == 0 && args.rate == 0 {
+        anyhow::bail!("Nothing to do: pass --sessions and/or --rate");
+    }
+    let client = args.auth.client()?;
+    let api = negotiate(&client, &args.url).await?;
+    println!(
+        "Benchmarking {} for {}s: {} sessions, {} executes/s\n",
+        api, args.duration, args.sessions, args.rate
+    );
+
+    let args = Arc::new(args);
+    let recorder = Recorder::default();
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(args.duration);
+
+    let mut tasks = Vec::new();
+    for _ in 0..args.sessions {
+        tasks.push(tokio::spawn(run_session(
+            client.clone(),
+            api.clone(),
+            args.clone(),
+            recorder.clone(),
+            deadline,
+        )));
+    }
+    if args.rate > 0 {
+        tasks.push(tokio::spawn(run_executes(
+            client.clone(),
+            api.clone(),
+            args.clone(),
+            recorder.clone(),
+            deadline,
+        )));
+    }
+    for task in tasks {
+        let _ = task.await;
+    }
+
+    recorder.report(start.elapsed());
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! Shell completions and man pages, generated from the clap definitions so
+//! they can't drift from the flags.
+
+use clap::{Command, CommandFactory, ValueEnum};
+use clap_complete::Shell;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Print the completion script for `shell` to stdout
+pub fn print_completions<C: CommandFactory>(shell: Shell) {
+    let mut cmd = C::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+}
+
+/// Write man pages to `<out_dir>/man` (one for the binary and one per
+/// visible subcommand) and completion scripts for every supported shell to
+/// `<out_dir>/completions`
+pub fn generate_docs<C: CommandFactory>(out_dir: &Path) -> anyhow::Result<()> {
+    let mut cmd = C::command();
+    // Fills in the subcommands' display names, e.g. rat-client-bench
+    cmd.build();
+    let name = cmd.get_name().to_string();
+
+    let man_dir = out_dir.join("man");
+    fs::create_dir_all(&man_dir)?;
+    write_man(&cmd, &man_dir.join(format!("{}.1", name)))?;
+    for sub in cmd.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        let page = sub.get_display_name().unwrap_or(sub.get_name());
+        write_man(sub, &man_dir.join(format!("{}.1", page)))?;
+    }
+
+    let completions_dir = out_dir.join("completions");
+    fs::create_dir_all(&completions_dir)?;
+    for shell in Shell::value_variants() {
+        clap_complete::generate_to(*shell, &mut cmd, &name, &completions_dir)?;
+    }
+    eprintln!("Wrote man pages and completions for {} to {}", name, out_dir.display());
+    Ok(())
+}
+
+fn write_man(cmd: &Command, path: &Path) -> anyhow::Result<()> {
+    let mut page = Vec::new();
+    clap_mangen::Man::new(cmd.clone()).render(&mut page)?;
+    fs::write(path, page)?;
+    Ok(())
+}
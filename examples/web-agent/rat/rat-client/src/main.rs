@@ -1,17 +1,50 @@
+mod bench;
+mod docs;
+mod replay;
+mod watch;
+
 use anyhow::Result;
-use clap::Parser;
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use clap_complete::Shell;
+use flate2::{Decompress, FlushDecompress};
+use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
 use std::io::{self, Write};
+use std::path::PathBuf;
 use termion::raw::IntoRawMode;
 use tokio::io::AsyncReadExt;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        client::IntoClientRequest,
+        error::{Error as WsError, ProtocolError},
+        handshake::client::Request,
+        protocol::Message,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+/// Protocol version this client speaks; sent as `X-Rat-Protocol`
+const PROTOCOL_VERSION: u32 = 1;
+const PROTOCOL_HEADER: &str = "x-rat-protocol";
+
+/// WebSocket subprotocol for deflate-compressed server output
+const DEFLATE_PROTOCOL: &str = "rat.deflate";
+
+type WsStream = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "RAT client - Connect to remote shell")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Server URL (e.g., https://example.ngrok-free.dev)
-    url: String,
+    #[arg(required = true)]
+    url: Option<String>,
 
     /// Session ID to reconnect to (optional)
     #[arg(short, long)]
@@ -20,6 +53,75 @@ struct Args {
     /// Stop a session
     #[arg(short = 'k', long)]
     stop: Option<String>,
+
+    /// Don't ask the server to compress shell output
+    #[arg(long)]
+    no_compression: bool,
+
+    #[command(flatten)]
+    auth: Auth,
+}
+
+/// Token for servers that set `auth.require_token`
+#[derive(ClapArgs, Debug, Clone, Default)]
+pub(crate) struct Auth {
+    /// API token, or the admin token, sent as `Authorization: Bearer`
+    #[arg(long, env = "RAT_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+}
+
+impl Auth {
+    /// HTTP client that sends the token with every request
+    pub(crate) fn client(&self) -> Result<reqwest::Client> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(token) = &self.token {
+            let mut value = reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        Ok(reqwest::Client::builder().default_headers(headers).build()?)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Load-test a server with concurrent sessions and/or executes
+    Bench(bench::BenchArgs),
+    /// Play back a recorded session's output
+    Replay(replay::ReplayArgs),
+    /// Re-run a command on the server at an interval and redraw its output
+    Watch(watch::WatchArgs),
+    /// Print a completion script for your shell to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Write man pages and completion scripts into a directory, for packaging
+    #[command(hide = true)]
+    GenerateDocs { out_dir: PathBuf },
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    protocols: Vec<u32>,
+    api_prefix: String,
+}
+
+/// What `/capabilities` says the server supports. Everything defaults to
+/// unsupported, so fields newer servers add are ignored and ones older
+/// servers lack read as absent.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Capabilities {
+    framing: Framing,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Framing {
+    resize: bool,
+    compression: Vec<String>,
+    create_and_attach: bool,
 }
 
 #[derive(Deserialize)]
@@ -31,29 +133,55 @@ struct SessionCreateResponse {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    match args.command {
+        Some(Command::Bench(bench_args)) => return bench::run(bench_args).await,
+        Some(Command::Replay(replay_args)) => return replay::run(replay_args).await,
+        Some(Command::Watch(watch_args)) => return watch::run(watch_args).await,
+        Some(Command::Completions { shell }) => {
+            docs::print_completions::<Args>(shell);
+            return Ok(());
+        }
+        Some(Command::GenerateDocs { out_dir }) => return docs::generate_docs::<Args>(&out_dir),
+        None => {}
+    }
+    // Required unless a subcommand was given
+    let url = args.url.expect("url is required");
+    let client = args.auth.client()?;
+    let api = negotiate(&client, &url).await?;
 
     // Handle stop session
     if let Some(session_id) = args.stop {
-        stop_session(&args.url, &session_id).await?;
+        stop_session(&client, &api, &session_id).await?;
         println!("Session {} stopped", session_id);
         return Ok(());
     }
 
-    // Get or create session
-    let ws_url = if let Some(session_id) = args.session {
-        // Reconnect to existing session
-        let base = args.url.replace("https://", "wss://").replace("http://", "ws://");
-        format!("{}/shell/{}", base, session_id)
+    // Use what the server says it supports. Servers that predate
+    // /capabilities are asked for compression and /shell/new anyway, and
+    // fallen back from if they refuse, but never sent resize frames, which
+    // they might type into the shell.
+    let capabilities = capabilities(&client, &api).await?;
+    let compression = !args.no_compression
+        && capabilities
+            .as_ref()
+            .map_or(true, |c| c.framing.compression.iter().any(|p| p == DEFLATE_PROTOCOL));
+    let resize = capabilities.as_ref().is_some_and(|c| c.framing.resize);
+
+    // Connect WebSocket, to an existing session or a new one
+    let (ws_stream, mut inflater) = if let Some(session_id) = args.session {
+        connect(&format!("{}/shell/{}", ws_base(&api), session_id), compression, &args.auth).await?
+    } else if let Some((session_id, ws_stream, inflater)) =
+        connect_new(&api, compression, &args.auth, capabilities.as_ref()).await
+    {
+        println!("🔗 Created session: {}", session_id);
+        (ws_stream, inflater)
     } else {
-        // Create new session
-        let response = create_session(&args.url).await?;
+        // Servers without /shell/new: create the session, then connect
+        let response = create_session(&client, &api).await?;
         println!("🔗 Created session: {}", response.session_id);
         println!("🔗 Connecting to remote shell...\n");
-        response.ws_url
+        connect(&response.ws_url, compression, &args.auth).await?
     };
-
-    // Connect WebSocket
-    let (ws_stream, _) = connect_async(&ws_url).await?;
     println!("[REMOTE] Connected!\n");
 
     let (mut ws_tx, mut ws_rx) = ws_stream.split();
@@ -65,10 +193,15 @@ async fn main() -> Result<()> {
     let (shutdown_tx, mut shutdown_rx) = tokio::sync::mpsc::channel::<()>(1);
     let shutdown_tx2 = shutdown_tx.clone();
 
-    // Task 1: Read from stdin, send to WebSocket
+    // Task 1: Read from stdin, send to WebSocket, and keep the remote
+    // terminal the size of this one
     let stdin_task = tokio::spawn(async move {
         let mut stdin = tokio::io::stdin();
         let mut buf = [0u8; 1024];
+        let mut window_changes = if resize { signal(SignalKind::window_change()).ok() } else { None };
+        if resize && send_size(&mut ws_tx).await.is_err() {
+            return;
+        }
 
         loop {
             tokio::select! {
@@ -83,6 +216,16 @@ async fn main() -> Result<()> {
                         _ => break,
                     }
                 }
+                Some(()) = async {
+                    match window_changes.as_mut() {
+                        Some(changes) => changes.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    if send_size(&mut ws_tx).await.is_err() {
+                        break;
+                    }
+                }
                 _ = shutdown_rx.recv() => {
                     break;
                 }
@@ -90,11 +233,20 @@ async fn main() -> Result<()> {
         }
     });
 
-    // Task 2: Read from WebSocket, write to stdout
+    // Task 2: Read from WebSocket, write to stdout; ends with why the
+    // server closed the socket, if it said
     let stdout_task = tokio::spawn(async move {
+        let mut closed_because = None;
         while let Some(Ok(msg)) = ws_rx.next().await {
             match msg {
                 Message::Binary(data) => {
+                    let data = match inflater.as_mut() {
+                        Some(inflater) => match inflater.inflate(&data) {
+                            Ok(data) => data,
+                            Err(_) => break,
+                        },
+                        None => data,
+                    };
                     if stdout.write_all(&data).is_err() {
                         break;
                     }
@@ -110,44 +262,213 @@ async fn main() -> Result<()> {
                         break;
                     }
                 }
-                Message::Close(_) => {
+                Message::Close(frame) => {
+                    closed_because = frame.map(|frame| close_message(frame.code.into(), &frame.reason));
                     let _ = shutdown_tx2.send(()).await;
                     break;
                 }
                 _ => {}
             }
         }
+        closed_because
     });
 
     // Wait for either task to finish
-    tokio::select! {
-        _ = stdin_task => {},
-        _ = stdout_task => {},
+    let closed_because = tokio::select! {
+        _ = stdin_task => None,
+        result = stdout_task => result.ok().flatten(),
+    };
+
+    match closed_because {
+        Some(message) => println!("\n🔌 Disconnected: {}", message),
+        None => println!("\n🔌 Disconnected"),
     }
 
-    println!("\n🔌 Disconnected");
+    Ok(())
+}
 
+/// Tell the server this terminal's size with a resize frame. Nothing is
+/// sent when stdout isn't a terminal.
+async fn send_size(ws_tx: &mut SplitSink<WsStream, Message>) -> Result<()> {
+    let Ok((cols, rows)) = termion::terminal_size() else {
+        return Ok(());
+    };
+    let resize = serde_json::json!({"type": "resize", "cols": cols, "rows": rows});
+    ws_tx.send(Message::Text(resize.to_string())).await?;
     Ok(())
 }
 
-async fn create_session(base_url: &str) -> Result<SessionCreateResponse> {
-    let client = reqwest::Client::new();
+/// Reason the server gives when it closes a shell socket
+#[derive(Deserialize)]
+struct CloseReason {
+    message: String,
+}
+
+/// What to tell the user about a close frame. Servers send a JSON reason
+/// with a readable `message`; older ones send plain text or nothing.
+fn close_message(code: u16, reason: &str) -> String {
+    match serde_json::from_str::<CloseReason>(reason) {
+        Ok(reason) => reason.message,
+        Err(_) if reason.is_empty() => format!("connection closed (code {})", code),
+        Err(_) => format!("{} (code {})", reason, code),
+    }
+}
+
+/// Decompressor for `rat.deflate` frames; one per connection, fed in order
+struct Inflater {
+    inner: Decompress,
+}
+
+impl Inflater {
+    fn new() -> Self {
+        Inflater {
+            inner: Decompress::new(false),
+        }
+    }
+
+    fn inflate(&mut self, input: &[u8]) -> Result<Vec<u8>> {
+        let start = self.inner.total_in();
+        let mut out = Vec::with_capacity(input.len() * 4 + 64);
+        loop {
+            if out.capacity() - out.len() < 64 {
+                out.reserve(out.capacity());
+            }
+            let consumed = (self.inner.total_in() - start) as usize;
+            self.inner
+                .decompress_vec(&input[consumed..], &mut out, FlushDecompress::Sync)?;
+            let consumed = (self.inner.total_in() - start) as usize;
+            if consumed == input.len() && out.len() < out.capacity() {
+                return Ok(out);
+            }
+        }
+    }
+}
+
+fn ws_request(ws_url: &str, compression: bool, auth: &Auth) -> Result<Request> {
+    let mut request = ws_url.into_client_request()?;
+    let headers = request.headers_mut();
+    headers.insert(PROTOCOL_HEADER, PROTOCOL_VERSION.into());
+    if let Some(token) = &auth.token {
+        headers.insert("authorization", format!("Bearer {}", token).parse()?);
+    }
+    if compression {
+        headers.insert("sec-websocket-protocol", DEFLATE_PROTOCOL.parse()?);
+    }
+    Ok(request)
+}
+
+/// Connect to the shell socket, asking for compression when `compression`
+/// is set. Servers that don't offer it reject the subprotocol, in which
+/// case the connection is retried without it.
+async fn connect(ws_url: &str, compression: bool, auth: &Auth) -> Result<(WsStream, Option<Inflater>)> {
+    let result = connect_async(ws_request(ws_url, compression, auth)?).await;
+    let (ws_stream, response) = match result {
+        Err(WsError::Protocol(ProtocolError::SecWebSocketSubProtocolError(_))) if compression => {
+            connect_async(ws_request(ws_url, false, auth)?).await?
+        }
+        other => other?,
+    };
+
+    let compressed = response
+        .headers()
+        .get("sec-websocket-protocol")
+        .map_or(false, |p| p.as_bytes() == DEFLATE_PROTOCOL.as_bytes());
+    Ok((ws_stream, compressed.then(Inflater::new)))
+}
+
+/// WebSocket base URL for the API at `api`
+fn ws_base(api: &str) -> String {
+    api.replace("https://", "wss://").replace("http://", "ws://")
+}
+
+/// First frame on `/shell/new`, naming the session it created
+#[derive(Deserialize)]
+struct SessionAnnouncement {
+    session_id: String,
+}
+
+/// Create a session and connect to it in one round trip. `None` if the
+/// server's capabilities say it can't, or that failed, including on
+/// servers that predate `/shell/new`.
+async fn connect_new(
+    api: &str,
+    compression: bool,
+    auth: &Auth,
+    capabilities: Option<&Capabilities>,
+) -> Option<(String, WsStream, Option<Inflater>)> {
+    if capabilities.is_some_and(|c| !c.framing.create_and_attach) {
+        return None;
+    }
+    let (mut ws_stream, inflater) = connect(&format!("{}/shell/new", ws_base(api)), compression, auth).await.ok()?;
+    match ws_stream.next().await {
+        Some(Ok(Message::Text(text))) => {
+            let announcement = serde_json::from_str::<SessionAnnouncement>(&text).ok()?;
+            Some((announcement.session_id, ws_stream, inflater))
+        }
+        _ => None,
+    }
+}
+
+/// The server's capabilities; `None` for servers that predate
+/// `/capabilities`. A rejected token is an error, not an older server.
+async fn capabilities(client: &reqwest::Client, api: &str) -> Result<Option<Capabilities>> {
+    let response = client
+        .get(format!("{}/capabilities", api))
+        .header(PROTOCOL_HEADER, PROTOCOL_VERSION)
+        .send()
+        .await?;
+    match response.status() {
+        reqwest::StatusCode::UNAUTHORIZED => {
+            anyhow::bail!("Server rejected the API token; pass --token or set RAT_TOKEN")
+        }
+        status if status.is_success() => Ok(response.json().await.ok()),
+        _ => Ok(None),
+    }
+}
+
+/// Agree on a protocol version and return the base URL for API calls.
+/// Servers that predate `/version` only serve the unprefixed routes.
+async fn negotiate(client: &reqwest::Client, server_url: &str) -> Result<String> {
+    let server_url = server_url.trim_end_matches('/');
+    let response = client
+        .get(format!("{}/version", server_url))
+        .header(PROTOCOL_HEADER, PROTOCOL_VERSION)
+        .send()
+        .await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(server_url.to_string());
+    }
+
+    let version = response.error_for_status()?.json::<VersionResponse>().await?;
+    if !version.protocols.contains(&PROTOCOL_VERSION) {
+        anyhow::bail!(
+            "Server speaks protocol versions {:?}, this client needs {}",
+            version.protocols,
+            PROTOCOL_VERSION
+        );
+    }
+    Ok(format!("{}{}", server_url, version.api_prefix))
+}
+
+async fn create_session(client: &reqwest::Client, base_url: &str) -> Result<SessionCreateResponse> {
     let url = format!("{}/session/create", base_url);
 
     let response = client.post(&url)
+        .header(PROTOCOL_HEADER, PROTOCOL_VERSION)
         .send()
         .await?
+        .error_for_status()?
         .json::<SessionCreateResponse>()
         .await?;
 
     Ok(response)
 }
 
-async fn stop_session(base_url: &str, session_id: &str) -> Result<()> {
-    let client = reqwest::Client::new();
+async fn stop_session(client: &reqwest::Client, base_url: &str, session_id: &str) -> Result<()> {
     let url = format!("{}/session/{}/stop", base_url, session_id);
 
-    client.post(&url).send().await?;
+    client.post(&url).header(PROTOCOL_HEADER, PROTOCOL_VERSION).send().await?.error_for_status()?;
 
     Ok(())
 }
@@ -0,0 +1,76 @@
+//! `rat-client replay`: play a recorded session's output back to the
+//! terminal with its original timing, to reproduce rendering bugs.
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::Args as ClapArgs;
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+use termion::raw::IntoRawMode;
+
+#[derive(ClapArgs, Debug)]
+pub(crate) struct ReplayArgs {
+    /// Recording written by the server's `shell.record_dir`
+    file: PathBuf,
+
+    /// Playback speed; 2 plays twice as fast, 0 as fast as possible
+    #[arg(long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Print the recording frame by frame instead of playing it
+    #[arg(long)]
+    frames: bool,
+}
+
+/// One line of a recording; see `rat_core::recording`
+#[derive(Deserialize)]
+struct RecordedFrame {
+    at_ms: u64,
+    direction: String,
+    kind: String,
+    data: String,
+}
+
+pub(crate) async fn run(args: ReplayArgs) -> Result<()> {
+    let text = std::fs::read_to_string(&args.file)?;
+    let mut frames = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let frame: RecordedFrame =
+            serde_json::from_str(line).map_err(|e| anyhow::anyhow!("Line {}: {}", i + 1, e))?;
+        let data = STANDARD.decode(&frame.data)?;
+        frames.push((frame, data));
+    }
+
+    if args.frames {
+        for (frame, data) in &frames {
+            println!(
+                "{:>8}ms {:<6} {:<6} {:>6}B {:?}",
+                frame.at_ms,
+                frame.direction,
+                frame.kind,
+                data.len(),
+                String::from_utf8_lossy(data)
+            );
+        }
+        return Ok(());
+    }
+
+    // Output is written exactly as the client would have received it
+    let mut stdout = io::stdout().into_raw_mode()?;
+    let mut last_ms = 0;
+    for (frame, data) in frames.iter().filter(|(frame, _)| frame.direction == "output") {
+        if args.speed > 0.0 {
+            let wait = frame.at_ms.saturating_sub(last_ms) as f64 / args.speed;
+            tokio::time::sleep(Duration::from_millis(wait as u64)).await;
+        }
+        last_ms = frame.at_ms;
+        stdout.write_all(data)?;
+        stdout.flush()?;
+    }
+    drop(stdout);
+
+    println!("\r\n⏹  End of recording");
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! `rat-client watch`: run a command on the server every few seconds and
+//! redraw its output, like watch(1). Each run is a plain `/execute`, so no
+//! shell session is held open in between.
+
+use crate::{negotiate, Auth, PROTOCOL_HEADER, PROTOCOL_VERSION};
+use anyhow::Result;
+use clap::Args as ClapArgs;
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::Duration;
+use termion::{clear, cursor, style};
+
+#[derive(ClapArgs, Debug)]
+pub(crate) struct WatchArgs {
+    /// Server URL
+    url: String,
+
+    /// Seconds between runs
+    #[arg(short = 'n', long, default_value_t = 2.0)]
+    interval: f64,
+
+    /// Highlight the characters that changed since the previous run
+    #[arg(short, long)]
+    differences: bool,
+
+    /// Command to run and its arguments, after `--`
+    #[arg(required = true, last = true)]
+    command: Vec<String>,
+
+    #[command(flatten)]
+    auth: Auth,
+}
+
+#[derive(Deserialize)]
+struct CommandResponse {
+    success: bool,
+    output: String,
+    error: Option<String>,
+}
+
+async fn execute(client: &reqwest::Client, api: &str, command: &[String]) -> Result<CommandResponse> {
+    let response = client
+        .post(format!("{}/execute", api))
+        .header(PROTOCOL_HEADER, PROTOCOL_VERSION)
+        .json(&serde_json::json!({ "command": command[0], "args": &command[1..] }))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("HTTP {}: {}", status, response.text().await.unwrap_or_default());
+    }
+    Ok(response.json().await?)
+}
+
+/// `current` with the characters that differ from `previous`, position by
+/// position on each line, in reverse video
+fn highlight(current: &str, previous: &str) -> String {
+    let mut out = String::new();
+    let mut previous_lines = previous.lines();
+    for line in current.lines() {
+        let old: Vec<char> = previous_lines.next().unwrap_or("").chars().collect();
+        let mut inverted = false;
+        for (i, c) in line.chars().enumerate() {
+            let changed = old.get(i) != Some(&c);
+            if changed != inverted {
+                if changed {
+                    let _ = write!(out, "{}", style::Invert);
+                } else {
+                    let _ = write!(out, "{}", style::NoInvert);
+                }
+                inverted = changed;
+            }
+            out.push(c);
+        }
+        if inverted {
+            let _ = write!(out, "{}", style::NoInvert);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub(crate) async fn run(args: WatchArgs) -> Result<()> {
+    if !args.interval.is_finite() || args.interval <= 0.0 {
+        anyhow::bail!("--interval must be more than 0");
+    }
+    let client = args.auth.client()?;
+    let api = negotiate(&client, &args.url).await?;
+    let interval = Duration::from_secs_f64(args.interval);
+    let title = args.command.join(" ");
+
+    let mut previous = String::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    for run in 1.. {
+        ticker.tick().await;
+        let (status, output) = match execute(&client, &api, &args.command).await {
+            Ok(response) => {
+                let mut output = response.output;
+                if let Some(stderr) = response.error {
+                    output.push_str(&stderr);
+                }
+                let status = if response.success { "ok" } else { "failed" };
+                (status.to_string(), output)
+            }
+            // Keep showing the last output; the server may be back next time
+            Err(e) => (format!("error: {}", e), previous.clone()),
+        };
+
+        let body = if args.differences && run > 1 {
+            highlight(&output, &previous)
+        } else {
+            output.clone()
+        };
+        let mut stdout = io::stdout().lock();
+        write!(stdout, "{}{}", clear::All, cursor::Goto(1, 1))?;
+        writeln!(stdout, "Every {}s: {}    [run {}, {}]\n", args.interval, title, run, status)?;
+        stdout.write_all(body.as_bytes())?;
+        stdout.flush()?;
+        previous = output;
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+// Admin dashboard. Everything here goes through the public /v1 API; the
+// admin token is only needed for the event stream and config reloads.
+(function () {
+  'use strict';
+
+  var API = '/v1';
+  var POLL_MS = 2000;
+
+  var token = sessionStorage.getItem('rat-admin-token') || '';
+  var events = null;
+
+  function $(id) {
+    return document.getElementById(id);
+  }
+
+  function api(method, path, body) {
+    var headers = { Accept: 'application/json' };
+    if (body !== undefined) {
+      headers['Content-Type'] = 'application/json';
+    }
+    if (token) {
+      headers.Authorization = 'Bearer ' + token;
+    }
+    return fetch(API + path, {
+      method: method,
+      headers: headers,
+      body: body === undefined ? undefined : JSON.stringify(body),
+    }).then(function (response) {
+      var parse = (response.headers.get('Content-Type') || '').indexOf('json') >= 0
+        ? response.json()
+        : response.text();
+      return parse.then(function (data) {
+        if (!response.ok) {
+          throw new Error(response.status + ' ' + (typeof data === 'string' ? data : JSON.stringify(data)));
+        }
+        return data;
+      });
+    });
+  }
+
+  function bytes(n) {
+    var units = ['B', 'KiB', 'MiB', 'GiB'];
+    var i = 0;
+    while (n >= 1024 && i < units.length - 1) {
+      n /= 1024;
+      i++;
+    }
+    return n.toFixed(i ? 1 : 0) + ' ' + units[i];
+  }
+
+  function cell(row, text, className) {
+    var td = document.createElement('td');
+    td.textContent = text;
+    if (className) {
+      td.className = className;
+    }
+    row.appendChild(td);
+    return td;
+  }
+
+  function button(label, onClick) {
+    var b = document.createElement('button');
+    b.textContent = label;
+    b.addEventListener('click', onClick);
+    return b;
+  }
+
+  function renderStats(stats) {
+    var rows = [
+      ['Version', stats.version],
+      ['Uptime', stats.uptime_secs + ' s'],
+      ['Sessions', stats.active_sessions],
+      ['Running jobs', stats.running_jobs],
+      ['Commands run', stats.commands_executed],
+      ['Memory', stats.memory_rss_bytes == null ? 'n/a' : bytes(stats.memory_rss_bytes)],
+      ['Tunnel', stats.tunnel.enabled ? (stats.tunnel.public_url || 'not connected') : 'disabled'],
+    ];
+    var dl = $('stats');
+    dl.textContent = '';
+    rows.forEach(function (row) {
+      var dt = document.createElement('dt');
+      dt.textContent = row[0];
+      var dd = document.createElement('dd');
+      dd.textContent = row[1];
+      dl.appendChild(dt);
+      dl.appendChild(dd);
+    });
+    $('version').textContent = 'v' + stats.version;
+  }
+
+  function renderSessions(page) {
+    var tbody = $('sessions');
+    tbody.textContent = '';
+    page.sessions.forEach(function (s) {
+      var row = document.createElement('tr');
+      cell(row, s.id, 'id');
+      cell(row, s.attached ? 'yes' : 'no');
+      cell(row, s.idle_secs + ' s');
+      cell(row, bytes(s.bytes_in));
+      cell(row, bytes(s.bytes_out));
+      cell(row, s.frames_per_sec);
+      var actions = cell(row, '');
+      if (!s.attached) {
+        actions.appendChild(button('Attach', function () {
+          window.open('/terminal/' + encodeURIComponent(s.id), '_blank');
+        }));
+      }
+      actions.appendChild(button('Stop', function () {
+        api('POST', '/session/' + encodeURIComponent(s.id) + '/stop').then(refresh, alert);
+      }));
+      tbody.appendChild(row);
+    });
+  }
+
+  function refresh() {
+    api('GET', '/stats').then(renderStats, function () {});
+    api('GET', '/sessions?sort=last_activity&order=desc&limit=1000').then(renderSessions, function () {});
+  }
+
+  function connectEvents() {
+    if (events) {
+      events.close();
+    }
+    var log = $('events');
+    var scheme = location.protocol === 'https:' ? 'wss://' : 'ws://';
+    events = new WebSocket(scheme + location.host + API + '/events?token=' + encodeURIComponent(token));
+    events.onopen = function () {
+      log.textContent = '';
+    };
+    events.onmessage = function (message) {
+      var event = JSON.parse(message.data);
+      var line = new Date(event.timestamp_ms).toLocaleTimeString() + ' ' + message.data + '\n';
+      log.textContent = (line + log.textContent).slice(0, 20000);
+      if (event.type.indexOf('session_') === 0 || event.type.indexOf('command_') === 0) {
+        refresh();
+      }
+    };
+    events.onclose = function (event) {
+      log.textContent = 'Event stream closed' + (event.reason ? ': ' + event.reason : '') + '\n' + log.textContent;
+    };
+  }
+
+  $('token').value = token;
+  $('token-form').addEventListener('submit', function (e) {
+    e.preventDefault();
+    token = $('token').value;
+    sessionStorage.setItem('rat-admin-token', token);
+    connectEvents();
+  });
+
+  $('reload').addEventListener('click', function () {
+    api('POST', '/admin/reload').then(function (result) {
+      alert('Config reloaded: ' + JSON.stringify(result));
+    }, alert);
+  });
+
+  $('new-session').addEventListener('click', function () {
+    api('POST', '/session/create').then(refresh, alert);
+  });
+
+  $('exec-form').addEventListener('submit', function (e) {
+    e.preventDefault();
+    var args = $('exec-args').value.trim();
+    var dir = $('exec-dir').value.trim();
+    var out = $('exec-output');
+    out.textContent = 'running…';
+    api('POST', '/execute', {
+      command: $('exec-command').value,
+      args: args ? args.split(/\s+/) : [],
+      working_dir: dir || null,
+    }).then(function (result) {
+      out.textContent = result.output + (result.error ? '\n' + result.error : '');
+      refresh();
+    }, function (err) {
+      out.textContent = err.message;
+    });
+  });
+
+  refresh();
+  setInterval(refresh, POLL_MS);
+  if (token) {
+    connectEvents();
+  }
+})();
@@ -0,0 +1,15 @@
+#!/bin/sh
+# Vendor xterm.js into assets/terminal/vendor/ so the /terminal page works
+# without reaching a CDN. Rebuild rat-core afterwards to embed the files.
+set -eu
+
+XTERM_VERSION=5.3.0
+FIT_VERSION=0.8.0
+CDN=https://cdn.jsdelivr.net/npm
+DEST="$(dirname "$0")/terminal/vendor"
+
+mkdir -p "$DEST"
+curl -fsSL "$CDN/xterm@$XTERM_VERSION/lib/xterm.js" -o "$DEST/xterm.js"
+curl -fsSL "$CDN/xterm@$XTERM_VERSION/css/xterm.css" -o "$DEST/xterm.css"
+curl -fsSL "$CDN/xterm-addon-fit@$FIT_VERSION/lib/xterm-addon-fit.js" -o "$DEST/xterm-addon-fit.js"
+echo "xterm.js $XTERM_VERSION vendored into $DEST"
@@ -0,0 +1,131 @@
+// Browser client for the shell WebSocket. Served at /terminal/<session_id>.
+//
+// xterm.js is loaded from assets/vendor/ when it has been vendored with
+// rat-core/assets/fetch-xterm.sh, otherwise from jsDelivr.
+(function () {
+  'use strict';
+
+  var XTERM_VERSION = '5.3.0';
+  var FIT_VERSION = '0.8.0';
+  var CDN = 'https://cdn.jsdelivr.net/npm/';
+
+  var statusEl = document.getElementById('status');
+  var sessionId = decodeURIComponent(location.pathname.split('/').filter(Boolean).pop());
+
+  // API token for servers that require one: from ?token= on first load,
+  // then kept in sessionStorage and taken out of the address bar. Falls
+  // back to the token the dashboard was given.
+  var params = new URLSearchParams(location.search);
+  if (params.has('token')) {
+    sessionStorage.setItem('rat-token', params.get('token'));
+    params.delete('token');
+    var query = params.toString();
+    history.replaceState(null, '', location.pathname + (query ? '?' + query : '') + location.hash);
+  }
+  var token = sessionStorage.getItem('rat-token') || sessionStorage.getItem('rat-admin-token') || '';
+
+  function setStatus(text) {
+    statusEl.textContent = text;
+  }
+
+  function loadScript(local, fallback) {
+    return new Promise(function (resolve, reject) {
+      var script = document.createElement('script');
+      script.src = local;
+      script.onload = resolve;
+      script.onerror = function () {
+        if (!fallback) {
+          reject(new Error('failed to load ' + local));
+          return;
+        }
+        script.remove();
+        loadScript(fallback, null).then(resolve, reject);
+      };
+      document.head.appendChild(script);
+    });
+  }
+
+  function loadStyle(local, fallback) {
+    var link = document.createElement('link');
+    link.rel = 'stylesheet';
+    link.href = local;
+    link.onerror = function () {
+      link.onerror = null;
+      link.href = fallback;
+    };
+    document.head.appendChild(link);
+  }
+
+  function start() {
+    var term = new Terminal({ cursorBlink: true, scrollback: 5000 });
+    var fit = new FitAddon.FitAddon();
+    term.loadAddon(fit);
+    term.open(document.getElementById('terminal'));
+    fit.fit();
+    term.focus();
+
+    var scheme = location.protocol === 'https:' ? 'wss://' : 'ws://';
+    var url = scheme + location.host + '/v1/shell/' + encodeURIComponent(sessionId);
+    var ws = new WebSocket(token ? url + '?token=' + encodeURIComponent(token) : url);
+    ws.binaryType = 'arraybuffer';
+    var encoder = new TextEncoder();
+
+    function sendResize() {
+      if (ws.readyState === WebSocket.OPEN) {
+        ws.send(JSON.stringify({ type: 'resize', cols: term.cols, rows: term.rows }));
+      }
+    }
+
+    ws.onopen = function () {
+      setStatus('session ' + sessionId);
+      sendResize();
+    };
+    ws.onmessage = function (event) {
+      term.write(typeof event.data === 'string' ? event.data : new Uint8Array(event.data));
+    };
+    ws.onclose = function (event) {
+      setStatus('disconnected' + (event.reason ? ': ' + event.reason : ''));
+      term.options.disableStdin = true;
+    };
+
+    // Keystrokes and pastes go out as binary frames; text frames are
+    // reserved for control messages such as resize
+    term.onData(function (data) {
+      if (ws.readyState === WebSocket.OPEN) {
+        ws.send(encoder.encode(data));
+      }
+    });
+    term.onResize(sendResize);
+    window.addEventListener('resize', function () {
+      fit.fit();
+    });
+
+    // Ctrl+Shift+C copies the selection; Ctrl+Shift+V is left to the
+    // browser, whose paste event xterm.js turns into input
+    term.attachCustomKeyEventHandler(function (event) {
+      if (event.type === 'keydown' && event.ctrlKey && event.shiftKey && event.code === 'KeyC') {
+        var selection = term.getSelection();
+        if (selection && navigator.clipboard) {
+          navigator.clipboard.writeText(selection);
+        }
+        return false;
+      }
+      if (event.ctrlKey && event.shiftKey && event.code === 'KeyV') {
+        return false;
+      }
+      return true;
+    });
+  }
+
+  loadStyle('assets/vendor/xterm.css', CDN + 'xterm@' + XTERM_VERSION + '/css/xterm.css');
+  loadScript('assets/vendor/xterm.js', CDN + 'xterm@' + XTERM_VERSION + '/lib/xterm.js')
+    .then(function () {
+      return loadScript(
+        'assets/vendor/xterm-addon-fit.js',
+        CDN + 'xterm-addon-fit@' + FIT_VERSION + '/lib/xterm-addon-fit.js'
+      );
+    })
+    .then(start, function (err) {
+      setStatus(err.message);
+    });
+})();
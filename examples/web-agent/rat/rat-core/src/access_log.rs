@@ -0,0 +1,299 @@
+//! Access log of API requests, for shipping the agent's activity to SIEM
+//! and log pipelines.
+//!
+//! With `logging.access_log` set, every API request is appended to that
+//! file as a JSON line once its response is ready:
+//!
+//! ```json
+//! {"timestamp_ms":1760000000000,"remote_addr":"10.0.0.5","token":"ci","method":"POST","path":"/v1/execute","protocol":"HTTP/1.1","status":200,"bytes":52,"duration_ms":31,"user_agent":"curl/8.5.0"}
+//! ```
+//!
+//! `GET /admin/access-log` and `rat access-log` export a time range of it
+//! as JSON Lines or Common Log Format. Query strings are left out, since
+//! they may carry a token.
+
+use crate::auth::{require_admin, Caller};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::state::{unix_millis, AppState};
+use axum::{
+    body::{Body, HttpBody},
+    extract::{ConnectInfo, OriginalUri, Query, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// One API request
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AccessEntry {
+    /// When the request arrived, Unix milliseconds
+    pub timestamp_ms: u64,
+    /// Client IP address, when the server saw the connection
+    pub remote_addr: Option<String>,
+    /// Name of the API token used; unset for the admin token and
+    /// unauthenticated requests
+    pub token: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub protocol: String,
+    pub status: u16,
+    /// Response body size, when known before it was sent
+    pub bytes: Option<u64>,
+    /// Milliseconds until the response headers were ready
+    pub duration_ms: u64,
+    pub user_agent: Option<String>,
+}
+
+impl AccessEntry {
+    /// The entry in Common Log Format, the token as the user:
+    /// `10.0.0.5 - ci [10/Oct/2025:13:55:36 +0000] "POST /v1/execute HTTP/1.1" 200 52`
+    pub fn to_clf(&self) -> String {
+        format!(
+            "{} - {} [{}] \"{} {} {}\" {} {}",
+            self.remote_addr.as_deref().unwrap_or("-"),
+            self.token.as_deref().unwrap_or("-"),
+            clf_time(self.timestamp_ms),
+            self.method,
+            self.path,
+            self.protocol,
+            self.status,
+            self.bytes.map_or_else(|| "-".to_string(), |bytes| bytes.to_string()),
+        )
+    }
+}
+
+/// `10/Oct/2025:13:55:36 +0000` for a time in Unix milliseconds
+fn clf_time(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let time = secs % 86_400;
+    // Days since the epoch to a civil date, after Howard Hinnant's
+    // `civil_from_days`
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Export format of the access log
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessFormat {
+    /// JSON Lines, as stored
+    #[default]
+    Json,
+    /// Common Log Format
+    Clf,
+}
+
+impl AccessFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            AccessFormat::Json => "application/x-ndjson",
+            AccessFormat::Clf => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+impl FromStr for AccessFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(AccessFormat::Json),
+            "clf" => Ok(AccessFormat::Clf),
+            other => Err(format!("invalid format '{}', expected json or clf", other)),
+        }
+    }
+}
+
+/// A stored log line in `format`, newline included, if it falls in
+/// `since_ms..until_ms`. Lines that don't parse are skipped.
+fn export_line(line: &str, format: AccessFormat, since_ms: Option<u64>, until_ms: Option<u64>) -> Option<String> {
+    let entry: AccessEntry = serde_json::from_str(line).ok()?;
+    if since_ms.is_some_and(|since| entry.timestamp_ms < since)
+        || until_ms.is_some_and(|until| entry.timestamp_ms >= until)
+    {
+        return None;
+    }
+    Some(match format {
+        AccessFormat::Json => format!("{}\n", line.trim_end()),
+        AccessFormat::Clf => format!("{}\n", entry.to_clf()),
+    })
+}
+
+/// Write the entries of the access log at `path` from `since_ms` up to
+/// `until_ms` to `out`, for `rat access-log`
+pub fn export(
+    path: &Path,
+    format: AccessFormat,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Failed to open access log {}: {}", path.display(), e))?;
+    for line in std::io::BufReader::new(file).lines() {
+        if let Some(line) = export_line(&line?, format, since_ms, until_ms) {
+            out.write_all(line.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Appends entries to the access log file from a background task, so
+/// logging never holds up a response
+pub(crate) struct AccessLog {
+    entries: mpsc::UnboundedSender<AccessEntry>,
+}
+
+impl AccessLog {
+    fn open(path: PathBuf) -> AccessLog {
+        let (entries, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_entries(path, rx));
+        AccessLog { entries }
+    }
+}
+
+async fn write_entries(path: PathBuf, mut entries: mpsc::UnboundedReceiver<AccessEntry>) {
+    if let Some(dir) = path.parent() {
+        let _ = tokio::fs::create_dir_all(dir).await;
+    }
+    let mut file = match tokio::fs::OpenOptions::new().create(true).append(true).open(&path).await {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Not writing the access log to {}: {}", path.display(), e);
+            return;
+        }
+    };
+    while let Some(entry) = entries.recv().await {
+        let Ok(mut line) = serde_json::to_vec(&entry) else { continue };
+        line.push(b'\n');
+        if let Err(e) = async { file.write_all(&line).await?; file.flush().await }.await {
+            warn!("Access log {} stopped: {}", path.display(), e);
+            return;
+        }
+    }
+}
+
+/// The access log, opened on first use; `None` when `logging.access_log`
+/// isn't set
+fn access_log(state: &AppState) -> Option<&AccessLog> {
+    state
+        .shared
+        .access_log
+        .get_or_init(|| state.config().logging.access_log.clone().map(AccessLog::open))
+        .as_ref()
+}
+
+/// Middleware that appends every API request to the access log
+pub(crate) async fn record(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(log) = access_log(&state) else {
+        return next.run(request).await;
+    };
+    let started = Instant::now();
+    let timestamp_ms = unix_millis();
+    let path = match request.extensions().get::<OriginalUri>() {
+        Some(OriginalUri(uri)) => uri.path().to_string(),
+        None => request.uri().path().to_string(),
+    };
+    let remote_addr = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let method = request.method().to_string();
+    let protocol = format!("{:?}", request.version());
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(request).await;
+
+    let _ = log.entries.send(AccessEntry {
+        timestamp_ms,
+        remote_addr,
+        token: response.extensions().get::<Caller>().and_then(Caller::token_name).map(str::to_string),
+        method,
+        path,
+        protocol,
+        status: response.status().as_u16(),
+        bytes: response.body().size_hint().exact(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        user_agent,
+    });
+    response
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct ExportQuery {
+    /// `json` for JSON Lines [default] or `clf` for Common Log Format
+    format: Option<AccessFormat>,
+    /// Earliest request to include, Unix milliseconds
+    since_ms: Option<u64>,
+    /// Only requests before this time, Unix milliseconds
+    until_ms: Option<u64>,
+}
+
+/// Export the access log over a time range, oldest first
+#[utoipa::path(get, path = "/admin/access-log", tag = "admin",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "JSON Lines or Common Log Format, one request per line", content_type = "text/plain"),
+        (status = 401, description = "Invalid admin token", body = ErrorBody),
+        (status = 403, description = "Admin API disabled", body = ErrorBody),
+        (status = 501, description = "Access logging is off", body = ErrorBody),
+    ))]
+pub(crate) async fn export_access_log(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    require_admin(&state, &headers, None)?;
+    let path = state.config().logging.access_log.clone().ok_or_else(|| {
+        ApiError::new(ErrorCode::LogSourceUnavailable, "Access logging is off; set logging.access_log")
+    })?;
+    let file = tokio::fs::File::open(&path).await.map_err(|e| {
+        ApiError::new(
+            ErrorCode::LogSourceUnavailable,
+            format!("Failed to open access log {}: {}", path.display(), e),
+        )
+    })?;
+
+    let format = query.format.unwrap_or_default();
+    let mut lines = tokio::io::BufReader::new(file).lines();
+    let body = async_stream::stream! {
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(line) = export_line(&line, format, query.since_ms, query.until_ms) {
+                yield Ok::<_, std::io::Error>(line);
+            }
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, format.content_type())], Body::from_stream(body)).into_response())
+}
@@ -0,0 +1,59 @@
+//! Configuration reload via SIGHUP and `POST /admin/reload`.
+
+use crate::auth::require_admin;
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::state::AppState;
+use axum::{extract::State, http::HeaderMap};
+use tracing::{error, info, warn};
+
+fn log_reload_result(result: &anyhow::Result<Vec<String>>) {
+    match result {
+        Ok(restart_required) if restart_required.is_empty() => info!("Configuration reloaded"),
+        Ok(restart_required) => warn!(
+            "Configuration reloaded; changes to [{}] need a restart to take effect",
+            restart_required.join(", ")
+        ),
+        Err(e) => error!("Configuration reload failed, keeping previous config: {}", e),
+    }
+}
+
+/// Reload the config on every SIGHUP
+pub async fn reload_on_sighup(state: AppState) {
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(sig) => sig,
+        Err(e) => {
+            error!("Failed to install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("SIGHUP received, reloading configuration");
+        log_reload_result(&state.reload_config());
+    }
+}
+
+/// Admin endpoint to reload the config without sending SIGHUP
+#[utoipa::path(post, path = "/admin/reload", tag = "admin",
+    responses(
+        (status = 200, description = "Reloaded; lists sections that need a restart"),
+        (status = 400, description = "Config could not be loaded", body = ErrorBody),
+        (status = 401, description = "Invalid admin token"),
+        (status = 403, description = "Admin API disabled"),
+    ))]
+pub(crate) async fn admin_reload(
+    State(state): State<AppState>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Encoded<serde_json::Value>, ApiError> {
+    require_admin(&state, &headers, None)?;
+    let result = state.reload_config();
+    log_reload_result(&result);
+    match result {
+        Ok(restart_required) => Ok(Encoded(format, serde_json::json!({
+            "status": "reloaded",
+            "restart_required": restart_required,
+        }))),
+        Err(e) => Err(ApiError::new(ErrorCode::ReloadFailed, format!("Reload failed: {}", e))),
+    }
+}
@@ -0,0 +1,133 @@
+//! Admin token checks, and identifying API callers by the `[[auth.tokens]]`
+//! entry they present so their usage can be charged to it.
+
+use crate::error::{ApiError, ErrorCode};
+use crate::state::AppState;
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query, Request, State},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use tracing::warn;
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Check the admin token from the `Authorization: Bearer` header, falling
+/// back to a `?token=` query parameter for browser WebSocket clients
+pub(crate) fn require_admin(
+    state: &AppState,
+    headers: &HeaderMap,
+    query_token: Option<&str>,
+) -> Result<(), ApiError> {
+    let expected = match state.config().auth.admin_token.clone() {
+        Some(token) => token,
+        None => return Err(ApiError::new(ErrorCode::AdminDisabled, "Admin API disabled")),
+    };
+
+    let provided = bearer_token(headers).or(query_token);
+
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => {
+            warn!("Rejected admin request with missing or invalid token");
+            Err(ApiError::new(ErrorCode::Unauthorized, "Invalid admin token"))
+        }
+    }
+}
+
+/// Name of the `[[auth.tokens]]` entry a request presented; `None` for
+/// anonymous and admin requests, which no quota applies to
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Caller(pub(crate) Option<String>);
+
+impl Caller {
+    pub(crate) fn token_name(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+/// Work out who is calling from the `Authorization: Bearer` header or a
+/// `?token=` query parameter. Fails only when `auth.require_token` is set
+/// and neither an API token nor the admin token was presented.
+pub(crate) fn identify(
+    state: &AppState,
+    headers: &HeaderMap,
+    query_token: Option<&str>,
+) -> Result<Caller, ApiError> {
+    let config = state.config();
+    if let Some(provided) = bearer_token(headers).or(query_token) {
+        let entry = config
+            .auth
+            .tokens
+            .iter()
+            .find(|entry| constant_time_eq(entry.token.as_bytes(), provided.as_bytes()));
+        if let Some(entry) = entry {
+            return Ok(Caller(Some(entry.name.clone())));
+        }
+        let is_admin = config
+            .auth
+            .admin_token
+            .as_deref()
+            .is_some_and(|admin| constant_time_eq(admin.as_bytes(), provided.as_bytes()));
+        if is_admin {
+            return Ok(Caller(None));
+        }
+    }
+
+    if config.auth.require_token {
+        warn!("Rejected API request with missing or invalid token");
+        return Err(ApiError::new(ErrorCode::Unauthorized, "Missing or invalid API token"));
+    }
+    Ok(Caller(None))
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// Middleware that identifies the caller of every API request and makes it
+/// available to handlers as a `Caller`. Probes and `/version` are left
+/// alone so health checks work without a token.
+pub(crate) async fn authenticate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let path = request.uri().path();
+    if path == "/version" || path.starts_with("/health") {
+        return Ok(next.run(request).await);
+    }
+
+    let query_token = Query::<TokenQuery>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(query)| query.token);
+    let caller = identify(&state, request.headers(), query_token.as_deref())?;
+    request.extensions_mut().insert(caller.clone());
+    let mut response = next.run(request).await;
+    // For the access log, which sees only the response
+    response.extensions_mut().insert(caller);
+    Ok(response)
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Caller>().cloned().unwrap_or_default())
+    }
+}
@@ -0,0 +1,42 @@
+//! Fault injection on shell sockets, enabled with the hidden `--chaos` flag.
+//!
+//! Each PTY output frame rolls once against `chaos.probability`. A hit
+//! applies one fault, chosen uniformly: hold the frame back for up to
+//! `chaos.max_delay_ms`, drop it, split it into two frames, or kill the PTY
+//! reader, which ends the attachment the way a dead tunnel would.
+
+use crate::config::ChaosConfig;
+use std::time::Duration;
+use tracing::warn;
+
+/// Apply a fault, if one is rolled, to an output frame about to be sent.
+/// Returns the frames to send in its place, or `None` if the reader was
+/// killed and the attachment should end.
+pub async fn apply(config: &ChaosConfig, session_id: &str, frame: Vec<u8>) -> Option<Vec<Vec<u8>>> {
+    if config.probability <= 0.0 || fastrand::f64() >= config.probability {
+        return Some(vec![frame]);
+    }
+    match fastrand::u8(0..4) {
+        0 => {
+            let delay = Duration::from_millis(fastrand::u64(0..=config.max_delay_ms));
+            warn!("chaos: delaying {} byte frame of session {} by {:?}", frame.len(), session_id, delay);
+            tokio::time::sleep(delay).await;
+            Some(vec![frame])
+        }
+        1 => {
+            warn!("chaos: dropping {} byte frame of session {}", frame.len(), session_id);
+            Some(Vec::new())
+        }
+        2 if frame.len() > 1 => {
+            let mut head = frame;
+            let tail = head.split_off(fastrand::usize(1..head.len()));
+            warn!("chaos: splitting frame of session {} at byte {}", session_id, head.len());
+            Some(vec![head, tail])
+        }
+        2 => Some(vec![frame]),
+        _ => {
+            warn!("chaos: killing PTY reader of session {}", session_id);
+            None
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Negotiated compression for WebSocket output.
+//!
+//! The WebSocket library has no permessage-deflate support, so compression
+//! is negotiated as a subprotocol instead: a client that offers
+//! `rat.deflate` in `Sec-WebSocket-Protocol` and gets it back receives
+//! every server → client data frame as a binary frame of raw DEFLATE data,
+//! ending in a sync flush, with one compression context for the whole
+//! connection (the equivalent of permessage-deflate with context takeover).
+//! Client → server frames are never compressed.
+
+use crate::encoding::Format;
+use axum::extract::ws::Message;
+use flate2::{Compress, Compression, FlushCompress};
+
+pub(crate) const DEFLATE_PROTOCOL: &str = "rat.deflate";
+
+/// Whether the upgraded socket agreed on `rat.deflate`
+pub(crate) fn negotiated(protocol: Option<&axum::http::HeaderValue>) -> bool {
+    protocol.map_or(false, |p| p.as_bytes() == DEFLATE_PROTOCOL.as_bytes())
+}
+
+/// Per-connection compressor; output of successive calls must be inflated
+/// in order by a single decompressor
+pub(crate) struct Deflater {
+    inner: Compress,
+}
+
+impl Deflater {
+    pub(crate) fn new() -> Self {
+        // Terminal output is repetitive enough that the fastest level
+        // captures most of the gain
+        Deflater {
+            inner: Compress::new(Compression::fast(), false),
+        }
+    }
+
+    pub(crate) fn compress(&mut self, input: &[u8]) -> Vec<u8> {
+        let start = self.inner.total_in();
+        let mut out = Vec::with_capacity(input.len() / 2 + 64);
+        loop {
+            if out.capacity() - out.len() < 64 {
+                out.reserve(out.capacity().max(1024));
+            }
+            let consumed = (self.inner.total_in() - start) as usize;
+            // Compressing into a Vec with spare capacity cannot fail
+            let _ = self
+                .inner
+                .compress_vec(&input[consumed..], &mut out, FlushCompress::Sync);
+            let consumed = (self.inner.total_in() - start) as usize;
+            // The flush is complete once all input is in and the output
+            // buffer was not filled to the brim
+            if consumed == input.len() && out.len() < out.capacity() {
+                return out;
+            }
+        }
+    }
+}
+
+/// Frame an encoded message for a streaming socket: deflated binary when
+/// compression was negotiated, otherwise text for JSON and binary for the
+/// other formats
+pub(crate) fn frame(deflater: Option<&mut Deflater>, format: Format, bytes: Vec<u8>) -> Message {
+    match (deflater, format) {
+        (Some(deflater), _) => Message::Binary(deflater.compress(&bytes)),
+        (None, Format::Json) => Message::Text(String::from_utf8_lossy(&bytes).into_owned()),
+        (None, _) => Message::Binary(bytes),
+    }
+}
@@ -0,0 +1,481 @@
+//! TOML configuration file.
+//!
+//! Precedence, highest first: command-line flags, `RAT_*` environment
+//! variables, the file passed with `--config`, built-in defaults.
+
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub auth: AuthConfig,
+    pub tls: TlsConfig,
+    pub tunnel: TunnelConfig,
+    pub daemon: DaemonConfig,
+    pub logging: LoggingConfig,
+    pub crash: CrashConfig,
+    pub limits: LimitsConfig,
+    pub policy: PolicyConfig,
+    pub cors: CorsConfig,
+    pub shell: ShellConfig,
+    pub websocket: WebSocketConfig,
+    pub http_compression: HttpCompressionConfig,
+    pub system: SystemConfig,
+    pub repl: ReplConfig,
+    pub jobs: JobsConfig,
+    pub chaos: ChaosConfig,
+    pub search: SearchConfig,
+    /// Named environments sessions and commands can ask for with `profile`
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+    /// Seconds to wait for child processes to exit after SIGHUP (shells) or
+    /// SIGTERM (commands) on shutdown
+    pub shutdown_grace_secs: u64,
+    /// Seconds a client has to send a request's headers before its
+    /// connection is closed
+    pub header_read_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: "0.0.0.0".to_string(),
+            port: 3000,
+            shutdown_grace_secs: 10,
+            header_read_timeout_secs: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Bearer token for admin endpoints; they are disabled when unset
+    pub admin_token: Option<String>,
+    /// Reject API requests that carry neither one of `tokens` nor the admin
+    /// token. Health probes and `/version` stay open
+    pub require_token: bool,
+    /// API tokens, each with its own quotas
+    pub tokens: Vec<TokenConfig>,
+}
+
+/// An API token and the quotas its requests are charged against. Quotas
+/// left unset are unlimited
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenConfig {
+    /// Identifies the token in logs and `/admin/quotas`
+    pub name: String,
+    pub token: String,
+    /// Concurrent PTY sessions created with this token
+    pub max_sessions: Option<usize>,
+    /// Commands started in any rolling hour
+    pub max_jobs_per_hour: Option<usize>,
+    /// Command and shell output per UTC day
+    pub max_output_bytes_per_day: Option<u64>,
+}
+
+/// HTTPS and WSS on the server's port. Off unless both files are set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert: Option<PathBuf>,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    pub fn enabled(&self) -> bool {
+        self.cert.is_some() && self.key.is_some()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TunnelConfig {
+    pub ngrok: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub enabled: bool,
+    pub pid_file: PathBuf,
+    pub working_dir: PathBuf,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            enabled: false,
+            pid_file: PathBuf::from("/tmp/rat.pid"),
+            working_dir: PathBuf::from("/tmp"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// tracing filter directive; `RUST_LOG` takes precedence when set
+    pub filter: String,
+    /// Log file for tracing output; defaults to /tmp/rat.log when daemonized
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// JSON Lines log of every API request, for `/admin/access-log`
+    pub access_log: Option<PathBuf>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            filter: "rat=info,tower_http=info".to_string(),
+            file: None,
+            rotation: LogRotation::Daily,
+            access_log: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrashConfig {
+    pub dir: PathBuf,
+    pub webhook: Option<String>,
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        CrashConfig {
+            dir: PathBuf::from("/tmp/rat-crashes"),
+            webhook: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum concurrent PTY sessions; unlimited when unset
+    pub max_sessions: Option<usize>,
+    /// Seconds an API request may take, body upload included, before it is
+    /// answered with 504; 0 disables. Streaming routes are exempt
+    pub request_timeout_secs: u64,
+    /// Timeouts for requests whose path starts with the key, such as
+    /// `"/execute"`, overriding `request_timeout_secs`. The longest
+    /// matching prefix wins
+    pub route_timeouts: BTreeMap<String, u64>,
+    /// API requests handled at once; more are answered with 503. Unlimited
+    /// when unset
+    pub max_in_flight_requests: Option<usize>,
+    /// Shell output sent to each attached client per second; unlimited when
+    /// unset
+    pub session_output_bytes_per_sec: Option<u64>,
+    /// Shell output sent per second across all sessions; unlimited when
+    /// unset
+    pub total_output_bytes_per_sec: Option<u64>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_sessions: None,
+            request_timeout_secs: 300,
+            route_timeouts: BTreeMap::new(),
+            max_in_flight_requests: None,
+            session_output_bytes_per_sec: None,
+            total_output_bytes_per_sec: None,
+        }
+    }
+}
+
+/// Which commands `/execute`, `/execute/stream`, jobs and the `execute`
+/// tool may run. An entry matches a command by the name it was given as or
+/// by its file name, so `rm` also covers `/bin/rm`. This is not a sandbox:
+/// an allowed shell or interpreter can run anything.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// Only these commands may run; empty allows any
+    pub allowed_commands: Vec<String>,
+    /// Refused even when allowed above
+    pub denied_commands: Vec<String>,
+}
+
+impl PolicyConfig {
+    /// Whether `command` may run
+    pub fn permits(&self, command: &str) -> bool {
+        let file_name = Path::new(command).file_name().and_then(|name| name.to_str()).unwrap_or(command);
+        let listed = |list: &[String]| list.iter().any(|entry| entry == command || entry == file_name);
+        (self.allowed_commands.is_empty() || listed(&self.allowed_commands)) && !listed(&self.denied_commands)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API; `"*"` allows any origin
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+/// PTY → WebSocket output tuning. Applies to sessions attached after a change.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShellConfig {
+    /// Bytes requested from the PTY per read
+    pub read_buffer_bytes: usize,
+    /// Queued output is merged into one WebSocket frame until it reaches
+    /// this size
+    pub max_frame_bytes: usize,
+    /// How long to wait for more output before sending a partly filled
+    /// frame; 0 only merges output that is already queued
+    pub coalesce_delay_ms: u64,
+    /// Record every attachment's frames here, one JSON lines file each
+    pub record_dir: Option<PathBuf>,
+    /// Stop sessions with no traffic in either direction for this many
+    /// seconds; 0 keeps them forever
+    pub idle_timeout_secs: u64,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        ShellConfig {
+            read_buffer_bytes: 8192,
+            max_frame_bytes: 65536,
+            coalesce_delay_ms: 0,
+            record_dir: None,
+            idle_timeout_secs: 0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// Offer `rat.deflate` compression to clients that ask for it
+    pub compression: bool,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig { compression: true }
+    }
+}
+
+/// gzip and brotli compression of HTTP responses, for clients that send
+/// `Accept-Encoding`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpCompressionConfig {
+    pub enabled: bool,
+    /// Responses smaller than this are sent as they are
+    pub min_size_bytes: u16,
+}
+
+impl Default for HttpCompressionConfig {
+    fn default() -> Self {
+        HttpCompressionConfig {
+            enabled: true,
+            min_size_bytes: 1024,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemConfig {
+    /// `/system/env` masks variables whose name contains any of these,
+    /// ignoring case
+    pub env_redact: Vec<String>,
+    /// Default sampling interval of the `/system/metrics` stream
+    pub metrics_interval_ms: u64,
+    /// Directories `/system/logs?source=file` may read from
+    pub log_dirs: Vec<PathBuf>,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        SystemConfig {
+            env_redact: ["TOKEN", "SECRET", "KEY", "PASSWORD", "PASSWD", "CREDENTIAL", "AUTH"]
+                .map(String::from)
+                .to_vec(),
+            metrics_interval_ms: 1000,
+            log_dirs: vec![PathBuf::from("/var/log")],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplConfig {
+    /// Seconds an eval may run before its REPL is stopped
+    pub eval_timeout_secs: u64,
+}
+
+impl Default for ReplConfig {
+    fn default() -> Self {
+        ReplConfig { eval_timeout_secs: 30 }
+    }
+}
+
+/// Detached jobs and their spooled output
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobsConfig {
+    /// Output kept per job; the oldest lines are dropped past this
+    pub max_output_bytes: usize,
+    /// Seconds a finished job and its output are kept
+    pub retention_secs: u64,
+    /// Seconds the result of a request made with an `Idempotency-Key` is
+    /// kept for retries
+    pub idempotency_retention_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        JobsConfig {
+            max_output_bytes: 4 * 1024 * 1024,
+            retention_secs: 3600,
+            idempotency_retention_secs: 86400,
+        }
+    }
+}
+
+/// Full-text index of shell transcripts and command history behind
+/// `/search`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// SQLite database the index is kept in; search is off when unset
+    pub db_path: Option<PathBuf>,
+    /// Days entries are kept; 0 keeps them forever
+    pub retention_days: u64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            db_path: None,
+            retention_days: 90,
+        }
+    }
+}
+
+/// An environment a session or command asks for by name
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Variables set on top of the agent's own environment
+    pub env: BTreeMap<String, String>,
+    /// Working directory, unless the request gives one
+    pub cwd: Option<PathBuf>,
+    /// Shell for sessions, unless the request gives one, and the shell that
+    /// sources `rc` before a command. Must take `-c` [default: bash for
+    /// sessions, sh for commands]
+    pub shell: Option<String>,
+    /// Script sourced before the command runs or the session's first
+    /// prompt: by bash with `--rcfile`, by other shells through `$ENV`
+    pub rc: Option<PathBuf>,
+}
+
+/// Fault injection on shell sockets, for testing clients against a flaky
+/// link. Set with the hidden `--chaos` flag; never enable it in production.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+    /// Chance, from 0 to 1, that each output frame hits a fault
+    #[serde(deserialize_with = "probability")]
+    pub probability: f64,
+    /// Longest delay a delayed frame is held back for
+    pub max_delay_ms: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            probability: 0.0,
+            max_delay_ms: 2000,
+        }
+    }
+}
+
+/// Parse a probability from 0 to 1, for `--chaos`
+pub fn parse_probability(s: &str) -> Result<f64, String> {
+    let value: f64 = s.parse().map_err(|_| format!("invalid probability '{}'", s))?;
+    check_probability(value)
+}
+
+/// Reject probabilities outside 0..=1, NaN included
+fn check_probability(value: f64) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("probability {} is not between 0 and 1", value))
+    }
+}
+
+fn probability<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    check_probability(f64::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "never" => Ok(LogRotation::Never),
+            other => Err(format!("invalid rotation '{}', expected hourly, daily or never", other)),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file, or the defaults when no path is given
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let Some(path) = path else {
+            return Ok(Config::default());
+        };
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config {}: {}", path.display(), e))?;
+        toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("Failed to parse config {}: {}", path.display(), e))
+    }
+
+    /// Effective log file: the configured one, or /tmp/rat.log for daemons
+    pub fn log_file(&self) -> Option<PathBuf> {
+        match &self.logging.file {
+            Some(path) => Some(path.clone()),
+            None if self.daemon.enabled => Some(PathBuf::from("/tmp/rat.log")),
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Content negotiation between JSON, MessagePack and CBOR.
+//!
+//! Responses use the best supported type in the request's `Accept` header
+//! (JSON when there is none). Request bodies are decoded according to
+//! `Content-Type`. WebSocket streams, which can't negotiate with headers
+//! from a browser, take a `?format=` query parameter instead.
+
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use crate::error::{ApiError, ErrorCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::convert::Infallible;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+    #[default]
+    Json,
+    Msgpack,
+    Cbor,
+}
+
+impl Format {
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Msgpack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type.trim().to_ascii_lowercase().as_str() {
+            "application/json" => Some(Format::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::Msgpack)
+            }
+            "application/cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Highest-quality supported type in an `Accept` header
+    fn from_accept(accept: &str) -> Self {
+        let mut best: Option<(Format, f32)> = None;
+        for item in accept.split(',') {
+            let mut params = item.split(';');
+            let Some(format) = params.next().and_then(Format::from_media_type) else {
+                continue;
+            };
+            let quality = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality > 0.0 && best.map_or(true, |(_, q)| quality > q) {
+                best = Some((format, quality));
+            }
+        }
+        best.map(|(format, _)| format).unwrap_or_default()
+    }
+
+    /// Format of a request body; `None` for unsupported content types
+    fn from_content_type(headers: &HeaderMap) -> Option<Self> {
+        match headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+            None => Some(Format::Json),
+            Some(value) => Format::from_media_type(value.split(';').next().unwrap_or_default()),
+        }
+    }
+
+    pub(crate) fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Format::Msgpack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(value, &mut out).map_err(|e| e.to_string())?;
+                Ok(out)
+            }
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Msgpack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// The response format the client asked for through `Accept`
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .map(Format::from_accept)
+            .unwrap_or_default())
+    }
+}
+
+/// Response body serialized in the negotiated format
+pub(crate) struct Encoded<T>(pub(crate) Format, pub(crate) T);
+
+impl<T: Serialize> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Encoded(format, value) = self;
+        match format.encode(&value) {
+            Ok(body) => ([(header::CONTENT_TYPE, format.content_type())], body).into_response(),
+            Err(e) => ApiError::new(ErrorCode::Internal, format!("Failed to encode response: {}", e))
+                .into_response(),
+        }
+    }
+}
+
+/// Request body decoded according to its `Content-Type`
+pub(crate) struct Decoded<T>(pub(crate) T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for Decoded<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = Format::from_content_type(req.headers()).ok_or_else(|| {
+            ApiError::new(
+                ErrorCode::UnsupportedMediaType,
+                "Expected application/json, application/msgpack or application/cbor",
+            )
+        })?;
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| ApiError::new(ErrorCode::BadRequest, e.to_string()).with_status(e.status()))?;
+        format
+            .decode(&bytes)
+            .map(Decoded)
+            .map_err(|e| ApiError::new(ErrorCode::InvalidBody, format!("Invalid request body: {}", e)))
+    }
+}
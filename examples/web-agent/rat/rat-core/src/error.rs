@@ -0,0 +1,207 @@
+//! The error envelope every endpoint answers failures with:
+//!
+//! ```json
+//! {"error": {"code": "SESSION_NOT_FOUND", "message": "Session not found", "details": null}}
+//! ```
+//!
+//! `code` is stable and meant for clients to branch on; `message` is for
+//! people and may change. Handlers return `ApiError`; failures produced
+//! before a handler runs (axum's extractor rejections, unknown routes) are
+//! rewrapped by `envelope` with a code derived from their status.
+
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+/// Largest plain-text error body `envelope` reads back to rewrap
+const MAX_REWRAPPED_BODY: usize = 64 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum ErrorCode {
+    BadRequest,
+    InvalidBody,
+    UnsupportedMediaType,
+    UnsupportedProtocol,
+    Unauthorized,
+    AdminDisabled,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    SessionNotFound,
+    SessionAlreadyAttached,
+    SessionLimitReached,
+    ReplNotFound,
+    ReplLimitReached,
+    ReplFailed,
+    EvalTimeout,
+    JobNotFound,
+    IdempotencyKeyReused,
+    ToolNotFound,
+    ProfileNotFound,
+    CommandDenied,
+    InvalidArguments,
+    QuotaExceeded,
+    SpawnFailed,
+    PtyFailed,
+    ProcessNotFound,
+    UnknownSignal,
+    LogSourceUnavailable,
+    SearchDisabled,
+    ReloadFailed,
+    TooManyRequests,
+    Overloaded,
+    ShuttingDown,
+    Timeout,
+    Internal,
+}
+
+impl ErrorCode {
+    pub(crate) fn status(self) -> StatusCode {
+        use ErrorCode::*;
+        match self {
+            BadRequest | UnsupportedProtocol | UnknownSignal | ReloadFailed => StatusCode::BAD_REQUEST,
+            InvalidBody | InvalidArguments | IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Unauthorized => StatusCode::UNAUTHORIZED,
+            AdminDisabled | Forbidden | CommandDenied => StatusCode::FORBIDDEN,
+            NotFound
+            | SessionNotFound
+            | ReplNotFound
+            | JobNotFound
+            | ToolNotFound
+            | ProfileNotFound
+            | ProcessNotFound => StatusCode::NOT_FOUND,
+            MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            SessionAlreadyAttached => StatusCode::CONFLICT,
+            SessionLimitReached | ReplLimitReached | QuotaExceeded | TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            LogSourceUnavailable | SearchDisabled => StatusCode::NOT_IMPLEMENTED,
+            Overloaded | ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            EvalTimeout | Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ReplFailed | SpawnFailed | PtyFailed | Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The generic code for a status, for errors that don't carry their own
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::InvalidBody,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::TooManyRequests,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::ShuttingDown,
+            StatusCode::GATEWAY_TIMEOUT | StatusCode::REQUEST_TIMEOUT => ErrorCode::Timeout,
+            status if status.is_client_error() => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+/// A failed request. The status comes from the code unless overridden.
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    pub(crate) status: StatusCode,
+    pub(crate) code: ErrorCode,
+    pub(crate) message: String,
+    pub(crate) details: Option<Value>,
+}
+
+impl ApiError {
+    pub(crate) fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status: code.status(),
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Machine-readable context, such as the supported values of a field
+    pub(crate) fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub(crate) fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// The envelope as documented in the OpenAPI spec
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorDetail {
+    code: ErrorCode,
+    message: String,
+    #[schema(value_type = Option<Object>)]
+    details: Option<Value>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: ErrorDetail {
+                code: self.code,
+                message: self.message,
+                details: self.details,
+            },
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// Rewrap plain-text and empty error responses, such as axum's extractor
+/// rejections and unknown routes, in the envelope. Errors that already have
+/// a structured body are left alone.
+pub(crate) async fn envelope(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+    let content_type = response.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let plain = match content_type {
+        Some(content_type) => content_type.starts_with("text/plain"),
+        None => true,
+    };
+    if !plain {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let message = match axum::body::to_bytes(body, MAX_REWRAPPED_BODY).await {
+        Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => status.canonical_reason().unwrap_or("Error").to_string(),
+    };
+    let mut rewrapped = ApiError::new(ErrorCode::from_status(status), message)
+        .with_status(status)
+        .into_response();
+    // Keep headers such as `Allow` and `Deprecation`, but not the old body's
+    for (name, value) in parts.headers.iter() {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            rewrapped.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    rewrapped
+}
@@ -0,0 +1,115 @@
+//! Server event bus and the admin `/events` WebSocket firehose.
+
+use crate::auth::require_admin;
+use crate::compression::{self, Deflater, DEFLATE_PROTOCOL};
+use crate::encoding::Format;
+use crate::state::AppState;
+use axum::{
+    extract::{ws::{Message, WebSocket}, Query, State, WebSocketUpgrade},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::info;
+
+/// Server-wide event published to `/events` subscribers
+#[derive(Clone, Serialize)]
+pub struct ServerEvent {
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    SessionCreated { session_id: String },
+    SessionStopped { session_id: String },
+    SessionAttached { session_id: String },
+    SessionDetached { session_id: String },
+    CommandStarted { command: String, args: Vec<String> },
+    CommandFinished { command: String, exit_code: Option<i32> },
+    ProcessSignalled { pid: u32, signal: i32 },
+    ReplCreated { repl_id: String, language: String },
+    ReplStopped { repl_id: String },
+    Error { message: String },
+    TunnelUrlChanged { url: String },
+    ConfigReloaded { restart_required: Vec<String> },
+    ShutdownStarted,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct EventsQuery {
+    token: Option<String>,
+    /// Encoding of each event; JSON events are sent as text frames, the
+    /// binary formats as binary frames
+    #[serde(default)]
+    format: Format,
+}
+
+/// Admin-only WebSocket streaming every server event
+#[utoipa::path(get, path = "/events", tag = "admin",
+    params(
+        ("token" = Option<String>, Query, description = "Admin token, if not sent as a header"),
+        ("format" = Option<String>, Query, description = "json (default), msgpack or cbor"),
+    ),
+    responses(
+        (status = 101, description = "WebSocket upgrade; text frames carry JSON events"),
+        (status = 401, description = "Invalid admin token"),
+        (status = 403, description = "Admin API disabled"),
+    ))]
+pub(crate) async fn events_ws_handler(
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> Response {
+    if let Err(e) = require_admin(&state, &headers, query.token.as_deref()) {
+        return e.into_response();
+    }
+    let events = state.shared.events.subscribe();
+    let ws = if state.config().websocket.compression {
+        ws.protocols([DEFLATE_PROTOCOL])
+    } else {
+        ws
+    };
+    let format = query.format;
+    ws.on_upgrade(move |socket| handle_events_socket(socket, events, format))
+}
+
+async fn handle_events_socket(
+    socket: WebSocket,
+    mut events: broadcast::Receiver<ServerEvent>,
+    format: Format,
+) {
+    info!("Event subscriber connected");
+    let mut deflater = compression::negotiated(socket.protocol()).then(Deflater::new);
+    let (mut ws_tx, mut ws_rx) = socket.split();
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let encoded = match event {
+                    Ok(event) => format.encode(&event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        format.encode(&serde_json::json!({"type": "lagged", "skipped": skipped}))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Ok(bytes) = encoded else { continue };
+                if ws_tx.send(compression::frame(deflater.as_mut(), format, bytes)).await.is_err() {
+                    break;
+                }
+            }
+            msg = ws_rx.next() => {
+                match msg {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+    info!("Event subscriber disconnected");
+}
@@ -0,0 +1,491 @@
+//! One-shot and streaming command execution.
+
+use crate::auth::Caller;
+use crate::config::ProfileConfig;
+use crate::encoding::{Decoded, Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::events::EventKind;
+use crate::idempotency::{self, Claim};
+use crate::jobs::{self, Job};
+use crate::limits;
+use crate::profiles;
+use crate::quota;
+use crate::search;
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, HeaderValue},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tracing::{error, info};
+use utoipa::{IntoParams, ToSchema};
+
+/// Response header naming the job behind a detached stream
+const JOB_ID_HEADER: &str = "x-rat-job-id";
+/// Request header an SSE client reconnects with, naming the last event it saw
+const LAST_EVENT_ID_HEADER: &str = "last-event-id";
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub(crate) struct CommandRequest {
+    pub(crate) command: String,
+    pub(crate) args: Option<Vec<String>>,
+    pub(crate) working_dir: Option<String>,
+    /// Run with the environment of this `[profiles]` entry
+    pub(crate) profile: Option<String>,
+    /// Kill the command after this many seconds. `/execute` defaults to
+    /// the `[limits]` timeout for its route; streams and jobs to none
+    pub(crate) timeout_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub(crate) struct CommandResponse {
+    success: bool,
+    output: String,
+    error: Option<String>,
+}
+
+/// Counts a command as running for as long as the guard is alive, and
+/// registers its child PID so shutdown can signal it
+struct JobGuard {
+    state: AppState,
+    pid: Option<u32>,
+}
+
+impl JobGuard {
+    fn start(state: &AppState) -> Self {
+        state.shared.commands_executed.fetch_add(1, Ordering::Relaxed);
+        state.shared.running_jobs.fetch_add(1, Ordering::Relaxed);
+        JobGuard { state: state.clone(), pid: None }
+    }
+
+    fn track(&mut self, pid: Option<u32>) {
+        if let Some(pid) = pid {
+            self.state.shared.child_pids.insert(pid);
+        }
+        self.pid = pid;
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        self.state.shared.running_jobs.fetch_sub(1, Ordering::Relaxed);
+        if let Some(pid) = self.pid {
+            self.state.shared.child_pids.remove(&pid);
+        }
+    }
+}
+
+fn build_command(request: &CommandRequest, profile: Option<&ProfileConfig>) -> Command {
+    let mut cmd = match profile.and_then(|profile| profile.rc.as_ref()) {
+        Some(rc) => {
+            // Source the rc script in a shell that then becomes the command
+            let shell = profile.and_then(|profile| profile.shell.as_deref()).unwrap_or("sh");
+            let mut cmd = Command::new(shell);
+            cmd.arg("-c").arg(r#". "$0" && exec "$@""#).arg(rc).arg(&request.command);
+            cmd
+        }
+        None => Command::new(&request.command),
+    };
+
+    if let Some(args) = &request.args {
+        cmd.args(args);
+    }
+
+    if let Some(profile) = profile {
+        cmd.envs(&profile.env);
+    }
+    let working_dir = request
+        .working_dir
+        .as_deref()
+        .map(Path::new)
+        .or_else(|| profile.and_then(|profile| profile.cwd.as_deref()));
+    if let Some(working_dir) = working_dir {
+        cmd.current_dir(working_dir);
+    }
+
+    // A request that times out or a client that goes away drops the job;
+    // don't leave the command running untracked
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    cmd
+}
+
+/// Refuse a command the `policy` section doesn't allow
+fn check_policy(state: &AppState, request: &CommandRequest) -> Result<(), ApiError> {
+    if state.config().policy.permits(&request.command) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            ErrorCode::CommandDenied,
+            format!("Command not allowed by policy: {}", request.command),
+        ))
+    }
+}
+
+/// Run a command to completion, tracked as a job charged to `caller`, and
+/// collect its output
+pub(crate) async fn run_command(
+    state: &AppState,
+    caller: &Caller,
+    request: &CommandRequest,
+) -> Result<Output, ApiError> {
+    let profile = profiles::find(state, request.profile.as_deref())?;
+    check_policy(state, request)?;
+    quota::start_job(state, caller)?;
+    info!("Executing command: {} with args: {:?}", request.command, request.args);
+    search::record_command(state, caller, request);
+    let mut job = JobGuard::start(state);
+    state.emit(EventKind::CommandStarted {
+        command: request.command.clone(),
+        args: request.args.clone().unwrap_or_default(),
+    });
+
+    let child = build_command(request, profile.as_ref()).spawn().map_err(|e| {
+        error!("Failed to execute command: {}", e);
+        quota::refund_job(state, caller);
+        state.emit(EventKind::Error { message: format!("Failed to execute {}: {}", request.command, e) });
+        ApiError::new(ErrorCode::SpawnFailed, format!("Failed to execute command: {}", e))
+    })?;
+    job.track(child.id());
+
+    // Dropping the child on timeout kills it
+    let output = match request.timeout_secs {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), child.wait_with_output())
+            .await
+            .map_err(|_| {
+                info!("Command {} timed out after {}s, killed", request.command, secs);
+                ApiError::new(ErrorCode::Timeout, format!("Command timed out after {}s and was killed", secs))
+            })?,
+        None => child.wait_with_output().await,
+    }
+    .map_err(|e| {
+        error!("Failed to execute command: {}", e);
+        ApiError::new(ErrorCode::Internal, format!("Failed to execute command: {}", e))
+    })?;
+    quota::charge_output(state, caller.token_name(), output.stdout.len() + output.stderr.len());
+
+    state.emit(EventKind::CommandFinished {
+        command: request.command.clone(),
+        exit_code: output.status.code(),
+    });
+    Ok(output)
+}
+
+/// One item of a streamed command's output
+pub(crate) enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+    Error(String),
+    /// Always the last item of a stream that got as far as waiting
+    Exit(Option<i32>),
+}
+
+/// Spawn a command, tracked as a job charged to `caller`, and stream its
+/// output line by line. The command is killed if `caller` runs over its
+/// output quota.
+pub(crate) fn stream_command(
+    state: &AppState,
+    caller: &Caller,
+    request: &CommandRequest,
+) -> Result<impl Stream<Item = OutputLine> + Send + 'static, ApiError> {
+    let profile = profiles::find(state, request.profile.as_deref())?;
+    check_policy(state, request)?;
+    quota::start_job(state, caller)?;
+    info!("Streaming command: {} with args: {:?}", request.command, request.args);
+    search::record_command(state, caller, request);
+
+    let mut child = build_command(request, profile.as_ref()).spawn().map_err(|e| {
+        error!("Failed to spawn command: {}", e);
+        quota::refund_job(state, caller);
+        state.emit(EventKind::Error { message: format!("Failed to spawn {}: {}", request.command, e) });
+        ApiError::new(ErrorCode::SpawnFailed, format!("Failed to spawn command: {}", e))
+    })?;
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    let stdout_reader = BufReader::new(stdout);
+    let stderr_reader = BufReader::new(stderr);
+
+    let mut job = JobGuard::start(state);
+    job.track(child.id());
+    state.emit(EventKind::CommandStarted {
+        command: request.command.clone(),
+        args: request.args.clone().unwrap_or_default(),
+    });
+    let command = request.command.clone();
+    let timeout_secs = request.timeout_secs;
+    let state = state.clone();
+    let owner = caller.token_name().map(str::to_string);
+
+    Ok(async_stream::stream! {
+        let _job = job;
+        let mut stdout_lines = stdout_reader.lines();
+        let mut stderr_lines = stderr_reader.lines();
+        let mut stdout_open = true;
+        let mut stderr_open = true;
+        let mut over_quota = false;
+        let mut timed_out = false;
+        let expired = async {
+            match timeout_secs {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(expired);
+
+        while stdout_open || stderr_open {
+            tokio::select! {
+                _ = &mut expired => {
+                    timed_out = true;
+                    break;
+                }
+                result = stdout_lines.next_line(), if stdout_open => {
+                    match result {
+                        Ok(Some(line)) => {
+                            if !quota::charge_output(&state, owner.as_deref(), line.len() + 1) {
+                                over_quota = true;
+                                break;
+                            }
+                            yield OutputLine::Stdout(line);
+                        }
+                        Ok(None) => stdout_open = false,
+                        Err(e) => {
+                            yield OutputLine::Error(e.to_string());
+                            break;
+                        }
+                    }
+                }
+                result = stderr_lines.next_line(), if stderr_open => {
+                    match result {
+                        Ok(Some(line)) => {
+                            if !quota::charge_output(&state, owner.as_deref(), line.len() + 1) {
+                                over_quota = true;
+                                break;
+                            }
+                            yield OutputLine::Stderr(line);
+                        }
+                        Ok(None) => stderr_open = false,
+                        Err(e) => {
+                            yield OutputLine::Error(e.to_string());
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        if over_quota {
+            yield OutputLine::Error("Output quota exceeded; command killed".to_string());
+            let _ = child.start_kill();
+        }
+        if timed_out {
+            yield OutputLine::Error(format!("Command timed out after {}s; command killed", timeout_secs.unwrap_or(0)));
+            let _ = child.start_kill();
+        }
+
+        // Wait for the command to complete
+        match child.wait().await {
+            Ok(status) => {
+                state.emit(EventKind::CommandFinished { command, exit_code: status.code() });
+                yield OutputLine::Exit(status.code());
+            }
+            Err(e) => {
+                yield OutputLine::Error(e.to_string());
+            }
+        }
+    })
+}
+
+/// Execute a command and return the output
+#[utoipa::path(post, path = "/execute", tag = "exec",
+    params(("Idempotency-Key" = Option<String>, Header,
+        description = "Retries with the same key return the first request's result instead of running the command again")),
+    request_body = CommandRequest,
+    responses(
+        (status = 200, body = CommandResponse),
+        (status = 403, description = "The command isn't allowed by policy", body = ErrorBody),
+        (status = 422, description = "The Idempotency-Key was used with a different request", body = ErrorBody),
+        (status = 429, description = "The token's job or output quota is used up", body = ErrorBody),
+        (status = 500, description = "Command could not be started", body = ErrorBody),
+        (status = 504, description = "The command ran past its timeout and was killed", body = ErrorBody),
+    ))]
+pub(crate) async fn execute_command(
+    State(state): State<AppState>,
+    format: Format,
+    caller: Caller,
+    headers: HeaderMap,
+    Decoded(mut payload): Decoded<CommandRequest>,
+) -> Result<Response, ApiError> {
+    // The command is timed here rather than by the request timeout, so the
+    // client hears why it was killed
+    if payload.timeout_secs.is_none() {
+        payload.timeout_secs = limits::route_timeout(&state, "/execute").map(|limit| limit.as_secs());
+    }
+    let Some(key) = idempotency::key(&headers)? else {
+        let output = run_command(&state, &caller, &payload).await?;
+        return Ok(Encoded(format, command_response(output)).into_response());
+    };
+
+    let pending = match idempotency::claim(&state, &caller, "execute", &key, &payload).await? {
+        Claim::New(pending) => pending,
+        Claim::Replay(result) => {
+            let response: CommandResponse = serde_json::from_value(result)
+                .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Stored result is unreadable: {}", e)))?;
+            return Ok(idempotency::replayed(Encoded(format, response)));
+        }
+    };
+    // Run apart from this request, so a client that gives up and retries
+    // gets the result instead of having killed the command
+    let task = tokio::spawn(async move {
+        let output = run_command(&state, &caller, &payload).await?;
+        let response = command_response(output);
+        pending.complete(serde_json::to_value(&response).unwrap_or_default());
+        Ok::<_, ApiError>(response)
+    });
+    let response = task
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Command task failed: {}", e)))??;
+    Ok(Encoded(format, response).into_response())
+}
+
+fn command_response(output: Output) -> CommandResponse {
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+    CommandResponse {
+        success: output.status.success(),
+        output: stdout,
+        error: if stderr.is_empty() { None } else { Some(stderr) },
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct StreamQuery {
+    /// Run the command as a detached job that keeps running, its output
+    /// spooled, if the client disconnects
+    #[serde(default)]
+    detach: bool,
+}
+
+/// The SSE event for an output line, with its sequence number as the `id:`
+/// a reconnecting client sends back as `Last-Event-ID`
+fn output_event(seq: Option<u64>, line: OutputLine) -> Result<Event, Infallible> {
+    let data = match line {
+        OutputLine::Stdout(line) => format!("stdout: {}", line),
+        OutputLine::Stderr(line) => format!("stderr: {}", line),
+        OutputLine::Error(e) => format!("error: {}", e),
+        OutputLine::Exit(code) => format!("exit_code: {}", code.unwrap_or(-1)),
+    };
+    let event = Event::default().data(data);
+    Ok(match seq {
+        Some(seq) => event.id(seq.to_string()),
+        None => event,
+    })
+}
+
+/// Sequence number to resume a job's stream from, one past the
+/// `Last-Event-ID` a reconnecting client sent
+pub(crate) fn resume_from(headers: &HeaderMap) -> Result<u64, ApiError> {
+    let Some(value) = headers.get(LAST_EVENT_ID_HEADER) else {
+        return Ok(0);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|id| id.trim().parse::<u64>().ok())
+        .map(|seq| seq + 1)
+        .ok_or_else(|| ApiError::new(ErrorCode::BadRequest, "Last-Event-ID must be a sequence number"))
+}
+
+/// A job's output as server-sent events from `from_seq` on, led by its id
+pub(crate) fn job_events(job: Arc<Job>, from_seq: u64) -> Response {
+    let job_id = job.id().to_string();
+    let announce = Event::default().data(format!("job_id: {}", job_id));
+    let lines = jobs::follow(job, from_seq).map(|(seq, line)| output_event(seq, line));
+    let events = futures::stream::iter([Ok::<_, Infallible>(announce)]).chain(lines);
+    let mut response = Sse::new(events).keep_alive(KeepAlive::default()).into_response();
+    if let Ok(value) = HeaderValue::from_str(&job_id) {
+        response.headers_mut().insert(JOB_ID_HEADER, value);
+    }
+    response
+}
+
+/// Execute a command and stream output line by line
+#[utoipa::path(post, path = "/execute/stream", tag = "exec",
+    params(StreamQuery, ("Idempotency-Key" = Option<String>, Header,
+        description = "With `detach=true`, retries with the same key follow the job the first request started"),
+        ("Last-Event-ID" = Option<u64>, Header,
+        description = "Resume the job's stream after this line. Only a stream with `detach=true` and an \
+        Idempotency-Key can resume; anywhere else this header is a 400")),
+    request_body = CommandRequest,
+    responses((status = 200, description = "Server-sent events, one per output line, each line's sequence \
+        number as its `id`. A detached job's stream starts with `job_id: <id>`, also sent as `X-Rat-Job-Id`",
+        content_type = "text/event-stream"),
+        (status = 400, description = "Last-Event-ID was sent on a stream that can't resume", body = ErrorBody)))]
+pub(crate) async fn execute_command_stream(
+    State(state): State<AppState>,
+    caller: Caller,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+    Decoded(payload): Decoded<CommandRequest>,
+) -> Response {
+    // Only a detached job outlives its stream, and only its Idempotency-Key
+    // finds it again; resuming anything else would replay from the start
+    let resumable = query.detach && matches!(idempotency::key(&headers), Ok(Some(_)));
+    if headers.contains_key(LAST_EVENT_ID_HEADER) && !resumable {
+        return ApiError::new(
+            ErrorCode::BadRequest,
+            "Last-Event-ID can only resume a stream started with detach=true and an Idempotency-Key",
+        )
+        .into_response();
+    }
+
+    if query.detach {
+        let from_seq = match resume_from(&headers) {
+            Ok(from_seq) => from_seq,
+            Err(e) => return e.into_response(),
+        };
+        // A retry with the same Idempotency-Key follows the original job
+        let (job, replayed) = match jobs::start_job_once(&state, &caller, &headers, &payload).await {
+            Ok(started) => started,
+            Err(e) => return e.into_response(),
+        };
+        let response = job_events(job, if replayed { from_seq } else { 0 });
+        return if replayed { idempotency::replayed(response) } else { response };
+    }
+
+    let lines = match stream_command(&state, &caller, &payload) {
+        Ok(lines) => lines,
+        Err(e) => return e.into_response(),
+    };
+    // The command dies with the connection, so there is nothing to resume
+    // (a Last-Event-ID was refused above), but lines are numbered the same
+    // way as a job's
+    let mut next_seq = 0;
+    let events = lines.map(move |line| {
+        let seq = match line {
+            OutputLine::Exit(_) => None,
+            _ => {
+                next_seq += 1;
+                Some(next_seq - 1)
+            }
+        };
+        output_event(seq, line)
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
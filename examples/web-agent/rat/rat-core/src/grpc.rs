@@ -0,0 +1,236 @@
+//! gRPC service (`rat.v1.Rat`, see `proto/rat.proto`) over the same
+//! execute and session operations as the REST API. Served on the HTTP
+//! port; only built with the `grpc` feature.
+
+use crate::auth::{identify, Caller};
+use crate::error::{ApiError, ErrorCode};
+use crate::exec::{run_command, stream_command, CommandRequest, OutputLine};
+use crate::pty_io::CHANNEL_CAPACITY;
+use crate::quota;
+use crate::search::{EntryKind, Transcript};
+use crate::session::{attach, remove_session, resize_session, spawn_session, Attachment, SessionEnd, SessionOptions};
+use crate::state::AppState;
+use crate::throttle::{throttle_output, Throttle};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use tokio::sync::mpsc;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{info, warn};
+
+mod proto {
+    tonic::include_proto!("rat.v1");
+}
+
+use proto::rat_server::{Rat, RatServer};
+use proto::{
+    attach_input, output_chunk, AttachInput, AttachOutput, CreateSessionRequest, CreateSessionResponse,
+    ExecuteRequest, ExecuteResponse, ExitStatus, OutputChunk, StopSessionRequest, StopSessionResponse,
+};
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Routes for the gRPC service, ready to merge into the agent's router
+pub(crate) fn router(state: AppState) -> axum::Router {
+    tonic::service::Routes::new(RatServer::new(RatService { state })).into_axum_router()
+}
+
+struct RatService {
+    state: AppState,
+}
+
+/// Map the REST layer's errors onto the closest gRPC status
+fn status_from_http(error: ApiError) -> Status {
+    let message = error.message;
+    match error.code {
+        ErrorCode::SessionNotFound => Status::not_found(message),
+        ErrorCode::SessionAlreadyAttached => Status::failed_precondition(message),
+        ErrorCode::SessionLimitReached | ErrorCode::QuotaExceeded => Status::resource_exhausted(message),
+        ErrorCode::ShuttingDown => Status::unavailable(message),
+        ErrorCode::Unauthorized => Status::unauthenticated(message),
+        ErrorCode::CommandDenied => Status::permission_denied(message),
+        ErrorCode::BadRequest | ErrorCode::InvalidArguments => Status::invalid_argument(message),
+        _ => Status::internal(message),
+    }
+}
+
+impl RatService {
+    /// Identify the caller from the `authorization` metadata, as the REST
+    /// API does from the header
+    fn caller<T>(&self, request: &Request<T>) -> Result<Caller, Status> {
+        identify(&self.state, &request.metadata().clone().into_headers(), None)
+            .map_err(|e| Status::unauthenticated(e.message))
+    }
+}
+
+fn command_request(request: ExecuteRequest) -> CommandRequest {
+    CommandRequest {
+        command: request.command,
+        args: Some(request.args),
+        working_dir: request.working_dir,
+        profile: request.profile,
+        timeout_secs: None,
+    }
+}
+
+#[tonic::async_trait]
+impl Rat for RatService {
+    async fn execute(&self, request: Request<ExecuteRequest>) -> Result<Response<ExecuteResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let request = command_request(request.into_inner());
+        let output = run_command(&self.state, &caller, &request)
+            .await
+            .map_err(status_from_http)?;
+
+        Ok(Response::new(ExecuteResponse {
+            success: output.status.success(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code(),
+        }))
+    }
+
+    type ExecuteStreamStream = ResponseStream<OutputChunk>;
+
+    async fn execute_stream(
+        &self,
+        request: Request<ExecuteRequest>,
+    ) -> Result<Response<Self::ExecuteStreamStream>, Status> {
+        let caller = self.caller(&request)?;
+        let request = command_request(request.into_inner());
+        let lines = stream_command(&self.state, &caller, &request).map_err(status_from_http)?;
+
+        let chunks = lines.map(|line| {
+            let event = match line {
+                OutputLine::Stdout(line) => output_chunk::Event::Stdout(line),
+                OutputLine::Stderr(line) => output_chunk::Event::Stderr(line),
+                OutputLine::Error(message) => output_chunk::Event::Error(message),
+                OutputLine::Exit(code) => output_chunk::Event::Exit(ExitStatus { code }),
+            };
+            Ok(OutputChunk { event: Some(event) })
+        });
+        Ok(Response::new(Box::pin(chunks)))
+    }
+
+    async fn create_session(
+        &self,
+        request: Request<CreateSessionRequest>,
+    ) -> Result<Response<CreateSessionResponse>, Status> {
+        let caller = self.caller(&request)?;
+        let options = SessionOptions {
+            profile: request.into_inner().profile,
+            ..SessionOptions::default()
+        };
+        let session_id = spawn_session(&self.state, &caller, &options).map_err(status_from_http)?;
+        Ok(Response::new(CreateSessionResponse { session_id }))
+    }
+
+    async fn stop_session(
+        &self,
+        request: Request<StopSessionRequest>,
+    ) -> Result<Response<StopSessionResponse>, Status> {
+        self.caller(&request)?;
+        if remove_session(&self.state, &request.into_inner().session_id, SessionEnd::Stopped) {
+            Ok(Response::new(StopSessionResponse {}))
+        } else {
+            Err(Status::not_found("Session not found"))
+        }
+    }
+
+    type AttachStream = ResponseStream<AttachOutput>;
+
+    async fn attach(
+        &self,
+        request: Request<Streaming<AttachInput>>,
+    ) -> Result<Response<Self::AttachStream>, Status> {
+        self.caller(&request)?;
+        let mut inbound = request.into_inner();
+        let session_id = match inbound.message().await? {
+            Some(AttachInput { input: Some(attach_input::Input::SessionId(id)) }) => id,
+            _ => return Err(Status::invalid_argument("First message must carry a session_id")),
+        };
+
+        let Attachment { mut output, input, metrics, owner, handle, .. } =
+            attach(&self.state, &session_id).map_err(status_from_http)?;
+        info!("gRPC client attached to session {}", session_id);
+        let mut input_transcript = Transcript::start(&self.state, EntryKind::Input, &session_id, owner.as_deref());
+        let mut output_transcript = Transcript::start(&self.state, EntryKind::Output, &session_id, owner.as_deref());
+
+        // Client → PTY, until the client half-closes or shutdown starts
+        let metrics_in = metrics.clone();
+        let mut shutdown_rx = self.state.shared.shutdown.subscribe();
+        let input_state = self.state.clone();
+        let input_session_id = session_id.clone();
+        let mut input_task = tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    message = inbound.message() => message,
+                    _ = shutdown_rx.changed() => break,
+                };
+                match message {
+                    Ok(Some(AttachInput { input: Some(attach_input::Input::Data(data)) })) => {
+                        metrics_in.record_in(data.len());
+                        if let Some(transcript) = &mut input_transcript {
+                            transcript.feed(&data);
+                        }
+                        if input.send(Bytes::from(data)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Some(AttachInput { input: Some(attach_input::Input::Resize(size)) })) => {
+                        let cols = size.cols.min(u16::MAX as u32) as u16;
+                        let rows = size.rows.min(u16::MAX as u32) as u16;
+                        if let Err(e) = resize_session(&input_state, &input_session_id, cols, rows) {
+                            warn!("Resize of session {} failed: {}", input_session_id, e);
+                        }
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) | Err(_) => break,
+                }
+            }
+        });
+
+        // PTY → client. Runs in its own task so the attachment is released
+        // even when tonic drops the response stream on disconnect.
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let output_state = self.state.clone();
+        let throttle = Throttle::default();
+        tokio::spawn(async move {
+            let input_finished = loop {
+                tokio::select! {
+                    data = output.recv() => match data {
+                        Some(data) => {
+                            metrics.record_out(data.len());
+                            if !quota::charge_output(&output_state, owner.as_deref(), data.len()) {
+                                let _ = tx.send(Err(Status::resource_exhausted("Output quota exceeded"))).await;
+                                break false;
+                            }
+                            if let Some(transcript) = &mut output_transcript {
+                                transcript.feed(&data);
+                            }
+                            throttle_output(&output_state, &throttle, data.len()).await;
+                            if tx.send(Ok(AttachOutput { data: data.to_vec() })).await.is_err() {
+                                break false;
+                            }
+                        }
+                        None => break false,
+                    },
+                    _ = &mut input_task => break true,
+                    _ = tx.closed() => break false,
+                }
+            };
+            if !input_finished {
+                input_task.abort();
+                let _ = input_task.await;
+            }
+            drop(output);
+            handle.detach().await;
+            info!("gRPC client detached from session {}", session_id);
+        });
+
+        let outbound = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|item| (item, rx))
+        });
+        Ok(Response::new(Box::pin(outbound)))
+    }
+}
@@ -0,0 +1,227 @@
+//! Health probes, `/stats` and Prometheus `/metrics`.
+
+use crate::encoding::{Encoded, Format};
+use crate::session::SessionInfo;
+use crate::state::AppState;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+};
+use portable_pty::{PtySize, PtySystem};
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct HealthResponse {
+    status: String,
+    version: String,
+    public_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CheckResult {
+    ok: bool,
+    error: Option<String>,
+}
+
+impl CheckResult {
+    fn from_result(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => CheckResult { ok: true, error: None },
+            Err(e) => CheckResult { ok: false, error: Some(e) },
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ReadinessResponse {
+    status: String,
+    checks: HashMap<String, CheckResult>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct TunnelStatus {
+    enabled: bool,
+    connected: bool,
+    public_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct StatsResponse {
+    version: String,
+    uptime_secs: u64,
+    active_sessions: usize,
+    running_jobs: usize,
+    commands_executed: u64,
+    memory_rss_bytes: Option<u64>,
+    tunnel: TunnelStatus,
+}
+
+/// Health check endpoint
+#[utoipa::path(get, path = "/health", tag = "health",
+    responses((status = 200, body = HealthResponse)))]
+pub(crate) async fn health(State(state): State<AppState>, format: Format) -> Encoded<HealthResponse> {
+    let public_url = state.shared.public_url.read().await.clone();
+    Encoded(format, HealthResponse {
+        status: "ok".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        public_url,
+    })
+}
+
+/// Liveness probe: the process is up and serving requests
+#[utoipa::path(get, path = "/health/live", tag = "health",
+    responses((status = 200, description = "Process is alive")))]
+pub(crate) async fn health_live(format: Format) -> Encoded<serde_json::Value> {
+    Encoded(format, serde_json::json!({"status": "alive"}))
+}
+
+/// Verify a PTY can actually be opened on this host
+fn check_pty(pty_system: Box<dyn PtySystem + Send>) -> Result<(), String> {
+    pty_system
+        .openpty(PtySize {
+            rows: 1,
+            cols: 1,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open PTY: {}", e))
+}
+
+/// If a tunnel was requested it must have produced a public URL
+async fn check_tunnel(state: &AppState) -> Result<(), String> {
+    if !state.shared.tunnel_enabled.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    if state.shared.public_url.read().await.is_some() {
+        Ok(())
+    } else {
+        Err("Tunnel enabled but no public URL".to_string())
+    }
+}
+
+/// Sessions are unusable if their locks are poisoned or the map is stuck
+async fn check_sessions(state: &AppState) -> Result<(), String> {
+    let shared = state.shared.clone();
+    let poisoned = tokio::time::timeout(
+        Duration::from_secs(1),
+        tokio::task::spawn_blocking(move || {
+            shared.sessions.iter().filter(|entry| entry.value().is_poisoned()).count()
+        }),
+    )
+    .await
+    .map_err(|_| "Timed out reading the session map".to_string())?
+    .map_err(|e| format!("Session check failed: {}", e))?;
+
+    if poisoned == 0 {
+        Ok(())
+    } else {
+        Err(format!("{} session lock(s) poisoned", poisoned))
+    }
+}
+
+/// Readiness probe: returns 503 unless every dependency check passes
+#[utoipa::path(get, path = "/health/ready", tag = "health",
+    responses(
+        (status = 200, body = ReadinessResponse),
+        (status = 503, body = ReadinessResponse, description = "A dependency check failed"),
+    ))]
+pub(crate) async fn health_ready(
+    State(state): State<AppState>,
+    format: Format,
+) -> (StatusCode, Encoded<ReadinessResponse>) {
+    let pty_system = state.pty_system();
+    let pty = tokio::task::spawn_blocking(move || check_pty(pty_system))
+        .await
+        .unwrap_or_else(|e| Err(format!("PTY check failed: {}", e)));
+
+    let mut checks = HashMap::new();
+    checks.insert("pty".to_string(), CheckResult::from_result(pty));
+    checks.insert("tunnel".to_string(), CheckResult::from_result(check_tunnel(&state).await));
+    checks.insert("sessions".to_string(), CheckResult::from_result(check_sessions(&state).await));
+
+    let ready = checks.values().all(|c| c.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (status, Encoded(format, ReadinessResponse {
+        status: if ready { "ready" } else { "not_ready" }.to_string(),
+        checks,
+    }))
+}
+
+/// Resident set size of this process, read from /proc on Linux
+fn memory_usage_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Server statistics endpoint
+#[utoipa::path(get, path = "/stats", tag = "health",
+    responses((status = 200, body = StatsResponse)))]
+pub(crate) async fn stats(State(state): State<AppState>, format: Format) -> Encoded<StatsResponse> {
+    let public_url = state.shared.public_url.read().await.clone();
+    let shared = &state.shared;
+
+    Encoded(format, StatsResponse {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: state.uptime().as_secs(),
+        active_sessions: shared.sessions.len(),
+        running_jobs: shared.running_jobs.load(Ordering::Relaxed),
+        commands_executed: shared.commands_executed.load(Ordering::Relaxed),
+        memory_rss_bytes: memory_usage_bytes(),
+        tunnel: TunnelStatus {
+            enabled: shared.tunnel_enabled.load(Ordering::Relaxed),
+            connected: public_url.is_some(),
+            public_url,
+        },
+    })
+}
+
+/// Prometheus text-format metrics, including per-session traffic counters
+#[utoipa::path(get, path = "/metrics", tag = "health",
+    responses((status = 200, description = "Prometheus text exposition format", content_type = "text/plain")))]
+pub(crate) async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    use std::fmt::Write;
+
+    let sessions: Vec<SessionInfo> = state
+        .shared
+        .sessions
+        .iter()
+        .map(|entry| SessionInfo::from_session(&entry.value().lock().unwrap()))
+        .collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "# TYPE rat_uptime_seconds gauge");
+    let _ = writeln!(out, "rat_uptime_seconds {}", state.uptime().as_secs());
+    let _ = writeln!(out, "# TYPE rat_sessions_active gauge");
+    let _ = writeln!(out, "rat_sessions_active {}", sessions.len());
+    let _ = writeln!(out, "# TYPE rat_jobs_running gauge");
+    let _ = writeln!(out, "rat_jobs_running {}", state.shared.running_jobs.load(Ordering::Relaxed));
+    let _ = writeln!(out, "# TYPE rat_commands_executed_total counter");
+    let _ = writeln!(out, "rat_commands_executed_total {}", state.shared.commands_executed.load(Ordering::Relaxed));
+
+    let per_session: [(&str, &str, fn(&SessionInfo) -> u64); 7] = [
+        ("rat_session_bytes_in_total", "counter", |s| s.bytes_in),
+        ("rat_session_bytes_out_total", "counter", |s| s.bytes_out),
+        ("rat_session_frames_in_total", "counter", |s| s.frames_in),
+        ("rat_session_frames_out_total", "counter", |s| s.frames_out),
+        ("rat_session_frames_per_second", "gauge", |s| s.frames_per_sec),
+        ("rat_session_idle_seconds", "gauge", |s| s.idle_secs),
+        ("rat_session_attached", "gauge", |s| s.attached as u64),
+    ];
+    for (name, kind, value) in per_session {
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for session in &sessions {
+            let _ = writeln!(out, "{}{{session=\"{}\"}} {}", name, session.id, value(session));
+        }
+    }
+
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], out)
+}
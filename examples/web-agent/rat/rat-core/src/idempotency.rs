@@ -0,0 +1,144 @@
+//! `Idempotency-Key` support for `POST /execute` and `POST /jobs`, so a
+//! client that retries after losing the response doesn't run the command
+//! twice.
+//!
+//! The first request with a key runs as usual and its result is stored
+//! under the key, scoped to the caller's token and the route. A retry with
+//! the same key and body gets that result back, marked with
+//! `Idempotent-Replayed: true`, and one that arrives while the original is
+//! still running waits for it. Reusing a key with a different body is
+//! refused. A request that fails stores nothing, so its retry runs afresh.
+//! Results are kept for `jobs.idempotency_retention_secs`.
+
+use crate::auth::Caller;
+use crate::error::{ApiError, ErrorCode};
+use crate::state::{unix_millis, AppState};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use dashmap::mapref::entry::Entry;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::watch;
+
+const KEY_HEADER: &str = "idempotency-key";
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+const MAX_KEY_LEN: usize = 255;
+
+/// A key's request and, once the original has finished, its result
+pub(crate) struct Stored {
+    fingerprint: String,
+    created_at_ms: u64,
+    result: watch::Sender<Option<Value>>,
+}
+
+/// The outcome of presenting a key
+pub(crate) enum Claim {
+    /// First use: run the request and `complete` with its result
+    New(Pending),
+    /// Seen before: the original request's result
+    Replay(Value),
+}
+
+/// A claimed key whose request is running. Dropped without `complete`,
+/// the key is released for a retry to claim.
+pub(crate) struct Pending {
+    state: AppState,
+    id: String,
+    stored: Arc<Stored>,
+}
+
+impl Pending {
+    pub(crate) fn complete(self, result: Value) {
+        self.stored.result.send_replace(Some(result));
+    }
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let finished = self.stored.result.borrow().is_some();
+        if !finished {
+            self.state.shared.idempotency_keys.remove_if(&self.id, |_, stored| Arc::ptr_eq(stored, &self.stored));
+        }
+    }
+}
+
+/// The request's `Idempotency-Key`, if it sent one
+pub(crate) fn key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(KEY_HEADER) else {
+        return Ok(None);
+    };
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => Ok(Some(key.to_string())),
+        _ => Err(ApiError::new(
+            ErrorCode::BadRequest,
+            format!("Idempotency-Key must be 1 to {} visible ASCII characters", MAX_KEY_LEN),
+        )),
+    }
+}
+
+/// Mark a response as the stored result of an earlier request
+pub(crate) fn replayed(response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    response.headers_mut().insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// Forget finished requests past their retention
+fn purge_expired(state: &AppState) {
+    let retention_ms = state.config().jobs.idempotency_retention_secs * 1000;
+    let now = unix_millis();
+    state.shared.idempotency_keys.retain(|_, stored| {
+        stored.result.borrow().is_none() || now.saturating_sub(stored.created_at_ms) < retention_ms
+    });
+}
+
+/// Claim `key` for `request` to `route`, or wait for the result of the
+/// request that already claimed it
+pub(crate) async fn claim<T: Serialize>(
+    state: &AppState,
+    caller: &Caller,
+    route: &str,
+    key: &str,
+    request: &T,
+) -> Result<Claim, ApiError> {
+    let fingerprint = serde_json::to_string(request).unwrap_or_default();
+    let id = format!("{}\n{}\n{}", caller.token_name().unwrap_or_default(), route, key);
+    loop {
+        purge_expired(state);
+        let mut result = match state.shared.idempotency_keys.entry(id.clone()) {
+            Entry::Occupied(existing) => {
+                if existing.get().fingerprint != fingerprint {
+                    return Err(ApiError::new(
+                        ErrorCode::IdempotencyKeyReused,
+                        "Idempotency-Key was already used with a different request",
+                    ));
+                }
+                existing.get().result.subscribe()
+            }
+            Entry::Vacant(vacant) => {
+                let stored = Arc::new(Stored {
+                    fingerprint,
+                    created_at_ms: unix_millis(),
+                    result: watch::channel(None).0,
+                });
+                vacant.insert(stored.clone());
+                return Ok(Claim::New(Pending {
+                    state: state.clone(),
+                    id,
+                    stored,
+                }));
+            }
+        };
+        loop {
+            let done = result.borrow_and_update().clone();
+            if let Some(value) = done {
+                return Ok(Claim::Replay(value));
+            }
+            // The original failed and released the key; try to claim it
+            if result.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Detached jobs: commands that run without a client attached, their output
+//! spooled in memory and read back by sequence number.
+//!
+//! A job is started with `POST /jobs`, or by `POST /execute/stream?detach=true`,
+//! which streams the job's output but leaves the command running if the
+//! client goes away. Each spooled line gets the next sequence number;
+//! `GET /jobs/:id/output?from_seq=` returns the lines from there on. Finished
+//! jobs are forgotten after `jobs.retention_secs`.
+
+use crate::auth::Caller;
+use crate::encoding::{Decoded, Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::exec::{self, stream_command, CommandRequest, OutputLine};
+use crate::idempotency::{self, Claim};
+use crate::state::{unix_millis, AppState};
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+const DEFAULT_OUTPUT_LINES: usize = 1000;
+const MAX_OUTPUT_LINES: usize = 10_000;
+
+#[derive(Serialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum JobStream {
+    Stdout,
+    Stderr,
+    /// Reading the command's output failed
+    Error,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub(crate) struct JobLine {
+    seq: u64,
+    stream: JobStream,
+    text: String,
+}
+
+impl JobLine {
+    fn into_output(self) -> OutputLine {
+        match self.stream {
+            JobStream::Stdout => OutputLine::Stdout(self.text),
+            JobStream::Stderr => OutputLine::Stderr(self.text),
+            JobStream::Error => OutputLine::Error(self.text),
+        }
+    }
+}
+
+/// A job's spooled output and how it ended
+#[derive(Default)]
+struct Spool {
+    lines: VecDeque<JobLine>,
+    bytes: usize,
+    next_seq: u64,
+    finished_at_ms: Option<u64>,
+    exit_code: Option<i32>,
+    stopped: bool,
+}
+
+impl Spool {
+    fn first_seq(&self) -> u64 {
+        self.lines.front().map_or(self.next_seq, |line| line.seq)
+    }
+
+    fn since(&self, from_seq: u64, limit: usize) -> Vec<JobLine> {
+        let skip = from_seq.saturating_sub(self.first_seq()) as usize;
+        self.lines.iter().skip(skip).take(limit).cloned().collect()
+    }
+}
+
+pub(crate) struct Job {
+    id: String,
+    command: String,
+    args: Vec<String>,
+    started_at_ms: u64,
+    spool: Mutex<Spool>,
+    /// Bumped whenever a line is spooled or the job ends
+    changed: watch::Sender<()>,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl Job {
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn push(&self, state: &AppState, stream: JobStream, text: String) {
+        let max_bytes = state.config().jobs.max_output_bytes;
+        let mut spool = self.spool.lock().unwrap();
+        let seq = spool.next_seq;
+        spool.next_seq += 1;
+        spool.bytes += text.len();
+        spool.lines.push_back(JobLine { seq, stream, text });
+        while spool.bytes > max_bytes {
+            match spool.lines.pop_front() {
+                Some(dropped) => spool.bytes -= dropped.text.len(),
+                None => break,
+            }
+        }
+        drop(spool);
+        self.changed.send_replace(());
+    }
+
+    fn finish(&self, exit_code: Option<i32>, stopped: bool) {
+        let mut spool = self.spool.lock().unwrap();
+        if spool.finished_at_ms.is_some() {
+            return;
+        }
+        spool.finished_at_ms = Some(unix_millis());
+        spool.exit_code = exit_code;
+        spool.stopped = stopped;
+        drop(spool);
+        self.changed.send_replace(());
+    }
+
+    fn info(&self) -> JobInfo {
+        let spool = self.spool.lock().unwrap();
+        JobInfo {
+            id: self.id.clone(),
+            command: self.command.clone(),
+            args: self.args.clone(),
+            started_at_ms: self.started_at_ms,
+            finished_at_ms: spool.finished_at_ms,
+            running: spool.finished_at_ms.is_none(),
+            exit_code: spool.exit_code,
+            stopped: spool.stopped,
+            lines: spool.next_seq,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct JobInfo {
+    id: String,
+    command: String,
+    args: Vec<String>,
+    started_at_ms: u64,
+    finished_at_ms: Option<u64>,
+    running: bool,
+    /// Set once the command has exited on its own
+    exit_code: Option<i32>,
+    /// Whether the job was ended with `POST /jobs/:id/stop`
+    stopped: bool,
+    /// Lines spooled so far, including any since dropped
+    lines: u64,
+}
+
+/// Forget finished jobs past their retention
+fn purge_expired(state: &AppState) {
+    let retention_ms = state.config().jobs.retention_secs * 1000;
+    let now = unix_millis();
+    state.shared.jobs.retain(|_, job| match job.spool.lock().unwrap().finished_at_ms {
+        Some(finished) => now.saturating_sub(finished) < retention_ms,
+        None => true,
+    });
+}
+
+/// Start `request` as a detached job charged to `caller`. The command runs
+/// until it exits or the job is stopped, whoever is reading its output.
+fn start_job(state: &AppState, caller: &Caller, request: &CommandRequest) -> Result<Arc<Job>, ApiError> {
+    purge_expired(state);
+    let lines = stream_command(state, caller, request)?;
+    let job = Arc::new(Job {
+        id: Uuid::new_v4().to_string(),
+        command: request.command.clone(),
+        args: request.args.clone().unwrap_or_default(),
+        started_at_ms: unix_millis(),
+        spool: Mutex::new(Spool::default()),
+        changed: watch::channel(()).0,
+        task: Mutex::new(None),
+    });
+    info!("Started job {}: {}", job.id, job.command);
+    state.shared.jobs.insert(job.id.clone(), job.clone());
+
+    let task = tokio::spawn({
+        let state = state.clone();
+        let job = job.clone();
+        async move {
+            let mut lines = std::pin::pin!(lines);
+            let mut exit_code = None;
+            while let Some(line) = lines.next().await {
+                match line {
+                    OutputLine::Stdout(text) => job.push(&state, JobStream::Stdout, text),
+                    OutputLine::Stderr(text) => job.push(&state, JobStream::Stderr, text),
+                    OutputLine::Error(text) => job.push(&state, JobStream::Error, text),
+                    OutputLine::Exit(code) => exit_code = code,
+                }
+            }
+            job.finish(exit_code, false);
+        }
+    });
+    *job.task.lock().unwrap() = Some(task.abort_handle());
+    Ok(job)
+}
+
+/// Start `request` as a job, or with an `Idempotency-Key` already used for
+/// it, find the job that request started. Says whether the job is a replay.
+pub(crate) async fn start_job_once(
+    state: &AppState,
+    caller: &Caller,
+    headers: &HeaderMap,
+    request: &CommandRequest,
+) -> Result<(Arc<Job>, bool), ApiError> {
+    let Some(key) = idempotency::key(headers)? else {
+        return Ok((start_job(state, caller, request)?, false));
+    };
+    match idempotency::claim(state, caller, "jobs", &key, request).await? {
+        Claim::New(pending) => {
+            let job = start_job(state, caller, request)?;
+            pending.complete(Value::from(job.id()));
+            Ok((job, false))
+        }
+        Claim::Replay(job_id) => Ok((find_job(state, job_id.as_str().unwrap_or_default())?, true)),
+    }
+}
+
+/// The job's output from `from_seq` on, live until it ends, in the shape of
+/// a streamed command's. Spooled lines come with their sequence number.
+pub(crate) fn follow(job: Arc<Job>, from_seq: u64) -> impl Stream<Item = (Option<u64>, OutputLine)> + Send + 'static {
+    async_stream::stream! {
+        // Subscribed before the first read, so no change can slip between
+        let mut changed = job.changed.subscribe();
+        let mut next_seq = from_seq;
+        loop {
+            let (lines, first_seq, finished, exit_code, stopped) = {
+                let spool = job.spool.lock().unwrap();
+                let first_seq = spool.first_seq();
+                (spool.since(next_seq, usize::MAX), first_seq, spool.finished_at_ms.is_some(), spool.exit_code, spool.stopped)
+            };
+            if first_seq > next_seq {
+                let dropped = first_seq - next_seq;
+                yield (None, OutputLine::Error(format!("{} lines were dropped from the job's spool", dropped)));
+                next_seq = first_seq;
+            }
+            for line in lines {
+                next_seq = line.seq + 1;
+                yield (Some(line.seq), line.into_output());
+            }
+            if finished {
+                if stopped {
+                    yield (None, OutputLine::Error("Job stopped".to_string()));
+                } else {
+                    yield (None, OutputLine::Exit(exit_code));
+                }
+                break;
+            }
+            if changed.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+fn find_job(state: &AppState, job_id: &str) -> Result<Arc<Job>, ApiError> {
+    state
+        .shared
+        .jobs
+        .get(job_id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| ApiError::new(ErrorCode::JobNotFound, "Job not found"))
+}
+
+/// Start a command as a detached job
+#[utoipa::path(post, path = "/jobs", tag = "jobs",
+    params(("Idempotency-Key" = Option<String>, Header,
+        description = "Retries with the same key return the job the first request started")),
+    request_body = CommandRequest,
+    responses(
+        (status = 200, body = JobInfo),
+        (status = 403, description = "The command isn't allowed by policy", body = ErrorBody),
+        (status = 422, description = "The Idempotency-Key was used with a different request", body = ErrorBody),
+        (status = 429, description = "The token's job or output quota is used up", body = ErrorBody),
+        (status = 500, description = "Command could not be started", body = ErrorBody),
+    ))]
+pub(crate) async fn create_job(
+    State(state): State<AppState>,
+    format: Format,
+    caller: Caller,
+    headers: HeaderMap,
+    Decoded(request): Decoded<CommandRequest>,
+) -> Result<Response, ApiError> {
+    let (job, replayed) = start_job_once(&state, &caller, &headers, &request).await?;
+    let response = Encoded(format, job.info());
+    Ok(if replayed { idempotency::replayed(response) } else { response.into_response() })
+}
+
+/// List running jobs and finished ones still within their retention
+#[utoipa::path(get, path = "/jobs", tag = "jobs",
+    responses((status = 200, body = [JobInfo])))]
+pub(crate) async fn list_jobs(State(state): State<AppState>, format: Format) -> Encoded<Vec<JobInfo>> {
+    purge_expired(&state);
+    let mut jobs: Vec<JobInfo> = state.shared.jobs.iter().map(|entry| entry.value().info()).collect();
+    jobs.sort_by_key(|job| job.started_at_ms);
+    Encoded(format, jobs)
+}
+
+/// A job's status
+#[utoipa::path(get, path = "/jobs/{job_id}", tag = "jobs",
+    params(("job_id" = String, Path)),
+    responses(
+        (status = 200, body = JobInfo),
+        (status = 404, description = "Job not found", body = ErrorBody),
+    ))]
+pub(crate) async fn get_job(
+    State(state): State<AppState>,
+    format: Format,
+    Path(job_id): Path<String>,
+) -> Result<Encoded<JobInfo>, ApiError> {
+    Ok(Encoded(format, find_job(&state, &job_id)?.info()))
+}
+
+/// Follow a job's output as server-sent events, from the start or, for a
+/// reconnecting client, after the line it saw last
+#[utoipa::path(get, path = "/jobs/{job_id}/stream", tag = "jobs",
+    params(("job_id" = String, Path),
+        ("Last-Event-ID" = Option<u64>, Header, description = "Resume the stream after this line")),
+    responses(
+        (status = 200, description = "Server-sent events like a detached `/execute/stream`'s",
+            content_type = "text/event-stream"),
+        (status = 404, description = "Job not found", body = ErrorBody),
+    ))]
+pub(crate) async fn stream_job(
+    State(state): State<AppState>,
+    Path(job_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let from_seq = exec::resume_from(&headers)?;
+    Ok(exec::job_events(find_job(&state, &job_id)?, from_seq))
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct JobOutputQuery {
+    /// First sequence number to return (default 0)
+    from_seq: Option<u64>,
+    /// Lines to return (default 1000, at most 10000)
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct JobOutput {
+    job: JobInfo,
+    /// Oldest line still spooled; earlier ones were dropped to stay within
+    /// `jobs.max_output_bytes`
+    first_seq: u64,
+    /// Pass as `from_seq` to continue after these lines
+    next_seq: u64,
+    lines: Vec<JobLine>,
+}
+
+/// Spooled output of a job
+#[utoipa::path(get, path = "/jobs/{job_id}/output", tag = "jobs",
+    params(("job_id" = String, Path), JobOutputQuery),
+    responses(
+        (status = 200, body = JobOutput),
+        (status = 404, description = "Job not found", body = ErrorBody),
+    ))]
+pub(crate) async fn job_output(
+    State(state): State<AppState>,
+    format: Format,
+    Path(job_id): Path<String>,
+    Query(query): Query<JobOutputQuery>,
+) -> Result<Encoded<JobOutput>, ApiError> {
+    let job = find_job(&state, &job_id)?;
+    let info = job.info();
+    let limit = query.limit.unwrap_or(DEFAULT_OUTPUT_LINES).min(MAX_OUTPUT_LINES);
+    let spool = job.spool.lock().unwrap();
+    let first_seq = spool.first_seq();
+    let from_seq = query.from_seq.unwrap_or(0).max(first_seq);
+    let lines = spool.since(from_seq, limit);
+    let next_seq = lines.last().map_or(from_seq.min(spool.next_seq), |line| line.seq + 1);
+    drop(spool);
+    Ok(Encoded(format, JobOutput {
+        job: info,
+        first_seq,
+        next_seq,
+        lines,
+    }))
+}
+
+/// Stop a job, killing its command. Its output stays readable.
+#[utoipa::path(post, path = "/jobs/{job_id}/stop", tag = "jobs",
+    params(("job_id" = String, Path)),
+    responses(
+        (status = 200, body = JobInfo),
+        (status = 404, description = "Job not found", body = ErrorBody),
+    ))]
+pub(crate) async fn stop_job(
+    State(state): State<AppState>,
+    format: Format,
+    Path(job_id): Path<String>,
+) -> Result<Encoded<JobInfo>, ApiError> {
+    let job = find_job(&state, &job_id)?;
+    info!("Stopping job {}", job_id);
+    // Dropping the command's stream kills it
+    if let Some(task) = job.task.lock().unwrap().take() {
+        task.abort();
+    }
+    job.finish(None, true);
+    Ok(Encoded(format, job.info()))
+}
@@ -0,0 +1,184 @@
+//! Core of the rat agent: PTY session management, command execution and the
+//! axum router that exposes them.
+//!
+//! The `rat` binary is a thin wrapper around this crate. Other applications
+//! can mount the same API inside their own axum server:
+//!
+//! ```no_run
+//! # async fn example() -> anyhow::Result<()> {
+//! let state = rat_core::AppState::new();
+//! let app = rat_core::build_router(state, rat_core::config::Config::default());
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+//! axum::serve(listener, app).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod access_log;
+pub mod chaos;
+pub mod config;
+pub mod plugin;
+pub mod recording;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+mod admin;
+mod auth;
+mod compression;
+mod encoding;
+mod error;
+mod events;
+mod exec;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod idempotency;
+mod jobs;
+mod limits;
+mod mcp;
+mod openapi;
+mod profiles;
+mod pty_io;
+mod query;
+mod quota;
+mod repl;
+mod search;
+mod server;
+mod session;
+mod shutdown;
+mod state;
+mod system;
+mod throttle;
+mod tools;
+mod tunnel;
+mod ui;
+mod version;
+
+pub use admin::reload_on_sighup;
+pub use events::{EventKind, ServerEvent};
+pub use mcp::serve_stdio as serve_mcp_stdio;
+pub use server::{serve, serve_tls, tls_acceptor};
+pub use shutdown::{shutdown_signal, terminate_children};
+pub use state::{AppState, ConfigLoader, PtySystemFactory};
+pub use tunnel::start_ngrok;
+
+use axum::{
+    body::HttpBody,
+    http::{HeaderValue, Response},
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use config::Config;
+use tower_http::compression::predicate::{DefaultPredicate, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use utoipa_swagger_ui::SwaggerUi;
+
+/// CORS layer that consults the live config on every request, so reloads
+/// take effect without rebuilding the router
+fn cors_layer(state: AppState) -> CorsLayer {
+    CorsLayer::permissive().allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+        state
+            .config()
+            .cors
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.as_bytes() == origin.as_bytes())
+    }))
+}
+
+/// Compresses responses as `[http_compression]` says at the time, on top of
+/// the default rules: never server-sent events, gRPC or images
+#[derive(Clone)]
+struct LiveCompression(AppState);
+
+impl Predicate for LiveCompression {
+    fn should_compress<B: HttpBody>(&self, response: &Response<B>) -> bool {
+        let config = self.0.config();
+        let compression = &config.http_compression;
+        compression.enabled
+            && SizeAbove::new(compression.min_size_bytes).should_compress(response)
+            && DefaultPredicate::new().should_compress(response)
+    }
+}
+
+/// Routes that make up one version of the API, mounted under `/v1` and,
+/// for older clients, at the root
+fn api_routes(state: &AppState) -> Router<AppState> {
+    let mut router = Router::new();
+    for plugin in state.plugins() {
+        router = router.nest_service(&format!("/plugins/{}", plugin.name()), plugin.router());
+    }
+
+    router
+        .route("/version", get(version::version))
+        .route("/capabilities", get(version::capabilities))
+        .route("/health", get(health::health))
+        .route("/health/live", get(health::health_live))
+        .route("/health/ready", get(health::health_ready))
+        .route("/stats", get(health::stats))
+        .route("/metrics", get(health::metrics))
+        .route("/execute", post(exec::execute_command))
+        .route("/execute/stream", post(exec::execute_command_stream))
+        .route("/jobs", get(jobs::list_jobs).post(jobs::create_job))
+        .route("/jobs/:job_id", get(jobs::get_job))
+        .route("/jobs/:job_id/output", get(jobs::job_output))
+        .route("/jobs/:job_id/stream", get(jobs::stream_job))
+        .route("/jobs/:job_id/stop", post(jobs::stop_job))
+        .route("/session/create", post(session::create_session))
+        .route("/sessions", get(session::list_sessions))
+        .route("/session/:session_id/stop", post(session::stop_session))
+        .route("/shell/new", get(session::new_shell_ws_handler))
+        .route("/shell/:session_id", get(session::shell_ws_handler))
+        .route("/repl/create", post(repl::create_repl))
+        .route("/repls", get(repl::list_repls))
+        .route("/repl/:repl_id/eval", post(repl::eval_repl))
+        .route("/repl/:repl_id/stop", post(repl::stop_repl))
+        .route("/mcp", post(mcp::mcp_http))
+        .route("/search", get(search::search))
+        .route("/tools", get(tools::list_tools))
+        .route("/tools/:name", post(tools::call_tool))
+        .route("/events", get(events::events_ws_handler))
+        .route("/admin/reload", post(admin::admin_reload))
+        .route("/admin/quotas", get(quota::admin_quotas))
+        .route("/admin/access-log", get(access_log::export_access_log))
+        .route("/plugins", get(plugin::list_plugins))
+        .route("/system/env", get(system::env::environment))
+        .route("/system/logs", get(system::logs::logs))
+        .route("/system/metrics", get(system::metrics::metrics_ws_handler))
+        .route("/system/network", get(system::network::network))
+        .route("/system/processes", get(system::processes::list_processes))
+        .route("/system/processes/:pid/signal", post(system::processes::signal_process))
+        .route("/system/users", get(system::users::users))
+}
+
+/// Build the agent's router with `config` as the active configuration.
+///
+/// The API lives under `/v1`; the same routes are still served without the
+/// prefix, marked with a `Deprecation` header, until clients have moved.
+/// With the `grpc` feature the `rat.v1.Rat` service is served alongside.
+pub fn build_router(state: AppState, config: Config) -> Router {
+    state.set_config(config);
+
+    let api = api_routes(&state)
+        .layer(middleware::from_fn_with_state(state.clone(), auth::authenticate))
+        .layer(middleware::from_fn_with_state(state.clone(), limits::limit_requests))
+        .layer(middleware::from_fn_with_state(state.clone(), access_log::record));
+
+    let router = Router::new()
+        .nest(version::API_PREFIX, api.clone())
+        .merge(api.layer(middleware::from_fn(version::deprecate_legacy)))
+        .merge(ui::routes())
+        .merge(SwaggerUi::new("/swagger-ui").url("/openapi.json", openapi::spec(&state)))
+        .layer(middleware::from_fn(version::negotiate))
+        .layer(middleware::from_fn(error::envelope))
+        .layer(cors_layer(state.clone()))
+        .layer(CompressionLayer::new().compress_when(LiveCompression(state.clone())))
+        .with_state(state.clone());
+
+    #[cfg(feature = "grpc")]
+    let router = router.merge(grpc::router(state));
+
+    router
+}
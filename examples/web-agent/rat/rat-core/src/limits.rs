@@ -0,0 +1,97 @@
+//! Request timeouts and the in-flight request cap from `[limits]`, so
+//! stalled clients and slow commands can't pile up on the server.
+//!
+//! Both are read from the live config on every request. The timeout covers
+//! reading the body and producing the response; streaming routes are left
+//! alone, as their responses are meant to last, and so are routes that time
+//! their own work and can say what was stopped. Connections that never
+//! finish sending headers are closed by `serve` instead.
+
+use crate::error::{ApiError, ErrorCode};
+use crate::state::AppState;
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tracing::warn;
+
+/// Routes whose responses stream for as long as the client wants
+const STREAMING_ROUTES: &[&str] = &[
+    "/execute/stream",
+    "/shell/",
+    "/events",
+    "/mcp",
+    "/system/logs",
+    "/system/metrics",
+    "/admin/access-log",
+];
+
+/// Routes that enforce a timeout of their own: `/execute` kills the command
+/// at its `timeout_secs` (by default the one below) and REPL evals stop at
+/// `repl.eval_timeout_secs`
+fn times_itself(path: &str) -> bool {
+    path == "/execute" || (path.starts_with("/repl/") && path.ends_with("/eval"))
+}
+
+/// Releases an in-flight slot when the request is done, however it ends
+struct InFlight<'a>(&'a AppState);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.shared.in_flight_requests.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// How long the middleware lets a request to `path` run, if at all
+fn timeout_for(state: &AppState, path: &str) -> Option<Duration> {
+    let follows_job = path.starts_with("/jobs/") && path.ends_with("/stream");
+    if follows_job || times_itself(path) || STREAMING_ROUTES.iter().any(|route| path.starts_with(route)) {
+        return None;
+    }
+    route_timeout(state, path)
+}
+
+/// Seconds `path` may take, from the longest matching `route_timeouts`
+/// prefix or else `request_timeout_secs`
+pub(crate) fn route_timeout(state: &AppState, path: &str) -> Option<Duration> {
+    let config = state.config();
+    let limits = &config.limits;
+    let secs = limits
+        .route_timeouts
+        .iter()
+        .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, secs)| *secs)
+        .unwrap_or(limits.request_timeout_secs);
+    (secs > 0).then_some(Duration::from_secs(secs))
+}
+
+/// Middleware that refuses requests past `max_in_flight_requests` and
+/// answers those that outlive their timeout with 504
+pub(crate) async fn limit_requests(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let in_flight = state.shared.in_flight_requests.fetch_add(1, Ordering::Relaxed) + 1;
+    let _slot = InFlight(&state);
+    if let Some(max) = state.config().limits.max_in_flight_requests {
+        if in_flight > max {
+            warn!("Refusing {}: {} requests already in flight", request.uri().path(), max);
+            let error = ApiError::new(ErrorCode::Overloaded, format!("Server is handling {} requests already", max));
+            return ([(header::RETRY_AFTER, "1")], error).into_response();
+        }
+    }
+
+    let path = request.uri().path().to_string();
+    let Some(timeout) = timeout_for(&state, &path) else {
+        return next.run(request).await;
+    };
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("Request to {} timed out after {:?}", path, timeout);
+            ApiError::new(ErrorCode::Timeout, format!("Request timed out after {}s", timeout.as_secs())).into_response()
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Model Context Protocol server, so MCP-capable agents can use the host
+//! through the tools in `tools` without custom glue.
+//!
+//! Two transports speak the same JSON-RPC 2.0 messages:
+//! - streamable HTTP at `POST /mcp`: each request is answered in the
+//!   response body; the server never opens an SSE stream of its own
+//! - stdio (`rat --mcp-stdio`): one message per line on stdin and stdout
+
+use crate::auth::Caller;
+use crate::config::Config;
+use crate::state::AppState;
+use crate::tools::{self, ToolFormat};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::Bytes;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// Newest protocol revision this server implements
+const PROTOCOL_VERSION: &str = "2025-03-26";
+/// Older revisions whose messages are the same for the methods served here
+const SUPPORTED_VERSIONS: &[&str] = &["2025-03-26", "2024-11-05"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+fn error_response(id: Value, code: i64, message: impl Into<String>) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message.into()}})
+}
+
+/// Dispatch one request to its method; `Err` is a JSON-RPC error
+async fn dispatch(state: &AppState, caller: &Caller, method: &str, params: Value) -> Result<Value, (i64, String)> {
+    match method {
+        "initialize" => {
+            // Answer in the client's revision if we speak it, else in ours
+            let requested = params.get("protocolVersion").and_then(Value::as_str);
+            let version = requested
+                .filter(|v| SUPPORTED_VERSIONS.contains(v))
+                .unwrap_or(PROTOCOL_VERSION);
+            if let Some(client) = params.pointer("/clientInfo/name").and_then(Value::as_str) {
+                info!("MCP client {} connected (protocol {})", client, version);
+            }
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": {"tools": {"listChanged": false}},
+                "serverInfo": {"name": "rat", "version": env!("CARGO_PKG_VERSION")},
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => {
+            let tools: Vec<Value> = tools::tools(state)
+                .iter()
+                .map(|tool| tool.definition(ToolFormat::Mcp))
+                .collect();
+            Ok(json!({"tools": tools}))
+        }
+        "tools/call" => {
+            let name = params
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+            let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            let output = tools::call(state, caller, name, arguments)
+                .await
+                .map_err(|e| (INVALID_PARAMS, e))?;
+            Ok(json!({
+                "content": [{"type": "text", "text": output.text}],
+                "isError": output.is_error,
+            }))
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+/// Handle one message. Notifications and responses from the client get no
+/// reply, so they yield `None`.
+async fn handle_message(state: &AppState, caller: &Caller, message: Value) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        // A response to a request we never send, or garbage with an id
+        return match id {
+            Some(id) if message.get("result").is_none() && message.get("error").is_none() => {
+                Some(error_response(id, INVALID_REQUEST, "Missing method"))
+            }
+            _ => None,
+        };
+    };
+    let id = id?;
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    Some(match dispatch(state, caller, method, params).await {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err((code, message)) => error_response(id, code, message),
+    })
+}
+
+/// Handle a raw message or batch; `None` if nothing needs answering
+async fn handle(state: &AppState, caller: &Caller, body: &[u8]) -> Option<Value> {
+    let message: Value = match serde_json::from_slice(body) {
+        Ok(message) => message,
+        Err(e) => return Some(error_response(Value::Null, PARSE_ERROR, format!("Parse error: {}", e))),
+    };
+
+    match message {
+        Value::Array(batch) => {
+            let mut replies = Vec::new();
+            for message in batch {
+                replies.extend(handle_message(state, caller, message).await);
+            }
+            (!replies.is_empty()).then_some(Value::Array(replies))
+        }
+        message => handle_message(state, caller, message).await,
+    }
+}
+
+/// Model Context Protocol endpoint (streamable HTTP transport)
+#[utoipa::path(post, path = "/mcp", tag = "mcp",
+    request_body(content = Object, description = "JSON-RPC 2.0 request, notification or batch"),
+    responses(
+        (status = 200, description = "JSON-RPC response", body = Object),
+        (status = 202, description = "Only notifications or responses were sent"),
+    ))]
+pub(crate) async fn mcp_http(State(state): State<AppState>, caller: Caller, body: Bytes) -> Response {
+    match handle(&state, &caller, &body).await {
+        Some(reply) => Json(reply).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// Serve MCP over stdin and stdout with `config` active, until stdin
+/// closes. Requests run concurrently, so a long `execute` doesn't hold up
+/// `ping`; replies are written whole, one per line, as they finish.
+pub async fn serve_stdio(state: AppState, config: Config) -> anyhow::Result<()> {
+    state.set_config(config);
+    info!("Serving MCP on stdio");
+    let (replies, mut outgoing) = mpsc::unbounded_channel::<Value>();
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(reply) = outgoing.recv().await {
+            let mut line = serde_json::to_vec(&reply).unwrap_or_default();
+            line.push(b'\n');
+            if stdout.write_all(&line).await.is_err() || stdout.flush().await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let state = state.clone();
+        let replies = replies.clone();
+        tokio::spawn(async move {
+            // The client launched us locally, so nothing is charged to a token
+            if let Some(reply) = handle(&state, &Caller::default(), line.as_bytes()).await {
+                let _ = replies.send(reply);
+            }
+        });
+    }
+
+    info!("MCP client closed stdin");
+    // Let in-flight calls finish and their replies go out
+    drop(replies);
+    let _ = writer.await;
+    Ok(())
+}
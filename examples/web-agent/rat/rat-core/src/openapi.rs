@@ -0,0 +1,165 @@
+//! OpenAPI description of the HTTP API, served at `/openapi.json` with a
+//! Swagger UI at `/swagger-ui`.
+
+use crate::state::AppState;
+use crate::{access_log, admin, error, events, exec, health, jobs, mcp, plugin, quota, repl, search, session, system, tools, version};
+use utoipa::openapi::path::{OperationBuilder, PathItemType};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rat",
+        description = "PTY sessions and command execution over HTTP and WebSocket. \
+            Endpoints documented as JSON also answer in MessagePack or CBOR when asked \
+            through `Accept`, and accept request bodies in either with a matching `Content-Type`."
+    ),
+    servers((url = "/v1")),
+    paths(
+        version::version,
+        version::capabilities,
+        health::health,
+        health::health_live,
+        health::health_ready,
+        health::stats,
+        health::metrics,
+        exec::execute_command,
+        exec::execute_command_stream,
+        jobs::create_job,
+        jobs::list_jobs,
+        jobs::get_job,
+        jobs::job_output,
+        jobs::stream_job,
+        jobs::stop_job,
+        session::create_session,
+        session::list_sessions,
+        session::stop_session,
+        session::shell_ws_handler,
+        session::new_shell_ws_handler,
+        repl::create_repl,
+        repl::list_repls,
+        repl::eval_repl,
+        repl::stop_repl,
+        mcp::mcp_http,
+        search::search,
+        tools::list_tools,
+        tools::call_tool,
+        events::events_ws_handler,
+        admin::admin_reload,
+        quota::admin_quotas,
+        access_log::export_access_log,
+        plugin::list_plugins,
+        system::env::environment,
+        system::logs::logs,
+        system::metrics::metrics_ws_handler,
+        system::network::network,
+        system::processes::list_processes,
+        system::processes::signal_process,
+        system::users::users,
+    ),
+    components(schemas(
+        error::ErrorBody,
+        error::ErrorDetail,
+        error::ErrorCode,
+        version::VersionResponse,
+        version::CapabilitiesResponse,
+        version::Framing,
+        version::Subsystems,
+        health::HealthResponse,
+        health::CheckResult,
+        health::ReadinessResponse,
+        health::TunnelStatus,
+        health::StatsResponse,
+        exec::CommandRequest,
+        exec::CommandResponse,
+        jobs::JobInfo,
+        jobs::JobLine,
+        jobs::JobStream,
+        jobs::JobOutput,
+        session::SessionCreateResponse,
+        session::SessionInfo,
+        session::SessionPage,
+        repl::Language,
+        repl::ReplCreateRequest,
+        repl::ReplCreateResponse,
+        repl::ReplInfo,
+        repl::EvalRequest,
+        repl::EvalResponse,
+        tools::ReadFileArgs,
+        tools::WriteFileArgs,
+        tools::SessionArgs,
+        tools::ToolFormat,
+        tools::ToolCallResponse,
+        quota::QuotaStatus,
+        access_log::AccessFormat,
+        search::EntryKind,
+        search::SearchHit,
+        plugin::PluginInfo,
+        plugin::ToolSpec,
+        system::logs::LogEntry,
+        system::metrics::MetricsSample,
+        system::metrics::MemorySample,
+        system::metrics::DiskSample,
+        system::metrics::NetworkSample,
+        system::network::NetworkResponse,
+        system::network::InterfaceInfo,
+        system::network::InterfaceAddress,
+        system::network::ListeningSocket,
+        system::processes::ProcessInfo,
+        system::processes::SignalRequest,
+        system::users::LoginRecord,
+        system::users::UsersResponse,
+    )),
+    tags(
+        (name = "health", description = "Probes, stats and metrics"),
+        (name = "exec", description = "One-shot command execution"),
+        (name = "jobs", description = "Detached commands with spooled output"),
+        (name = "sessions", description = "Interactive PTY sessions"),
+        (name = "repl", description = "Language REPLs with structured eval results"),
+        (name = "tools", description = "Tool definitions and calls for function-calling LLMs"),
+        (name = "mcp", description = "Model Context Protocol server for LLM agents"),
+        (name = "search", description = "Full-text search of shell transcripts and command history"),
+        (name = "admin", description = "Admin-token protected endpoints"),
+        (name = "plugins", description = "Routes contributed by registered plugins"),
+        (name = "system", description = "Structured views of the host"),
+    )
+)]
+struct ApiDoc;
+
+fn path_item_type(method: &str) -> Option<PathItemType> {
+    match method.to_ascii_uppercase().as_str() {
+        "GET" => Some(PathItemType::Get),
+        "POST" => Some(PathItemType::Post),
+        "PUT" => Some(PathItemType::Put),
+        "PATCH" => Some(PathItemType::Patch),
+        "DELETE" => Some(PathItemType::Delete),
+        _ => None,
+    }
+}
+
+/// The static API description plus one operation per registered plugin tool
+pub(crate) fn spec(state: &AppState) -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.info.version = env!("CARGO_PKG_VERSION").to_string();
+
+    for plugin in state.plugins() {
+        for tool in plugin.tools() {
+            let Some(method) = path_item_type(&tool.method) else {
+                continue;
+            };
+            let path = format!("/plugins/{}{}", plugin.name(), tool.path);
+            let operation = OperationBuilder::new()
+                .operation_id(Some(format!("{}_{}", plugin.name(), tool.name)))
+                .description(Some(tool.description))
+                .tag("plugins")
+                .build();
+            doc.paths
+                .paths
+                .entry(path)
+                .or_default()
+                .operations
+                .insert(method, operation);
+        }
+    }
+    doc
+}
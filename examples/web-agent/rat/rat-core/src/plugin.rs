@@ -0,0 +1,97 @@
+//! Extension point for third-party routes and tools.
+//!
+//! A plugin contributes an axum router, mounted at `/plugins/<name>`, and a
+//! list of tool descriptions that show up in `GET /plugins`, `GET /tools`
+//! and the MCP tool list.
+//!
+//! ```no_run
+//! use axum::{routing::get, Router};
+//! use rat_core::plugin::{Plugin, ToolSpec};
+//!
+//! struct Uptime;
+//!
+//! impl Plugin for Uptime {
+//!     fn name(&self) -> &str {
+//!         "uptime"
+//!     }
+//!
+//!     fn router(&self) -> Router {
+//!         Router::new().route("/", get(|| async { "up" }))
+//!     }
+//!
+//!     fn tools(&self) -> Vec<ToolSpec> {
+//!         vec![ToolSpec {
+//!             name: "uptime".to_string(),
+//!             description: "Report whether the host is up".to_string(),
+//!             method: "GET".to_string(),
+//!             path: "/".to_string(),
+//!             input_schema: serde_json::json!({"type": "object", "properties": {}}),
+//!         }]
+//!     }
+//! }
+//!
+//! let state = rat_core::AppState::new().with_plugin(Uptime);
+//! ```
+
+use crate::state::AppState;
+use crate::encoding::{Encoded, Format};
+use axum::{extract::State, Router};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Description of one operation a plugin exposes, relative to its mount point
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub method: String,
+    /// Path under `/plugins/<plugin name>`
+    pub path: String,
+    /// JSON Schema for the request body or query parameters
+    #[schema(value_type = Object)]
+    pub input_schema: serde_json::Value,
+}
+
+pub trait Plugin: Send + Sync + 'static {
+    /// URL-safe name: lowercase ASCII letters, digits, `-` and `_`
+    fn name(&self) -> &str;
+
+    /// Routes, mounted under `/plugins/<name>`
+    fn router(&self) -> Router;
+
+    fn tools(&self) -> Vec<ToolSpec> {
+        Vec::new()
+    }
+}
+
+pub(crate) fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct PluginInfo {
+    name: String,
+    base_path: String,
+    tools: Vec<ToolSpec>,
+}
+
+/// List registered plugins and their tools
+#[utoipa::path(get, path = "/plugins", tag = "plugins",
+    responses((status = 200, body = [PluginInfo])))]
+pub(crate) async fn list_plugins(State(state): State<AppState>, format: Format) -> Encoded<Vec<PluginInfo>> {
+    Encoded(
+        format,
+        state
+            .plugins()
+            .iter()
+            .map(|plugin| PluginInfo {
+                name: plugin.name().to_string(),
+                base_path: format!("/plugins/{}", plugin.name()),
+                tools: plugin.tools(),
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,31 @@
+//! Named environment profiles from `[profiles.<name>]`. A session or command
+//! that asks for one with `profile` gets its variables, working directory,
+//! shell and rc script, so clients don't resend them on every call.
+//!
+//! Profiles are read from the live config; a reload applies to sessions and
+//! commands started afterwards.
+
+use crate::config::ProfileConfig;
+use crate::error::{ApiError, ErrorCode};
+use crate::state::AppState;
+use std::path::Path;
+
+/// The profile a request named, or `None` if it didn't name one
+pub(crate) fn find(state: &AppState, name: Option<&str>) -> Result<Option<ProfileConfig>, ApiError> {
+    let Some(name) = name else {
+        return Ok(None);
+    };
+    state
+        .config()
+        .profiles
+        .get(name)
+        .cloned()
+        .map(Some)
+        .ok_or_else(|| ApiError::new(ErrorCode::ProfileNotFound, format!("No profile named {:?}", name)))
+}
+
+/// Whether `shell` is bash, which takes an rc script with `--rcfile`
+/// rather than through `$ENV`
+pub(crate) fn is_bash(shell: &str) -> bool {
+    Path::new(shell).file_name().is_some_and(|name| name == "bash")
+}
@@ -0,0 +1,173 @@
+//! Blocking PTY I/O bridged to async code.
+//!
+//! The PTY master only offers blocking `Read`/`Write`, so each attached
+//! session runs one reader and one writer on tokio's blocking pool. Both
+//! are connected to the WebSocket tasks through bounded channels: when the
+//! client falls behind, the reader stops draining the PTY and the shell
+//! blocks on its own output instead of buffering without limit. The reader
+//! polls with a timeout so it notices cancellation promptly on detach.
+//!
+//! Output is read into one reusable `BytesMut` and handed on as `Bytes`
+//! slices of it; once the WebSocket task has sent a chunk the allocation
+//! is reclaimed for the next read. Chunks that queue up while a frame is
+//! being sent are merged into the next frame (see `next_frame`), so bulk
+//! output costs one copy into each WebSocket frame rather than one
+//! allocation per read.
+
+use bytes::{Bytes, BytesMut};
+use std::io::{Read, Write};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// Chunks in flight in each direction before the producer waits
+pub(crate) const CHANNEL_CAPACITY: usize = 32;
+
+const POLL_INTERVAL_MS: libc::c_int = 200;
+
+/// How long `PtyPumps::stop` waits for the writer to hand itself back
+const WRITER_WAIT: Duration = Duration::from_secs(1);
+
+/// Wait up to `POLL_INTERVAL_MS` for `fd` to become readable (or hung up)
+fn wait_readable(fd: RawFd) -> std::io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: pollfd is a valid, initialised array of length 1
+    let ready = unsafe { libc::poll(&mut pollfd, 1, POLL_INTERVAL_MS) };
+    match ready {
+        -1 => {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+/// Handles to a session's PTY pumps; `stop` ends them
+pub(crate) struct PtyPumps {
+    cancel: Arc<AtomicBool>,
+    reader: JoinHandle<()>,
+    /// Returns the writer, for the next attachment
+    writer: JoinHandle<Box<dyn Write + Send>>,
+}
+
+impl PtyPumps {
+    /// Start pumping PTY output into the returned receiver and the
+    /// returned sender's input into the PTY. `fd` is the master's
+    /// descriptor, used to poll before blocking in `read`.
+    pub(crate) fn start(
+        mut reader: Box<dyn Read + Send>,
+        mut writer: Box<dyn Write + Send>,
+        fd: Option<RawFd>,
+        read_buffer_bytes: usize,
+    ) -> (Self, mpsc::Receiver<Bytes>, mpsc::Sender<Bytes>) {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (output_tx, output_rx) = mpsc::channel::<Bytes>(CHANNEL_CAPACITY);
+        let (input_tx, mut input_rx) = mpsc::channel::<Bytes>(CHANNEL_CAPACITY);
+        let read_size = read_buffer_bytes.max(1);
+
+        let reader_cancel = cancel.clone();
+        let reader = tokio::task::spawn_blocking(move || {
+            let mut buf = BytesMut::with_capacity(read_size);
+            while !reader_cancel.load(Ordering::Relaxed) {
+                if let Some(fd) = fd {
+                    match wait_readable(fd) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(_) => break,
+                    }
+                }
+                // Reuses the previous allocation once every chunk split off
+                // from it has been dropped
+                buf.reserve(read_size);
+                buf.resize(read_size, 0);
+                match reader.read(&mut buf) {
+                    Ok(n) if n > 0 => {
+                        buf.truncate(n);
+                        if output_tx.blocking_send(buf.split().freeze()).is_err() {
+                            break;
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        });
+
+        // Ends when every input sender is dropped
+        let writer = tokio::task::spawn_blocking(move || {
+            while let Some(data) = input_rx.blocking_recv() {
+                if writer.write_all(&data).is_err() || writer.flush().is_err() {
+                    break;
+                }
+            }
+            writer
+        });
+
+        (PtyPumps { cancel, reader, writer }, output_rx, input_tx)
+    }
+
+    /// Cancel both pumps and pass the writer to `release` once they have
+    /// returned, the reader first so two attachments never read the PTY at
+    /// once. The writer ends when its input sender is dropped, but may be
+    /// blocked on a shell that isn't reading its input, so this waits for
+    /// it at most `WRITER_WAIT`. The pumps are wound down on a task of their
+    /// own: the writer is released however long it takes, even if the
+    /// caller stops waiting.
+    pub(crate) async fn stop(self, release: impl FnOnce(Box<dyn Write + Send>) + Send + 'static) {
+        self.cancel.store(true, Ordering::Relaxed);
+        let PtyPumps { reader, writer, .. } = self;
+        let (released_tx, released) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = reader.await;
+            if let Ok(writer) = writer.await {
+                release(writer);
+            }
+            let _ = released_tx.send(());
+        });
+        let _ = tokio::time::timeout(WRITER_WAIT, released).await;
+    }
+}
+
+/// Build one WebSocket frame from `first` plus whatever output follows it:
+/// chunks already queued are always merged, and with a non-zero `delay`
+/// the frame waits that long for more. Chunks stop being added once the
+/// frame holds `max_bytes`.
+pub(crate) async fn next_frame(
+    first: Bytes,
+    rx: &mut mpsc::Receiver<Bytes>,
+    max_bytes: usize,
+    delay: Duration,
+) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(first.len());
+    frame.extend_from_slice(&first);
+    drop(first);
+
+    let deadline = Instant::now() + delay;
+    while frame.len() < max_bytes {
+        let chunk = match rx.try_recv() {
+            Ok(chunk) => chunk,
+            Err(TryRecvError::Empty) if !delay.is_zero() => {
+                match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(chunk)) => chunk,
+                    _ => break,
+                }
+            }
+            Err(_) => break,
+        };
+        frame.extend_from_slice(&chunk);
+    }
+    frame
+}
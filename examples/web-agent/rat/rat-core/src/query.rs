@@ -0,0 +1,24 @@
+//! Query parameters shared by list endpoints.
+
+use serde::Deserialize;
+use std::cmp::Ordering;
+use utoipa::ToSchema;
+
+/// Direction of a list's `sort`
+#[derive(Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    /// `ordering`, the ascending comparison, in this direction
+    pub(crate) fn apply(self, ordering: Ordering) -> Ordering {
+        match self {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    }
+}
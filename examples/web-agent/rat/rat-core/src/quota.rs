@@ -0,0 +1,173 @@
+//! Per-token quotas, so one runaway agent identity can't consume the host.
+//!
+//! Work done with one of the `[[auth.tokens]]` is charged to that token:
+//! the sessions it created, the commands it started in the last hour and
+//! the command and shell output it produced today. Anonymous and admin
+//! requests aren't charged. Usage is kept in memory and starts from zero
+//! on restart; limits are read from the live config, so reloads apply.
+
+use crate::auth::{require_admin, Caller};
+use crate::config::TokenConfig;
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorCode};
+use crate::state::{unix_millis, AppState};
+use axum::{extract::State, http::HeaderMap};
+use serde::Serialize;
+use std::collections::VecDeque;
+use tracing::warn;
+use utoipa::ToSchema;
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+const DAY_MS: u64 = 24 * HOUR_MS;
+
+/// What one token has used
+#[derive(Default)]
+pub(crate) struct Usage {
+    /// Start times of the jobs in the last hour, oldest first
+    job_starts: VecDeque<u64>,
+    /// UTC day, counted from the epoch, that `output_bytes` belongs to
+    output_day: u64,
+    output_bytes: u64,
+}
+
+impl Usage {
+    fn jobs_last_hour(&mut self, now: u64) -> usize {
+        while self.job_starts.front().is_some_and(|&start| now.saturating_sub(start) >= HOUR_MS) {
+            self.job_starts.pop_front();
+        }
+        self.job_starts.len()
+    }
+
+    fn output_today(&mut self, now: u64) -> u64 {
+        if self.output_day != now / DAY_MS {
+            self.output_day = now / DAY_MS;
+            self.output_bytes = 0;
+        }
+        self.output_bytes
+    }
+}
+
+fn limits(state: &AppState, name: &str) -> Option<TokenConfig> {
+    state.config().auth.tokens.iter().find(|entry| entry.name == name).cloned()
+}
+
+fn sessions_owned(state: &AppState, name: &str) -> usize {
+    state
+        .shared
+        .sessions
+        .iter()
+        .filter(|entry| entry.value().lock().unwrap().owner.as_deref() == Some(name))
+        .count()
+}
+
+fn exceeded(name: &str, what: &str) -> ApiError {
+    warn!("Token {} is over its {} quota", name, what);
+    ApiError::new(ErrorCode::QuotaExceeded, format!("Quota exceeded: {}", what))
+        .with_details(serde_json::json!({"quota": what}))
+}
+
+/// Refuse a new session if the caller already holds as many as allowed
+pub(crate) fn check_session(state: &AppState, caller: &Caller) -> Result<(), ApiError> {
+    let Some(name) = caller.token_name() else { return Ok(()) };
+    let Some(limits) = limits(state, name) else { return Ok(()) };
+    match limits.max_sessions {
+        Some(max) if sessions_owned(state, name) >= max => Err(exceeded(name, "max_sessions")),
+        _ => Ok(()),
+    }
+}
+
+/// Count a new job against the caller, refusing it if the hourly job quota
+/// or the daily output quota is used up
+pub(crate) fn start_job(state: &AppState, caller: &Caller) -> Result<(), ApiError> {
+    let Some(name) = caller.token_name() else { return Ok(()) };
+    let Some(limits) = limits(state, name) else { return Ok(()) };
+    let now = unix_millis();
+    let mut usage = state.shared.quota_usage.entry(name.to_string()).or_default();
+
+    if let Some(max) = limits.max_output_bytes_per_day {
+        if usage.output_today(now) >= max {
+            return Err(exceeded(name, "max_output_bytes_per_day"));
+        }
+    }
+    if let Some(max) = limits.max_jobs_per_hour {
+        if usage.jobs_last_hour(now) >= max {
+            return Err(exceeded(name, "max_jobs_per_hour"));
+        }
+    }
+    usage.job_starts.push_back(now);
+    Ok(())
+}
+
+/// Take back the job `start_job` counted when the command then failed to
+/// spawn, so a typo doesn't use up the hourly quota
+pub(crate) fn refund_job(state: &AppState, caller: &Caller) {
+    let Some(name) = caller.token_name() else { return };
+    if let Some(mut usage) = state.shared.quota_usage.get_mut(name) {
+        usage.job_starts.pop_back();
+    }
+}
+
+/// Charge output to a token. Returns false once the token is over its
+/// daily output quota, after which the stream should be cut off.
+pub(crate) fn charge_output(state: &AppState, token_name: Option<&str>, bytes: usize) -> bool {
+    let Some(name) = token_name else { return true };
+    let Some(limits) = limits(state, name) else { return true };
+    let now = unix_millis();
+    let mut usage = state.shared.quota_usage.entry(name.to_string()).or_default();
+    usage.output_today(now);
+    usage.output_bytes += bytes as u64;
+
+    match limits.max_output_bytes_per_day {
+        Some(max) if usage.output_bytes > max => {
+            warn!("Token {} is over its max_output_bytes_per_day quota", name);
+            false
+        }
+        _ => true,
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct QuotaStatus {
+    name: String,
+    sessions: usize,
+    max_sessions: Option<usize>,
+    jobs_last_hour: usize,
+    max_jobs_per_hour: Option<usize>,
+    output_bytes_today: u64,
+    max_output_bytes_per_day: Option<u64>,
+}
+
+/// Usage and quotas of every configured API token
+#[utoipa::path(get, path = "/admin/quotas", tag = "admin",
+    responses(
+        (status = 200, body = [QuotaStatus]),
+        (status = 401, description = "Invalid admin token"),
+        (status = 403, description = "Admin API disabled"),
+    ))]
+pub(crate) async fn admin_quotas(
+    State(state): State<AppState>,
+    format: Format,
+    headers: HeaderMap,
+) -> Result<Encoded<Vec<QuotaStatus>>, ApiError> {
+    require_admin(&state, &headers, None)?;
+    let now = unix_millis();
+    let statuses = state
+        .config()
+        .auth
+        .tokens
+        .iter()
+        .map(|entry| {
+            let mut usage = state.shared.quota_usage.entry(entry.name.clone()).or_default();
+            QuotaStatus {
+                name: entry.name.clone(),
+                sessions: sessions_owned(&state, &entry.name),
+                max_sessions: entry.max_sessions,
+                jobs_last_hour: usage.jobs_last_hour(now),
+                max_jobs_per_hour: entry.max_jobs_per_hour,
+                output_bytes_today: usage.output_today(now),
+                max_output_bytes_per_day: entry.max_output_bytes_per_day,
+            }
+        })
+        .collect();
+    Ok(Encoded(format, statuses))
+}
@@ -0,0 +1,154 @@
+//! Recording of shell socket traffic, for replaying field reports in tests.
+//!
+//! With `shell.record_dir` set, every attachment writes
+//! `<record_dir>/<session_id>-<unix_ms>.jsonl`: one line per WebSocket
+//! frame in either direction, timed from the start of the attachment.
+//! Output is recorded before compression, so a recording replays the same
+//! whether or not the client negotiated `rat.deflate`.
+//!
+//! ```json
+//! {"at_ms":0,"direction":"output","kind":"binary","data":"JCA="}
+//! {"at_ms":812,"direction":"input","kind":"text","data":"eyJ0eXBlIjoicmVzaXplIn0="}
+//! ```
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::warn;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Client → server
+    Input,
+    /// Server → client
+    Output,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameKind {
+    Binary,
+    Text,
+}
+
+/// One recorded WebSocket frame
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// Milliseconds since the attachment started
+    pub at_ms: u64,
+    pub direction: Direction,
+    pub kind: FrameKind,
+    /// Frame payload, base64 on disk
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub data: Vec<u8>,
+}
+
+impl RecordedFrame {
+    /// Whether this is a JSON control message, such as a resize, rather
+    /// than terminal input
+    pub fn is_control(&self) -> bool {
+        self.kind == FrameKind::Text
+            && serde_json::from_slice::<serde_json::Value>(&self.data).map_or(false, |v| v.get("type").is_some())
+    }
+}
+
+fn to_base64<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(data))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    STANDARD.decode(text).map_err(serde::de::Error::custom)
+}
+
+/// A recorded attachment, loaded back for replay
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> anyhow::Result<Recording> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read recording {}: {}", path.display(), e))?;
+        Recording::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Recording> {
+        let frames = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| anyhow::anyhow!("Line {}: {}", i + 1, e))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Recording { frames })
+    }
+
+    /// Frames sent in one direction, in order
+    pub fn frames(&self, direction: Direction) -> impl Iterator<Item = &RecordedFrame> {
+        self.frames.iter().filter(move |frame| frame.direction == direction)
+    }
+
+    /// Everything sent in one direction, concatenated
+    pub fn bytes(&self, direction: Direction) -> Vec<u8> {
+        self.frames(direction).flat_map(|frame| frame.data.iter().copied()).collect()
+    }
+}
+
+/// Appends an attachment's frames to its recording file. Writes happen on
+/// a background task so recording never holds up the socket.
+#[derive(Clone)]
+pub(crate) struct SessionRecorder {
+    start: Instant,
+    frames: mpsc::UnboundedSender<RecordedFrame>,
+}
+
+impl SessionRecorder {
+    /// Start recording into `dir`; `None` if no directory is configured
+    pub(crate) fn start(dir: Option<&Path>, session_id: &str) -> Option<SessionRecorder> {
+        let path = dir?.join(format!("{}-{}.jsonl", session_id, crate::state::unix_millis()));
+        let (frames, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_frames(path, rx));
+        Some(SessionRecorder {
+            start: Instant::now(),
+            frames,
+        })
+    }
+
+    pub(crate) fn record(&self, direction: Direction, kind: FrameKind, data: &[u8]) {
+        let _ = self.frames.send(RecordedFrame {
+            at_ms: self.start.elapsed().as_millis() as u64,
+            direction,
+            kind,
+            data: data.to_vec(),
+        });
+    }
+}
+
+async fn write_frames(path: PathBuf, mut frames: mpsc::UnboundedReceiver<RecordedFrame>) {
+    if let Some(dir) = path.parent() {
+        let _ = tokio::fs::create_dir_all(dir).await;
+    }
+    let mut file = match tokio::fs::File::create(&path).await {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Not recording to {}: {}", path.display(), e);
+            return;
+        }
+    };
+    while let Some(frame) = frames.recv().await {
+        let Ok(mut line) = serde_json::to_vec(&frame) else { continue };
+        line.push(b'\n');
+        // Flushed per frame so a recording survives the agent crashing
+        if let Err(e) = async { file.write_all(&line).await?; file.flush().await }.await {
+            warn!("Recording to {} stopped: {}", path.display(), e);
+            return;
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// Reads {"code": ...} lines on stdin and answers each with one line of
+// {"result", "stdout", "stderr", "error"} JSON on the real stdout.
+const readline = require("readline");
+const util = require("util");
+const vm = require("vm");
+const { Console } = require("console");
+const { Writable } = require("stream");
+
+let stdout = "";
+let stderr = "";
+const sink = (append) =>
+  new Writable({
+    write(chunk, _encoding, done) {
+      append(chunk.toString());
+      done();
+    },
+  });
+
+const context = vm.createContext({
+  require,
+  process,
+  Buffer,
+  setTimeout,
+  clearTimeout,
+  setInterval,
+  clearInterval,
+  console: new Console(sink((s) => (stdout += s)), sink((s) => (stderr += s))),
+});
+
+async function evaluate(code) {
+  stdout = "";
+  stderr = "";
+  let result = null;
+  let error = null;
+  try {
+    let value = vm.runInContext(code, context, { filename: "<repl>" });
+    // Promises from the context fail instanceof checks here
+    if (value && typeof value.then === "function") {
+      value = await value;
+    }
+    if (value !== undefined) {
+      result = util.inspect(value);
+    }
+  } catch (e) {
+    error = e && e.stack ? e.stack : String(e);
+  }
+  process.stdout.write(JSON.stringify({ result, stdout, stderr, error }) + "\n");
+}
+
+let queue = Promise.resolve();
+readline.createInterface({ input: process.stdin }).on("line", (line) => {
+  queue = queue.then(() => evaluate(JSON.parse(line).code));
+});
@@ -0,0 +1,38 @@
+# Reads {"code": ...} lines on stdin and answers each with one line of
+# {"result", "stdout", "stderr", "error"} JSON on the real stdout.
+import ast
+import contextlib
+import io
+import json
+import sys
+import traceback
+
+out = sys.stdout
+namespace = {"__name__": "__main__"}
+
+while True:
+    line = sys.stdin.readline()
+    if not line:
+        break
+    code = json.loads(line)["code"]
+    stdout, stderr = io.StringIO(), io.StringIO()
+    result = error = None
+    with contextlib.redirect_stdout(stdout), contextlib.redirect_stderr(stderr):
+        try:
+            tree = ast.parse(code, "<repl>", "exec")
+            # A trailing expression is the eval's result, as in the REPL
+            last = tree.body.pop() if tree.body and isinstance(tree.body[-1], ast.Expr) else None
+            exec(compile(tree, "<repl>", "exec"), namespace)
+            if last is not None:
+                value = eval(compile(ast.Expression(last.value), "<repl>", "eval"), namespace)
+                if value is not None:
+                    result = repr(value)
+        except BaseException:
+            error = traceback.format_exc()
+    out.write(json.dumps({
+        "result": result,
+        "stdout": stdout.getvalue(),
+        "stderr": stderr.getvalue(),
+        "error": error,
+    }) + "\n")
+    out.flush()
@@ -0,0 +1,28 @@
+# Reads {"code": ...} lines on stdin and answers each with one line of
+# {"result", "stdout", "stderr", "error"} JSON on the real stdout.
+require "json"
+require "stringio"
+
+out = $stdout
+err = $stderr
+# Locals defined by one eval stay visible to the next through this binding
+scope = binding
+
+while (line = $stdin.gets)
+  code = JSON.parse(line)["code"]
+  stdout = StringIO.new
+  stderr = StringIO.new
+  result = error = nil
+  begin
+    $stdout = stdout
+    $stderr = stderr
+    result = scope.eval(code, "<repl>").inspect
+  rescue Exception => e
+    error = "#{e.class}: #{e.message}\n#{Array(e.backtrace).join("\n")}"
+  ensure
+    $stdout = out
+    $stderr = err
+  end
+  out.puts(JSON.generate({ result: result, stdout: stdout.string, stderr: stderr.string, error: error }))
+  out.flush
+end
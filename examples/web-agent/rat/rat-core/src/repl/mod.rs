@@ -0,0 +1,296 @@
+//! REPL sessions: a long-lived python3, node or ruby process that keeps its
+//! state between evals, like a PTY session, but is driven over JSON lines
+//! so every eval returns its value, captured output and error separately.
+//!
+//! Each interpreter runs a small driver script (`driver.*` next to this
+//! file) that reads `{"code": ...}` requests on stdin and answers each
+//! with one JSON line on stdout. Evals on one REPL run one at a time.
+
+use crate::encoding::{Decoded, Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::events::EventKind;
+use crate::state::{unix_millis, AppState};
+use axum::extract::{Path, State};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const PYTHON_DRIVER: &str = include_str!("driver.py");
+const NODE_DRIVER: &str = include_str!("driver.js");
+const RUBY_DRIVER: &str = include_str!("driver.rb");
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Language {
+    Python3,
+    Node,
+    /// Also accepted as `irb`
+    #[serde(alias = "irb")]
+    Ruby,
+}
+
+impl Language {
+    fn name(self) -> &'static str {
+        match self {
+            Language::Python3 => "python3",
+            Language::Node => "node",
+            Language::Ruby => "ruby",
+        }
+    }
+
+    /// Interpreter, its flag for running a script given inline, and the driver
+    fn command(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Language::Python3 => ("python3", "-c", PYTHON_DRIVER),
+            Language::Node => ("node", "-e", NODE_DRIVER),
+            Language::Ruby => ("ruby", "-e", RUBY_DRIVER),
+        }
+    }
+}
+
+/// A running REPL. The metadata can be read while an eval holds `io`.
+pub(crate) struct ReplSession {
+    id: String,
+    language: Language,
+    created_at_ms: u64,
+    pid: Option<u32>,
+    evals: AtomicU64,
+    io: tokio::sync::Mutex<ReplIo>,
+}
+
+struct ReplIo {
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    // Killed when the session is dropped
+    _child: Child,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ReplCreateRequest {
+    language: Language,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ReplCreateResponse {
+    repl_id: String,
+    language: Language,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ReplInfo {
+    id: String,
+    language: Language,
+    pid: Option<u32>,
+    created_at_ms: u64,
+    evals: u64,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub(crate) struct EvalRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub(crate) struct EvalResponse {
+    /// Representation of the value of the last expression, if it had one
+    result: Option<String>,
+    /// Output printed during the eval
+    stdout: String,
+    stderr: String,
+    /// Exception and traceback, if the code raised
+    error: Option<String>,
+}
+
+fn spawn_repl(state: &AppState, language: Language) -> Result<ReplSession, ApiError> {
+    if let Some(max) = state.config().limits.max_sessions {
+        if state.shared.repls.len() >= max {
+            warn!("Refusing new REPL: limit of {} reached", max);
+            return Err(ApiError::new(ErrorCode::ReplLimitReached, format!("REPL limit of {} reached", max)));
+        }
+    }
+
+    let (program, flag, driver) = language.command();
+    let mut child = Command::new(program)
+        .arg(flag)
+        .arg(driver)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| ApiError::new(ErrorCode::SpawnFailed, format!("Failed to start {}: {}", program, e)))?;
+
+    let stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap()).lines();
+    Ok(ReplSession {
+        id: Uuid::new_v4().to_string(),
+        language,
+        created_at_ms: unix_millis(),
+        pid: child.id(),
+        evals: AtomicU64::new(0),
+        io: tokio::sync::Mutex::new(ReplIo { stdin, stdout, _child: child }),
+    })
+}
+
+/// Drop a REPL and kill its interpreter, even mid-eval; false if there was
+/// no such REPL
+fn remove_repl(state: &AppState, repl_id: &str) -> bool {
+    if let Some((_, repl)) = state.shared.repls.remove(repl_id) {
+        if let Some(pid) = repl.pid {
+            // SAFETY: kill(2) has no memory-safety preconditions
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        state.emit(EventKind::ReplStopped { repl_id: repl_id.to_string() });
+        true
+    } else {
+        false
+    }
+}
+
+/// Send one eval to the driver and read its answer
+async fn eval_in(repl: &ReplSession, code: String) -> Result<EvalResponse, String> {
+    let mut io = repl.io.lock().await;
+    let mut request = serde_json::to_vec(&EvalRequest { code }).map_err(|e| e.to_string())?;
+    request.push(b'\n');
+    io.stdin
+        .write_all(&request)
+        .await
+        .map_err(|e| format!("REPL process is gone: {}", e))?;
+    io.stdin.flush().await.map_err(|e| format!("REPL process is gone: {}", e))?;
+
+    let line = io
+        .stdout
+        .next_line()
+        .await
+        .map_err(|e| format!("Failed to read from REPL: {}", e))?
+        .ok_or_else(|| "REPL process exited".to_string())?;
+    repl.evals.fetch_add(1, Ordering::Relaxed);
+    serde_json::from_str(&line).map_err(|e| format!("Malformed REPL response: {}", e))
+}
+
+/// Start a REPL
+#[utoipa::path(post, path = "/repl/create", tag = "repl",
+    request_body = ReplCreateRequest,
+    responses(
+        (status = 200, body = ReplCreateResponse),
+        (status = 429, description = "REPL limit reached", body = ErrorBody),
+        (status = 500, description = "Interpreter could not be started", body = ErrorBody),
+    ))]
+pub(crate) async fn create_repl(
+    State(state): State<AppState>,
+    format: Format,
+    Decoded(request): Decoded<ReplCreateRequest>,
+) -> Result<Encoded<ReplCreateResponse>, ApiError> {
+    let repl = spawn_repl(&state, request.language)?;
+    let repl_id = repl.id.clone();
+    info!("Started {} REPL {}", request.language.name(), repl_id);
+
+    state
+        .shared
+        .repls
+        .insert(repl_id.clone(), Arc::new(repl));
+    state.emit(EventKind::ReplCreated {
+        repl_id: repl_id.clone(),
+        language: request.language.name().to_string(),
+    });
+    Ok(Encoded(format, ReplCreateResponse {
+        repl_id,
+        language: request.language,
+    }))
+}
+
+/// List all REPLs
+#[utoipa::path(get, path = "/repls", tag = "repl",
+    responses((status = 200, body = [ReplInfo])))]
+pub(crate) async fn list_repls(State(state): State<AppState>, format: Format) -> Encoded<Vec<ReplInfo>> {
+    let list = state
+        .shared
+        .repls
+        .iter()
+        .map(|entry| {
+            let repl = entry.value();
+            ReplInfo {
+                id: repl.id.clone(),
+                language: repl.language,
+                pid: repl.pid,
+                created_at_ms: repl.created_at_ms,
+                evals: repl.evals.load(Ordering::Relaxed),
+            }
+        })
+        .collect();
+    Encoded(format, list)
+}
+
+/// Evaluate code in a REPL
+#[utoipa::path(post, path = "/repl/{repl_id}/eval", tag = "repl",
+    params(("repl_id" = String, Path)),
+    request_body = EvalRequest,
+    responses(
+        (status = 200, description = "Evaluated; exceptions are reported in `error`", body = EvalResponse),
+        (status = 404, description = "REPL not found", body = ErrorBody),
+        (status = 500, description = "Interpreter died; the REPL is removed", body = ErrorBody),
+        (status = 504, description = "Eval timed out; the REPL is removed", body = ErrorBody),
+    ))]
+pub(crate) async fn eval_repl(
+    State(state): State<AppState>,
+    format: Format,
+    Path(repl_id): Path<String>,
+    Decoded(request): Decoded<EvalRequest>,
+) -> Result<Encoded<EvalResponse>, ApiError> {
+    let repl = state
+        .shared
+        .repls
+        .get(&repl_id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| ApiError::new(ErrorCode::ReplNotFound, "REPL not found"))?;
+    let timeout = Duration::from_secs(state.config().repl.eval_timeout_secs);
+
+    match tokio::time::timeout(timeout, eval_in(&repl, request.code)).await {
+        Ok(Ok(response)) => Ok(Encoded(format, response)),
+        Ok(Err(e)) => {
+            warn!("REPL {} failed: {}", repl_id, e);
+            remove_repl(&state, &repl_id);
+            Err(ApiError::new(ErrorCode::ReplFailed, e))
+        }
+        Err(_) => {
+            // The interpreter is still busy with this eval, so it can't
+            // answer the next one; stop it rather than leave it wedged
+            warn!("REPL {} eval timed out after {:?}, stopping it", repl_id, timeout);
+            remove_repl(&state, &repl_id);
+            Err(ApiError::new(
+                ErrorCode::EvalTimeout,
+                format!("Eval timed out after {}s; the REPL was stopped", timeout.as_secs()),
+            ))
+        }
+    }
+}
+
+/// Stop a REPL
+#[utoipa::path(post, path = "/repl/{repl_id}/stop", tag = "repl",
+    params(("repl_id" = String, Path)),
+    responses(
+        (status = 200, description = "REPL stopped"),
+        (status = 404, description = "REPL not found", body = ErrorBody),
+    ))]
+pub(crate) async fn stop_repl(
+    State(state): State<AppState>,
+    format: Format,
+    Path(repl_id): Path<String>,
+) -> Result<Encoded<serde_json::Value>, ApiError> {
+    info!("Stopping REPL {}", repl_id);
+
+    if remove_repl(&state, &repl_id) {
+        Ok(Encoded(format, serde_json::json!({"status": "stopped"})))
+    } else {
+        Err(ApiError::new(ErrorCode::ReplNotFound, "REPL not found"))
+    }
+}
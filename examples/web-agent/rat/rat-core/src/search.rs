@@ -0,0 +1,382 @@
+//! Full-text search over shell transcripts and command history.
+//!
+//! With `search.db_path` set, every command run through the API and the
+//! typed input and printed output of every shell attachment are indexed in
+//! a SQLite FTS5 table, so `GET /search?q=` can answer "which session ran
+//! that migration?" long after the session is gone. Transcripts are
+//! indexed as plain text, escape sequences stripped: input a line at a
+//! time as it is entered, output in chunks of a few KiB.
+//!
+//! Writes go through a background thread that batches them, so indexing
+//! never holds up a socket. Entries older than `search.retention_days` are
+//! deleted hourly. The index is opened on first use and the section only
+//! applies after a restart.
+
+use crate::auth::Caller;
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::exec::CommandRequest;
+use crate::state::{unix_millis, AppState};
+use axum::extract::{Query, State};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+/// Output is indexed once this much plain text has built up
+const OUTPUT_CHUNK_BYTES: usize = 4096;
+const DEFAULT_HITS: usize = 50;
+const MAX_HITS: usize = 500;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EntryKind {
+    /// A command run through `/execute`, `/jobs` or a tool call
+    Command,
+    /// A line typed into a shell session
+    Input,
+    /// Shell session output
+    Output,
+}
+
+impl EntryKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::Command => "command",
+            EntryKind::Input => "input",
+            EntryKind::Output => "output",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "command" => Some(EntryKind::Command),
+            "input" => Some(EntryKind::Input),
+            "output" => Some(EntryKind::Output),
+            _ => None,
+        }
+    }
+}
+
+struct Entry {
+    kind: EntryKind,
+    session_id: Option<String>,
+    owner: Option<String>,
+    at_ms: u64,
+    text: String,
+}
+
+/// The open index: where it lives, for queries, and the writer's queue
+pub(crate) struct SearchIndex {
+    path: PathBuf,
+    entries: mpsc::Sender<Entry>,
+}
+
+impl SearchIndex {
+    fn open(path: &Path, retention_days: u64) -> anyhow::Result<SearchIndex> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        // Lets queries read while the writer writes
+        conn.query_row("PRAGMA journal_mode=WAL", [], |_| Ok(()))?;
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS entries
+                USING fts5(text, kind UNINDEXED, session_id UNINDEXED, owner UNINDEXED, at_ms UNINDEXED)",
+        )?;
+        let (entries, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("search-index".to_string())
+            .spawn(move || write_entries(conn, rx, retention_days))?;
+        Ok(SearchIndex {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    fn add(&self, kind: EntryKind, session_id: Option<&str>, owner: Option<&str>, text: String) {
+        let _ = self.entries.send(Entry {
+            kind,
+            session_id: session_id.map(str::to_string),
+            owner: owner.map(str::to_string),
+            at_ms: unix_millis(),
+            text,
+        });
+    }
+
+    fn query(&self, query: &SearchQuery, owner: Option<&str>) -> Result<Vec<SearchHit>, ApiError> {
+        let internal = |e: rusqlite::Error| ApiError::new(ErrorCode::Internal, format!("Search failed: {}", e));
+        let conn = Connection::open(&self.path).map_err(internal)?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(internal)?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT kind, session_id, at_ms, snippet(entries, 0, '**', '**', '…', 16) FROM entries
+                 WHERE entries MATCH ?1
+                   AND (?2 IS NULL OR kind = ?2)
+                   AND (?3 IS NULL OR session_id = ?3)
+                   AND (?4 IS NULL OR owner = ?4)
+                 ORDER BY rank LIMIT ?5",
+            )
+            .map_err(internal)?;
+        let limit = query.limit.unwrap_or(DEFAULT_HITS).min(MAX_HITS);
+        let rows = stmt
+            .query_map(
+                params![
+                    match_expression(&query.q),
+                    query.kind.map(EntryKind::as_str),
+                    query.session_id,
+                    owner,
+                    limit as i64
+                ],
+                |row| {
+                    let kind: String = row.get(0)?;
+                    let at_ms: i64 = row.get(2)?;
+                    Ok(SearchHit {
+                        kind: EntryKind::parse(&kind).unwrap_or(EntryKind::Output),
+                        session_id: row.get(1)?,
+                        at_ms: at_ms as u64,
+                        snippet: row.get(3)?,
+                    })
+                },
+            )
+            .map_err(internal)?;
+        rows.collect::<Result<_, _>>().map_err(internal)
+    }
+}
+
+/// Each word of `q` as a quoted FTS5 string, so punctuation is searched
+/// for rather than parsed; all of them must match
+fn match_expression(q: &str) -> String {
+    q.split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_entries(mut conn: Connection, entries: mpsc::Receiver<Entry>, retention_days: u64) {
+    let mut purged_at: Option<Instant> = None;
+    loop {
+        let first = match entries.recv_timeout(PURGE_INTERVAL) {
+            Ok(entry) => Some(entry),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        let batch: Vec<Entry> = first.into_iter().chain(entries.try_iter()).collect();
+        if !batch.is_empty() {
+            if let Err(e) = insert(&mut conn, &batch) {
+                warn!("Failed to index {} search entries: {}", batch.len(), e);
+            }
+        }
+        if retention_days > 0 && purged_at.map_or(true, |at| at.elapsed() >= PURGE_INTERVAL) {
+            let cutoff = unix_millis().saturating_sub(retention_days * DAY_MS);
+            if let Err(e) = conn.execute("DELETE FROM entries WHERE at_ms < ?1", params![cutoff as i64]) {
+                warn!("Failed to purge old search entries: {}", e);
+            }
+            purged_at = Some(Instant::now());
+        }
+    }
+}
+
+fn insert(conn: &mut Connection, batch: &[Entry]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt =
+            tx.prepare_cached("INSERT INTO entries (text, kind, session_id, owner, at_ms) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+        for entry in batch {
+            stmt.execute(params![
+                entry.text,
+                entry.kind.as_str(),
+                entry.session_id,
+                entry.owner,
+                entry.at_ms as i64
+            ])?;
+        }
+    }
+    tx.commit()
+}
+
+/// The search index, opened on first use; `None` when `search.db_path`
+/// isn't set or the index couldn't be opened
+fn index(state: &AppState) -> Option<Arc<SearchIndex>> {
+    state
+        .shared
+        .search
+        .get_or_init(|| {
+            let config = state.config();
+            let path = config.search.db_path.as_deref()?;
+            match SearchIndex::open(path, config.search.retention_days) {
+                Ok(index) => {
+                    info!("Indexing transcripts and commands for search in {}", path.display());
+                    Some(Arc::new(index))
+                }
+                Err(e) => {
+                    warn!("Search disabled: failed to open {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .clone()
+}
+
+/// Index a command run on behalf of `caller`
+pub(crate) fn record_command(state: &AppState, caller: &Caller, request: &CommandRequest) {
+    let Some(index) = index(state) else { return };
+    let mut text = request.command.clone();
+    for arg in request.args.iter().flatten() {
+        text.push(' ');
+        text.push_str(arg);
+    }
+    index.add(EntryKind::Command, None, caller.token_name(), text);
+}
+
+/// Where a terminal stream is within an escape sequence
+#[derive(Default, Clone, Copy)]
+enum Escape {
+    #[default]
+    None,
+    Start,
+    Csi,
+    Osc,
+}
+
+/// One direction of a shell attachment, turned into plain text and
+/// indexed as it builds up. Whatever is left is indexed on drop.
+pub(crate) struct Transcript {
+    index: Arc<SearchIndex>,
+    kind: EntryKind,
+    session_id: String,
+    owner: Option<String>,
+    escape: Escape,
+    text: String,
+}
+
+impl Transcript {
+    /// `None` when search is disabled
+    pub(crate) fn start(state: &AppState, kind: EntryKind, session_id: &str, owner: Option<&str>) -> Option<Self> {
+        Some(Transcript {
+            index: index(state)?,
+            kind,
+            session_id: session_id.to_string(),
+            owner: owner.map(str::to_string),
+            escape: Escape::None,
+            text: String::new(),
+        })
+    }
+
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        for c in String::from_utf8_lossy(data).chars() {
+            self.escape = match (self.escape, c) {
+                (Escape::None, '\x1b') => Escape::Start,
+                (Escape::None, c) => {
+                    self.push(c);
+                    Escape::None
+                }
+                (Escape::Start, '[') => Escape::Csi,
+                (Escape::Start, ']') => Escape::Osc,
+                (Escape::Start, _) => Escape::None,
+                (Escape::Csi, '\x40'..='\x7e') => Escape::None,
+                (Escape::Csi, _) => Escape::Csi,
+                (Escape::Osc, '\x07') => Escape::None,
+                // ESC \ ends an OSC; the backslash is dropped from Start
+                (Escape::Osc, '\x1b') => Escape::Start,
+                (Escape::Osc, _) => Escape::Osc,
+            };
+        }
+
+        let complete = match self.kind {
+            EntryKind::Output => self.text.len() >= OUTPUT_CHUNK_BYTES,
+            _ => self.text.contains('\n'),
+        };
+        if complete {
+            let end = self.text.rfind('\n').map_or(self.text.len(), |i| i + 1);
+            let text: String = self.text.drain(..end).collect();
+            self.flush(text);
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        let typed = self.kind == EntryKind::Input;
+        match c {
+            '\n' => self.text.push('\n'),
+            '\r' if typed => self.text.push('\n'),
+            '\x7f' | '\x08' if typed => {
+                if !self.text.ends_with('\n') {
+                    self.text.pop();
+                }
+            }
+            '\t' => self.text.push('\t'),
+            c if c.is_control() => {}
+            c => self.text.push(c),
+        }
+    }
+
+    fn flush(&self, text: String) {
+        let text = text.trim();
+        if !text.is_empty() {
+            self.index.add(self.kind, Some(&self.session_id), self.owner.as_deref(), text.to_string());
+        }
+    }
+}
+
+impl Drop for Transcript {
+    fn drop(&mut self) {
+        let text = std::mem::take(&mut self.text);
+        self.flush(text);
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct SearchQuery {
+    /// Words to find; all must match
+    q: String,
+    /// Only entries of this kind
+    kind: Option<EntryKind>,
+    /// Only entries from this session
+    session_id: Option<String>,
+    /// Hits to return [default: 50, max: 500]
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SearchHit {
+    kind: EntryKind,
+    /// Session the input or output came from; unset for commands
+    session_id: Option<String>,
+    /// When the entry was indexed
+    at_ms: u64,
+    /// The matching text in context, matches wrapped in `**`
+    snippet: String,
+}
+
+/// Search shell transcripts and command history, best matches first.
+/// Requests made with an API token only see what that token did.
+#[utoipa::path(get, path = "/search", tag = "search",
+    params(SearchQuery),
+    responses(
+        (status = 200, body = [SearchHit]),
+        (status = 400, description = "Empty query", body = ErrorBody),
+        (status = 501, description = "Search is not enabled", body = ErrorBody),
+    ))]
+pub(crate) async fn search(
+    State(state): State<AppState>,
+    format: Format,
+    caller: Caller,
+    Query(query): Query<SearchQuery>,
+) -> Result<Encoded<Vec<SearchHit>>, ApiError> {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::new(ErrorCode::BadRequest, "Query is empty"));
+    }
+    let index = index(&state)
+        .ok_or_else(|| ApiError::new(ErrorCode::SearchDisabled, "Search is not enabled; set search.db_path"))?;
+    let owner = caller.token_name().map(str::to_string);
+    let hits = tokio::task::spawn_blocking(move || index.query(&query, owner.as_deref()))
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Search failed: {}", e)))??;
+    Ok(Encoded(format, hits))
+}
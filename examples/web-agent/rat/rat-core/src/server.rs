@@ -0,0 +1,168 @@
+//! The HTTP server loop. Works like `axum::serve`, but closes connections
+//! that don't finish sending a request's headers within
+//! `server.header_read_timeout_secs`, so a client stalled on a bad tunnel
+//! doesn't hold a connection open forever. With `[tls]` configured the
+//! same loop serves HTTPS and WSS.
+
+use crate::config::TlsConfig;
+use anyhow::Context;
+use axum::extract::ConnectInfo;
+use axum::{Extension, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tracing::debug;
+
+/// Serve `app` on `listener` until `signal` completes, then stop accepting
+/// and wait for open connections to finish their in-flight requests.
+/// WebSocket upgrades are supported.
+pub async fn serve<F>(listener: TcpListener, app: Router, header_read_timeout: Duration, signal: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    serve_with(listener, None, app, header_read_timeout, signal).await
+}
+
+/// Like `serve`, but every connection speaks TLS through `acceptor`. A
+/// client that doesn't finish the handshake within the header read
+/// timeout is dropped.
+pub async fn serve_tls<F>(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    header_read_timeout: Duration,
+    signal: F,
+) where
+    F: Future<Output = ()> + Send + 'static,
+{
+    serve_with(listener, Some(acceptor), app, header_read_timeout, signal).await
+}
+
+/// Build the TLS acceptor for `[tls]`, or `None` when TLS is off. The
+/// certificate file may hold a chain; the key may be PKCS#8, PKCS#1 or SEC1.
+pub fn tls_acceptor(config: &TlsConfig) -> anyhow::Result<Option<TlsAcceptor>> {
+    let (cert_path, key_path) = match (&config.cert, &config.key) {
+        (None, None) => return Ok(None),
+        (Some(cert), Some(key)) => (cert, key),
+        _ => anyhow::bail!("tls.cert and tls.key must be set together"),
+    };
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert_path).with_context(|| format!("Failed to open {}", cert_path.display()))?,
+    ))
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| format!("Failed to read certificates from {}", cert_path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {}", cert_path.display());
+    }
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        File::open(key_path).with_context(|| format!("Failed to open {}", key_path.display()))?,
+    ))
+    .with_context(|| format!("Failed to read private key from {}", key_path.display()))?
+    .ok_or_else(|| anyhow::anyhow!("No private key found in {}", key_path.display()))?;
+
+    let mut server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+async fn serve_with<F>(
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    app: Router,
+    header_read_timeout: Duration,
+    signal: F,
+) where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    // Every connection holds a receiver; `closed` resolves once all are gone
+    let (open_tx, open_rx) = watch::channel(());
+    tokio::pin!(signal);
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; back off rather than spin
+                    debug!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+
+        // Handlers and the access log can ask for the peer as `ConnectInfo`
+        let service = TowerToHyperService::new(app.clone().layer(Extension(ConnectInfo(peer))));
+        let shutdown = shutdown_rx.clone();
+        let open = open_rx.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            match tls {
+                None => serve_connection(stream, service, header_read_timeout, shutdown).await,
+                Some(acceptor) => {
+                    match tokio::time::timeout(header_read_timeout, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => serve_connection(stream, service, header_read_timeout, shutdown).await,
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", peer),
+                    }
+                }
+            }
+            drop(open);
+        });
+    }
+
+    drop(listener);
+    let _ = shutdown_tx.send(());
+    drop(open_rx);
+    open_tx.closed().await;
+}
+
+/// Serve HTTP/1 or HTTP/2 on one connection until the client is done or,
+/// once shutdown starts, its in-flight requests have finished
+async fn serve_connection<I>(
+    io: I,
+    service: TowerToHyperService<Router>,
+    header_read_timeout: Duration,
+    mut shutdown: watch::Receiver<()>,
+) where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(header_read_timeout);
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+    tokio::pin!(connection);
+
+    let mut draining = false;
+    loop {
+        tokio::select! {
+            result = connection.as_mut() => {
+                if let Err(e) = result {
+                    debug!("Connection closed with error: {}", e);
+                }
+                break;
+            }
+            _ = shutdown.changed(), if !draining => {
+                draining = true;
+                connection.as_mut().graceful_shutdown();
+            }
+        }
+    }
+}
@@ -0,0 +1,892 @@
+//! PTY sessions: creation, listing, traffic metrics and the shell WebSocket.
+
+use crate::auth::Caller;
+use crate::chaos;
+use crate::compression::{self, Deflater, DEFLATE_PROTOCOL};
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::events::EventKind;
+use crate::profiles;
+use crate::pty_io::{next_frame, PtyPumps};
+use crate::query::SortOrder;
+use crate::quota;
+use crate::recording::{Direction, FrameKind, SessionRecorder};
+use crate::search::{EntryKind, Transcript};
+use crate::state::{unix_millis, AppState};
+use crate::throttle::{throttle_output, Throttle};
+use crate::version::API_PREFIX;
+use axum::{
+    extract::{ws::{close_code, CloseFrame, Message, WebSocket}, Path, Query, State, WebSocketUpgrade},
+    response::Response,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use portable_pty::{Child, CommandBuilder, PtyPair, PtySize};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+pub(crate) struct PtySession {
+    pub(crate) id: String,
+    pub(crate) name: Option<String>,
+    pub(crate) pty_pair: PtyPair,
+    /// The PTY master's writer while no client is attached. An attached
+    /// client holds it and hands it back when it detaches.
+    pub(crate) writer: Option<Box<dyn Write + Send>>,
+    pub(crate) child: Box<dyn Child + Send + Sync>,
+    pub(crate) metrics: Arc<SessionMetrics>,
+    /// API token the session was created with; its output is charged there
+    pub(crate) owner: Option<String>,
+    /// Set when the session is removed, so an attached socket can say why
+    pub(crate) end: watch::Sender<Option<SessionEnd>>,
+}
+
+impl PtySession {
+    /// A client holds the PTY's writer
+    pub(crate) fn attached(&self) -> bool {
+        self.writer.is_none()
+    }
+}
+
+/// Why a shell socket was closed. Sent as the close frame's reason, as JSON
+/// with a `message` for people, under a close code per variant.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub(crate) enum SessionEnd {
+    /// The shell exited on its own
+    Exited { exit_code: Option<u32> },
+    /// Stopped through the API
+    Stopped,
+    /// Reaped after `shell.idle_timeout_secs` without traffic
+    Idle { idle_secs: u64 },
+    /// Its API token was removed from the config
+    Revoked,
+    QuotaExceeded,
+    ShuttingDown,
+    /// Attach refused: no such session
+    NotFound,
+    /// Attach refused: another client holds the session
+    AlreadyAttached,
+    /// Attach failed for another reason, such as the PTY
+    AttachFailed { error: String },
+}
+
+impl SessionEnd {
+    /// Why `attach` refused a socket
+    fn rejected(error: &ApiError) -> SessionEnd {
+        match error.code {
+            ErrorCode::SessionNotFound => SessionEnd::NotFound,
+            ErrorCode::SessionAlreadyAttached => SessionEnd::AlreadyAttached,
+            ErrorCode::ShuttingDown => SessionEnd::ShuttingDown,
+            _ => SessionEnd::AttachFailed { error: error.message.clone() },
+        }
+    }
+
+    fn code(&self) -> u16 {
+        match self {
+            SessionEnd::Exited { .. } => 4000,
+            SessionEnd::Stopped => 4001,
+            SessionEnd::Idle { .. } => 4002,
+            SessionEnd::Revoked => 4003,
+            SessionEnd::QuotaExceeded => close_code::POLICY,
+            SessionEnd::ShuttingDown => close_code::AWAY,
+            SessionEnd::NotFound => 4004,
+            SessionEnd::AlreadyAttached => 4005,
+            SessionEnd::AttachFailed { .. } => close_code::ERROR,
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            SessionEnd::Exited { exit_code: Some(code) } => format!("shell exited with status {}", code),
+            SessionEnd::Exited { exit_code: None } => "shell exited".to_string(),
+            SessionEnd::Stopped => "session stopped".to_string(),
+            SessionEnd::Idle { idle_secs } => format!("session reaped after {}s idle", idle_secs),
+            SessionEnd::Revoked => "session's API token was revoked".to_string(),
+            SessionEnd::QuotaExceeded => "output quota exceeded".to_string(),
+            SessionEnd::ShuttingDown => "server shutting down".to_string(),
+            SessionEnd::NotFound => "session not found".to_string(),
+            SessionEnd::AlreadyAttached => "session is already attached".to_string(),
+            SessionEnd::AttachFailed { error } => format!("failed to attach: {}", error),
+        }
+    }
+
+    fn close_frame(&self) -> CloseFrame<'static> {
+        #[derive(Serialize)]
+        struct Reason<'a> {
+            #[serde(flatten)]
+            end: &'a SessionEnd,
+            message: String,
+        }
+        let reason = serde_json::to_string(&Reason { end: self, message: self.message() }).unwrap_or_default();
+        CloseFrame { code: self.code(), reason: reason.into() }
+    }
+}
+
+/// Traffic counters for one session. "In" is client → PTY, "out" is PTY → client.
+#[derive(Default)]
+pub(crate) struct SessionMetrics {
+    created_at_ms: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    frames_in: AtomicU64,
+    frames_out: AtomicU64,
+    last_activity_ms: AtomicU64,
+    /// Frames in either direction during the last full second
+    frames_per_sec: AtomicU64,
+}
+
+impl SessionMetrics {
+    /// Create the counters and start a sampler that computes frames/sec
+    /// until the session is dropped
+    fn start() -> Arc<Self> {
+        let now = unix_millis();
+        let metrics = Arc::new(SessionMetrics::default());
+        metrics.created_at_ms.store(now, Ordering::Relaxed);
+        metrics.last_activity_ms.store(now, Ordering::Relaxed);
+
+        let weak: Weak<SessionMetrics> = Arc::downgrade(&metrics);
+        tokio::spawn(async move {
+            let mut previous = 0;
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let Some(metrics) = weak.upgrade() else { break };
+                let total = metrics.frames_in.load(Ordering::Relaxed)
+                    + metrics.frames_out.load(Ordering::Relaxed);
+                metrics.frames_per_sec.store(total - previous, Ordering::Relaxed);
+                previous = total;
+            }
+        });
+
+        metrics
+    }
+
+    pub(crate) fn record_in(&self, bytes: usize) {
+        self.bytes_in.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.frames_in.fetch_add(1, Ordering::Relaxed);
+        self.last_activity_ms.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_out(&self, bytes: usize) {
+        self.bytes_out.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.frames_out.fetch_add(1, Ordering::Relaxed);
+        self.last_activity_ms.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    fn idle_secs(&self) -> u64 {
+        unix_millis().saturating_sub(self.last_activity_ms.load(Ordering::Relaxed)) / 1000
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SessionCreateResponse {
+    session_id: String,
+    ws_url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SessionInfo {
+    pub(crate) id: String,
+    pub(crate) name: Option<String>,
+    pub(crate) active: bool,
+    pub(crate) attached: bool,
+    pub(crate) created_at_ms: u64,
+    pub(crate) last_activity_ms: u64,
+    pub(crate) idle_secs: u64,
+    pub(crate) bytes_in: u64,
+    pub(crate) bytes_out: u64,
+    pub(crate) frames_in: u64,
+    pub(crate) frames_out: u64,
+    pub(crate) frames_per_sec: u64,
+}
+
+/// Longest session name accepted
+const MAX_NAME_LEN: usize = 128;
+
+/// How to start a session, from the query string of `POST /session/create`
+/// or `WS /shell/new`
+#[derive(Deserialize, IntoParams, Default)]
+pub(crate) struct SessionOptions {
+    /// Program to run in the PTY [default: the profile's shell, or bash]
+    pub(crate) shell: Option<String>,
+    /// Terminal width [default: 80]
+    pub(crate) cols: Option<u16>,
+    /// Terminal height [default: 24]
+    pub(crate) rows: Option<u16>,
+    /// Label shown in `GET /sessions`
+    pub(crate) name: Option<String>,
+    /// Start the shell with the environment of this `[profiles]` entry
+    pub(crate) profile: Option<String>,
+}
+
+/// Sessions returned when `GET /sessions` isn't given a limit
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SessionSort {
+    #[default]
+    Created,
+    LastActivity,
+    BytesIn,
+    BytesOut,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SessionState {
+    Attached,
+    Detached,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct SessionQuery {
+    /// Sessions per page [default: 100, max: 1000]
+    limit: Option<usize>,
+    /// Sessions to skip, after filtering and sorting
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    #[param(inline)]
+    sort: SessionSort,
+    #[serde(default)]
+    #[param(inline)]
+    order: SortOrder,
+    #[param(inline)]
+    state: Option<SessionState>,
+}
+
+/// One page of sessions
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SessionPage {
+    /// Sessions matching the filter, across all pages
+    total: usize,
+    offset: usize,
+    limit: usize,
+    sessions: Vec<SessionInfo>,
+}
+
+impl SessionInfo {
+    pub(crate) fn from_session(session: &PtySession) -> Self {
+        let m = &session.metrics;
+        SessionInfo {
+            id: session.id.clone(),
+            name: session.name.clone(),
+            active: true,
+            attached: session.attached(),
+            created_at_ms: m.created_at_ms.load(Ordering::Relaxed),
+            last_activity_ms: m.last_activity_ms.load(Ordering::Relaxed),
+            idle_secs: m.idle_secs(),
+            bytes_in: m.bytes_in.load(Ordering::Relaxed),
+            bytes_out: m.bytes_out.load(Ordering::Relaxed),
+            frames_in: m.frames_in.load(Ordering::Relaxed),
+            frames_out: m.frames_out.load(Ordering::Relaxed),
+            frames_per_sec: m.frames_per_sec.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Open a PTY running the requested shell, bash by default, and register
+/// it as a new session owned by `caller`
+pub(crate) fn spawn_session(state: &AppState, caller: &Caller, options: &SessionOptions) -> Result<String, ApiError> {
+    if options.name.as_ref().is_some_and(|name| name.len() > MAX_NAME_LEN) {
+        return Err(ApiError::new(
+            ErrorCode::BadRequest,
+            format!("Session name is longer than {} bytes", MAX_NAME_LEN),
+        ));
+    }
+    if options.cols == Some(0) || options.rows == Some(0) {
+        return Err(ApiError::new(ErrorCode::BadRequest, "Terminal size must be at least 1x1"));
+    }
+    if let Some(max) = state.config().limits.max_sessions {
+        if state.shared.sessions.len() >= max {
+            warn!("Refusing new session: limit of {} reached", max);
+            return Err(ApiError::new(ErrorCode::SessionLimitReached, format!("Session limit of {} reached", max)));
+        }
+    }
+    quota::check_session(state, caller)?;
+    let profile = profiles::find(state, options.profile.as_deref())?.unwrap_or_default();
+
+    let session_id = Uuid::new_v4().to_string();
+
+    // Create PTY
+    let pty_system = state.pty_system();
+    let pty_pair = pty_system
+        .openpty(PtySize {
+            rows: options.rows.unwrap_or(24),
+            cols: options.cols.unwrap_or(80),
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|e| {
+            error!("Failed to create PTY: {}", e);
+            state.emit(EventKind::Error { message: format!("Failed to create PTY: {}", e) });
+            ApiError::new(ErrorCode::PtyFailed, format!("Failed to create PTY: {}", e))
+        })?;
+
+    // Spawn shell in PTY
+    let shell = options.shell.as_deref().or(profile.shell.as_deref()).unwrap_or("bash");
+    let mut cmd = CommandBuilder::new(shell);
+    cmd.env("TERM", "xterm-256color");
+    for (key, value) in &profile.env {
+        cmd.env(key, value);
+    }
+    if let Some(cwd) = &profile.cwd {
+        cmd.cwd(cwd);
+    }
+    if let Some(rc) = &profile.rc {
+        if profiles::is_bash(shell) {
+            cmd.arg("--rcfile");
+            cmd.arg(rc);
+        } else {
+            cmd.env("ENV", rc);
+        }
+    }
+
+    let child = pty_pair.slave.spawn_command(cmd).map_err(|e| {
+        error!("Failed to spawn shell: {}", e);
+        state.emit(EventKind::Error { message: format!("Failed to spawn shell: {}", e) });
+        ApiError::new(ErrorCode::SpawnFailed, format!("Failed to spawn shell: {}", e))
+    })?;
+
+    let writer = pty_pair.master.take_writer().map_err(|e| {
+        ApiError::new(ErrorCode::PtyFailed, format!("Failed to open PTY master: {}", e))
+    })?;
+    let metrics = SessionMetrics::start();
+    let owner = caller.token_name().map(str::to_string);
+    let session = PtySession {
+        id: session_id.clone(),
+        name: options.name.clone(),
+        pty_pair,
+        writer: Some(writer),
+        child,
+        metrics: metrics.clone(),
+        owner: owner.clone(),
+        end: watch::channel(None).0,
+    };
+
+    state.shared.sessions.insert(session_id.clone(), Arc::new(Mutex::new(session)));
+    state.shared.session_count.fetch_add(1, Ordering::Relaxed);
+    reap_when_stale(state, &session_id, metrics, owner);
+    state.emit(EventKind::SessionCreated { session_id: session_id.clone() });
+    Ok(session_id)
+}
+
+/// Remove the session once it has been idle for `shell.idle_timeout_secs`,
+/// or once its API token is no longer configured. Both are read from the
+/// live config, so reloads apply to existing sessions.
+fn reap_when_stale(state: &AppState, session_id: &str, metrics: Arc<SessionMetrics>, owner: Option<String>) {
+    let state = state.clone();
+    let session_id = session_id.to_string();
+    let metrics = Arc::downgrade(&metrics);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let Some(metrics) = metrics.upgrade() else { break };
+            if !state.shared.sessions.contains_key(&session_id) {
+                break;
+            }
+            let config = state.config();
+            let idle_secs = metrics.idle_secs();
+            let end = if config.shell.idle_timeout_secs > 0 && idle_secs >= config.shell.idle_timeout_secs {
+                SessionEnd::Idle { idle_secs }
+            } else if owner.as_ref().is_some_and(|name| !config.auth.tokens.iter().any(|t| t.name == *name)) {
+                SessionEnd::Revoked
+            } else {
+                continue;
+            };
+            info!("Reaping session {}: {}", session_id, end.message());
+            remove_session(&state, &session_id, end);
+            break;
+        }
+    });
+}
+
+/// Where clients reach this agent: the tunnel URL if there is one,
+/// otherwise localhost on the configured port
+pub(crate) async fn base_url(state: &AppState) -> String {
+    match state.shared.public_url.read().await.clone() {
+        Some(url) => url,
+        None => {
+            let config = state.config();
+            let scheme = if config.tls.enabled() { "https" } else { "http" };
+            format!("{}://localhost:{}", scheme, config.server.port)
+        }
+    }
+}
+
+/// WebSocket URL of a session's shell
+pub(crate) async fn shell_ws_url(state: &AppState, session_id: &str) -> String {
+    format!("{}{}/shell/{}", base_url(state).await.replace("http", "ws"), API_PREFIX, session_id)
+}
+
+/// Drop a session and its PTY, closing an attached socket with `end`;
+/// false if there was no such session
+pub(crate) fn remove_session(state: &AppState, session_id: &str, end: SessionEnd) -> bool {
+    if let Some((_, session)) = state.shared.sessions.remove(session_id) {
+        state.shared.session_count.fetch_sub(1, Ordering::Relaxed);
+        session.lock().unwrap().end.send_replace(Some(end));
+        state.emit(EventKind::SessionStopped { session_id: session_id.to_string() });
+        true
+    } else {
+        false
+    }
+}
+
+/// Set the terminal size of a session's PTY; the shell gets a SIGWINCH
+pub(crate) fn resize_session(state: &AppState, session_id: &str, cols: u16, rows: u16) -> Result<(), String> {
+    let session = state
+        .shared
+        .sessions
+        .get(session_id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| "Session not found".to_string())?;
+    let session = session.lock().unwrap();
+    session
+        .pty_pair
+        .master
+        .resize(PtySize {
+            rows,
+            cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        })
+        .map_err(|e| format!("Failed to resize PTY: {}", e))
+}
+
+/// Control messages a shell client can send as JSON text frames. Text that
+/// doesn't parse as one is typed into the shell like a binary frame.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ControlMessage {
+    Resize { cols: u16, rows: u16 },
+}
+
+/// A client's exclusive hold on a session's PTY. `output` yields what the
+/// shell writes; whatever is sent on `input` is typed into it.
+pub(crate) struct Attachment {
+    pub(crate) output: mpsc::Receiver<Bytes>,
+    pub(crate) input: mpsc::Sender<Bytes>,
+    pub(crate) metrics: Arc<SessionMetrics>,
+    /// Token the session's output is charged to, see `quota::charge_output`
+    pub(crate) owner: Option<String>,
+    /// Becomes `Some` when the session is removed
+    pub(crate) ended: watch::Receiver<Option<SessionEnd>>,
+    pub(crate) handle: AttachHandle,
+}
+
+/// Ends an attachment once the transport is done with it
+pub(crate) struct AttachHandle {
+    state: AppState,
+    session_id: String,
+    session: Weak<Mutex<PtySession>>,
+    pumps: PtyPumps,
+}
+
+impl AttachHandle {
+    /// Stop the PTY pumps and announce the detach. The writer goes back to
+    /// the session, so it can be attached again, once the `input` sender is
+    /// dropped and what was already sent has been typed.
+    pub(crate) async fn detach(self) {
+        let session = self.session;
+        self.pumps
+            .stop(move |writer| {
+                if let Some(session) = session.upgrade() {
+                    session.lock().unwrap().writer = Some(writer);
+                }
+            })
+            .await;
+        self.state.emit(EventKind::SessionDetached { session_id: self.session_id });
+    }
+}
+
+/// Take a session's PTY for one client. It is refused while another client
+/// holds it, and free to attach again once that client detaches.
+pub(crate) fn attach(state: &AppState, session_id: &str) -> Result<Attachment, ApiError> {
+    if *state.shared.shutdown.borrow() {
+        return Err(ApiError::new(ErrorCode::ShuttingDown, "Server shutting down"));
+    }
+
+    let session = state
+        .shared
+        .sessions
+        .get(session_id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| ApiError::new(ErrorCode::SessionNotFound, "Session not found"))?;
+
+    let pty_error = |e: anyhow::Error| {
+        ApiError::new(ErrorCode::PtyFailed, format!("Failed to open PTY master: {}", e))
+    };
+
+    // Lock only long enough to take the master's reader and writer
+    let (pty_reader, pty_writer, master_fd, metrics, owner, ended) = {
+        let mut session_lock = session.lock().unwrap();
+        if session_lock.attached() {
+            return Err(ApiError::new(ErrorCode::SessionAlreadyAttached, "Session is already attached"));
+        }
+
+        // Clone the reader first, so a failure leaves the writer in place
+        let reader = session_lock.pty_pair.master.try_clone_reader().map_err(pty_error)?;
+        let writer = session_lock.writer.take().expect("detached session has its writer");
+        let fd = session_lock.pty_pair.master.as_raw_fd();
+        let ended = session_lock.end.subscribe();
+        (reader, writer, fd, session_lock.metrics.clone(), session_lock.owner.clone(), ended)
+    };
+
+    let read_buffer_bytes = state.config().shell.read_buffer_bytes;
+    let (pumps, output, input) = PtyPumps::start(pty_reader, pty_writer, master_fd, read_buffer_bytes);
+    state.emit(EventKind::SessionAttached { session_id: session_id.to_string() });
+
+    Ok(Attachment {
+        output,
+        input,
+        metrics,
+        owner,
+        ended,
+        handle: AttachHandle {
+            state: state.clone(),
+            session_id: session_id.to_string(),
+            session: Arc::downgrade(&session),
+            pumps,
+        },
+    })
+}
+
+/// Create a new PTY session
+#[utoipa::path(post, path = "/session/create", tag = "sessions",
+    params(SessionOptions),
+    responses(
+        (status = 400, description = "Invalid name or terminal size", body = ErrorBody),
+        (status = 200, body = SessionCreateResponse),
+        (status = 429, description = "Session limit or the token's session quota reached", body = ErrorBody),
+        (status = 500, description = "PTY or shell could not be started", body = ErrorBody),
+    ))]
+pub(crate) async fn create_session(
+    State(state): State<AppState>,
+    format: Format,
+    caller: Caller,
+    Query(options): Query<SessionOptions>,
+) -> Result<Encoded<SessionCreateResponse>, ApiError> {
+    info!("Creating new PTY session");
+
+    let session_id = spawn_session(&state, &caller, &options)?;
+
+    let ws_url = shell_ws_url(&state, &session_id).await;
+
+    info!("Created session {} with WebSocket URL: {}", session_id, ws_url);
+
+    Ok(Encoded(format, SessionCreateResponse {
+        session_id,
+        ws_url,
+    }))
+}
+
+/// List sessions, a page at a time
+#[utoipa::path(get, path = "/sessions", tag = "sessions",
+    params(SessionQuery),
+    responses((status = 200, body = SessionPage)))]
+pub(crate) async fn list_sessions(
+    State(state): State<AppState>,
+    format: Format,
+    Query(query): Query<SessionQuery>,
+) -> Encoded<SessionPage> {
+    let mut sessions: Vec<SessionInfo> = state
+        .shared
+        .sessions
+        .iter()
+        .map(|entry| SessionInfo::from_session(&entry.value().lock().unwrap()))
+        .filter(|info| match query.state {
+            Some(SessionState::Attached) => info.attached,
+            Some(SessionState::Detached) => !info.attached,
+            None => true,
+        })
+        .collect();
+
+    // Ties, such as sessions created in the same millisecond, fall back to
+    // the id so pages don't overlap
+    sessions.sort_by(|a, b| {
+        let ordering = match query.sort {
+            SessionSort::Created => a.created_at_ms.cmp(&b.created_at_ms),
+            SessionSort::LastActivity => a.last_activity_ms.cmp(&b.last_activity_ms),
+            SessionSort::BytesIn => a.bytes_in.cmp(&b.bytes_in),
+            SessionSort::BytesOut => a.bytes_out.cmp(&b.bytes_out),
+        }
+        .then_with(|| a.id.cmp(&b.id));
+        query.order.apply(ordering)
+    });
+
+    let total = sessions.len();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let sessions = sessions.into_iter().skip(query.offset).take(limit).collect();
+    Encoded(format, SessionPage {
+        total,
+        offset: query.offset,
+        limit,
+        sessions,
+    })
+}
+
+/// Stop a session
+#[utoipa::path(post, path = "/session/{session_id}/stop", tag = "sessions",
+    params(("session_id" = String, Path)),
+    responses(
+        (status = 200, description = "Session stopped"),
+        (status = 404, description = "Session not found", body = ErrorBody),
+    ))]
+pub(crate) async fn stop_session(
+    State(state): State<AppState>,
+    format: Format,
+    Path(session_id): Path<String>,
+) -> Result<Encoded<serde_json::Value>, ApiError> {
+    info!("Stopping session {}", session_id);
+
+    if remove_session(&state, &session_id, SessionEnd::Stopped) {
+        Ok(Encoded(format, serde_json::json!({"status": "stopped"})))
+    } else {
+        Err(ApiError::new(ErrorCode::SessionNotFound, "Session not found"))
+    }
+}
+
+/// WebSocket handler for shell I/O
+#[utoipa::path(get, path = "/shell/{session_id}", tag = "sessions",
+    params(("session_id" = String, Path)),
+    responses((status = 101, description = "WebSocket upgrade; binary frames carry PTY I/O, deflated when the rat.deflate subprotocol is negotiated. Text frames of the form {\"type\":\"resize\",\"cols\":N,\"rows\":N} resize the terminal")))]
+pub(crate) async fn shell_ws_handler(
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+    Path(session_id): Path<String>,
+) -> Response {
+    info!("WebSocket connection request for session {}", session_id);
+
+    let ws = if state.config().websocket.compression {
+        ws.protocols([DEFLATE_PROTOCOL])
+    } else {
+        ws
+    };
+    ws.on_upgrade(move |socket| handle_shell_socket(state, socket, session_id, false))
+}
+
+/// Create a session and attach to it in one round trip
+#[utoipa::path(get, path = "/shell/new", tag = "sessions",
+    params(SessionOptions),
+    responses(
+        (status = 101, description = "WebSocket upgrade, as for `/shell/{session_id}`. The first frame is the text \
+            frame {\"type\":\"session\",\"session_id\":\"...\"} naming the new session, to stop it by"),
+        (status = 400, description = "Invalid name or terminal size", body = ErrorBody),
+        (status = 429, description = "Session limit or the token's session quota reached", body = ErrorBody),
+        (status = 500, description = "PTY or shell could not be started", body = ErrorBody),
+    ))]
+pub(crate) async fn new_shell_ws_handler(
+    State(state): State<AppState>,
+    caller: Caller,
+    Query(options): Query<SessionOptions>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // Created before upgrading, so a failure is still an HTTP error, and
+    // removed again if the upgrade then fails
+    let session_id = spawn_session(&state, &caller, &options)?;
+    info!("Created session {} for a new WebSocket connection", session_id);
+
+    let ws = if state.config().websocket.compression {
+        ws.protocols([DEFLATE_PROTOCOL])
+    } else {
+        ws
+    };
+    let (failed_state, failed_id) = (state.clone(), session_id.clone());
+    Ok(ws
+        .on_failed_upgrade(move |e| {
+            warn!("WebSocket upgrade for new session {} failed: {}", failed_id, e);
+            remove_session(&failed_state, &failed_id, SessionEnd::Stopped);
+        })
+        .on_upgrade(move |socket| handle_shell_socket(state, socket, session_id, true)))
+}
+
+/// How a session's shell exited, giving it a moment to be reaped after
+/// its PTY closed
+async fn shell_exit(state: &AppState, session_id: &str) -> SessionEnd {
+    let Some(session) = state.shared.sessions.get(session_id).map(|entry| entry.value().clone()) else {
+        return SessionEnd::Exited { exit_code: None };
+    };
+    for _ in 0..20 {
+        let status = session.lock().unwrap().child.try_wait();
+        if let Ok(Some(status)) = status {
+            return SessionEnd::Exited { exit_code: Some(status.exit_code()) };
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    SessionEnd::Exited { exit_code: None }
+}
+
+/// Pump a session's PTY over an upgraded socket. With `announce`, the
+/// client is first told which session it got.
+async fn handle_shell_socket(state: AppState, mut socket: WebSocket, session_id: String, announce: bool) {
+    info!("WebSocket connected for session {}", session_id);
+
+    let Attachment { output: mut pty_rx, input: ws_to_pty_tx, metrics, owner, mut ended, handle } =
+        match attach(&state, &session_id) {
+            Ok(attachment) => attachment,
+            Err(e) => {
+                warn!("Rejecting WebSocket for session {}: {}", session_id, e);
+                let _ = socket.send(Message::Close(Some(SessionEnd::rejected(&e).close_frame()))).await;
+                return;
+            }
+        };
+
+    let mut deflater = compression::negotiated(socket.protocol()).then(Deflater::new);
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    if announce {
+        let hello = serde_json::json!({"type": "session", "session_id": session_id});
+        if ws_tx.send(Message::Text(hello.to_string())).await.is_err() {
+            drop(ws_to_pty_tx);
+            handle.detach().await;
+            return;
+        }
+    }
+
+    let shell = state.config().shell.clone();
+    let chaos = state.config().chaos.clone();
+    let recorder = SessionRecorder::start(shell.record_dir.as_deref(), &session_id);
+    let input_recorder = recorder.clone();
+    let mut output_transcript = Transcript::start(&state, EntryKind::Output, &session_id, owner.as_deref());
+    let mut input_transcript = Transcript::start(&state, EntryKind::Input, &session_id, owner.as_deref());
+    let coalesce_delay = Duration::from_millis(shell.coalesce_delay_ms);
+
+    // PTY → WebSocket, closing the socket with the reason the session ended
+    let session_id_clone = session_id.clone();
+    let mut shutdown_rx = state.shared.shutdown.subscribe();
+    let metrics_out = metrics.clone();
+    let read_state = state.clone();
+    let throttle = Throttle::default();
+    let mut read_task = tokio::spawn(async move {
+        'pump: loop {
+            tokio::select! {
+                data = pty_rx.recv() => {
+                    match data {
+                        Some(first) => {
+                            let frame =
+                                next_frame(first, &mut pty_rx, shell.max_frame_bytes, coalesce_delay).await;
+                            metrics_out.record_out(frame.len());
+                            if !quota::charge_output(&read_state, owner.as_deref(), frame.len()) {
+                                let _ = ws_tx.send(Message::Close(Some(SessionEnd::QuotaExceeded.close_frame()))).await;
+                                break;
+                            }
+                            if let Some(transcript) = &mut output_transcript {
+                                transcript.feed(&frame);
+                            }
+                            let Some(frames) = chaos::apply(&chaos, &session_id_clone, frame).await else {
+                                break;
+                            };
+                            for frame in frames {
+                                if let Some(recorder) = &recorder {
+                                    recorder.record(Direction::Output, FrameKind::Binary, &frame);
+                                }
+                                let frame = match deflater.as_mut() {
+                                    Some(deflater) => deflater.compress(&frame),
+                                    None => frame,
+                                };
+                                throttle_output(&read_state, &throttle, frame.len()).await;
+                                if ws_tx.send(Message::Binary(frame)).await.is_err() {
+                                    break 'pump;
+                                }
+                            }
+                        }
+                        None => {
+                            // The PTY closed: the session was removed, or the shell exited
+                            let end = ended.borrow().clone();
+                            let end = match end {
+                                Some(end) => end,
+                                None => shell_exit(&read_state, &session_id_clone).await,
+                            };
+                            let _ = ws_tx.send(Message::Close(Some(end.close_frame()))).await;
+                            break;
+                        }
+                    }
+                }
+                Ok(()) = ended.changed() => {
+                    let end = ended.borrow().clone().unwrap_or(SessionEnd::Stopped);
+                    let _ = ws_tx.send(Message::Close(Some(end.close_frame()))).await;
+                    break;
+                }
+                _ = shutdown_rx.changed() => {
+                    let _ = ws_tx.send(Message::Close(Some(SessionEnd::ShuttingDown.close_frame()))).await;
+                    break;
+                }
+            }
+        }
+        info!("PTY→WS task ended for session {}", session_id_clone);
+    });
+
+    // WebSocket → PTY
+    let session_id_clone2 = session_id.clone();
+    let write_state = state.clone();
+    let mut shutdown_rx2 = state.shared.shutdown.subscribe();
+    let mut write_task = tokio::spawn(async move {
+        loop {
+            let msg = tokio::select! {
+                msg = ws_rx.next() => match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break,
+                },
+                _ = shutdown_rx2.changed() => break,
+            };
+            if let Some(recorder) = &input_recorder {
+                match &msg {
+                    Message::Binary(data) => recorder.record(Direction::Input, FrameKind::Binary, data),
+                    Message::Text(text) => recorder.record(Direction::Input, FrameKind::Text, text.as_bytes()),
+                    _ => {}
+                }
+            }
+            match msg {
+                Message::Binary(data) => {
+                    metrics.record_in(data.len());
+                    if let Some(transcript) = &mut input_transcript {
+                        transcript.feed(&data);
+                    }
+                    if ws_to_pty_tx.send(Bytes::from(data)).await.is_err() {
+                        break;
+                    }
+                }
+                Message::Text(text) => match serde_json::from_str::<ControlMessage>(&text) {
+                    Ok(ControlMessage::Resize { cols, rows }) => {
+                        if let Err(e) = resize_session(&write_state, &session_id_clone2, cols, rows) {
+                            warn!("Resize of session {} failed: {}", session_id_clone2, e);
+                        }
+                    }
+                    Err(_) => {
+                        metrics.record_in(text.len());
+                        if let Some(transcript) = &mut input_transcript {
+                            transcript.feed(text.as_bytes());
+                        }
+                        if ws_to_pty_tx.send(Bytes::from(text)).await.is_err() {
+                            break;
+                        }
+                    }
+                },
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        info!("WS→PTY task ended for session {}", session_id_clone2);
+    });
+
+    // Either side ending (client gone, shell exited, shutdown) ends the
+    // attachment; the other task is cancelled rather than left waiting
+    let remaining = tokio::select! {
+        _ = &mut read_task => write_task,
+        _ = &mut write_task => read_task,
+    };
+    remaining.abort();
+    let _ = remaining.await;
+    handle.detach().await;
+
+    info!("WebSocket disconnected for session {}", session_id);
+}
@@ -0,0 +1,119 @@
+//! Graceful shutdown: close attached sockets and terminate child processes.
+
+use crate::events::EventKind;
+use crate::session::PtySession;
+use crate::state::AppState;
+use portable_pty::ChildKiller;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: libc::c_int) {
+    // SAFETY: kill(2) has no memory-safety preconditions
+    unsafe {
+        libc::kill(pid as libc::pid_t, signal);
+    }
+}
+
+/// SIGHUP every shell and SIGTERM every command child, then SIGKILL
+/// whatever is still alive once the grace period runs out. REPLs are
+/// killed straight away.
+/// Runs once per server; later calls wait for the first to finish.
+pub async fn terminate_children(state: AppState, grace: Duration) {
+    state.shared.children_terminated.get_or_init(|| terminate(&state, grace)).await;
+}
+
+async fn terminate(state: &AppState, grace: Duration) {
+    let shared = &state.shared;
+    let ids = state.session_ids();
+    let sessions: Vec<Arc<Mutex<PtySession>>> = ids
+        .iter()
+        .filter_map(|id| shared.sessions.remove(id).map(|(_, session)| session))
+        .collect();
+    shared.session_count.fetch_sub(sessions.len(), Ordering::Relaxed);
+
+    let mut session_pids = Vec::new();
+    for session in &sessions {
+        if let Some(pid) = session.lock().unwrap().child.process_id() {
+            session_pids.push(pid);
+        }
+    }
+    let command_pids: Vec<u32> = shared.child_pids.iter().map(|pid| *pid).collect();
+
+    // REPLs hold no state worth a grace period; dropping them kills them
+    if !shared.repls.is_empty() {
+        info!("Stopping {} REPL(s)", shared.repls.len());
+        shared.repls.clear();
+    }
+
+    info!(
+        "Sending SIGHUP to {} shell(s) and SIGTERM to {} command(s)",
+        session_pids.len(),
+        command_pids.len()
+    );
+    // An interactive bash ignores SIGTERM, but exits on the SIGHUP a closing
+    // terminal would send, and passes it on to the jobs it started
+    for pid in &session_pids {
+        send_signal(*pid, libc::SIGHUP);
+    }
+    for pid in &command_pids {
+        send_signal(*pid, libc::SIGTERM);
+    }
+
+    let deadline = Instant::now() + grace;
+    loop {
+        let shells_exited = sessions
+            .iter()
+            .all(|s| matches!(s.lock().unwrap().child.try_wait(), Ok(Some(_))));
+        let commands_exited = shared.child_pids.is_empty();
+        if (shells_exited && commands_exited) || Instant::now() >= deadline {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    for session in &sessions {
+        let mut session = session.lock().unwrap();
+        if !matches!(session.child.try_wait(), Ok(Some(_))) {
+            warn!("Killing session {} after grace period", session.id);
+            let _ = session.child.kill();
+        }
+    }
+    for pid in shared.child_pids.iter() {
+        let pid = *pid;
+        warn!("Killing command pid {} after grace period", pid);
+        send_signal(pid, libc::SIGKILL);
+    }
+}
+
+/// Resolves on SIGINT or SIGTERM, after notifying attached WebSockets and
+/// starting child termination
+pub async fn shutdown_signal(state: AppState, grace: Duration) {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to install SIGTERM handler: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, draining connections");
+    state.shared.shutdown.send_replace(true);
+    state.emit(EventKind::ShutdownStarted);
+    tokio::spawn(terminate_children(state, grace));
+}
@@ -0,0 +1,249 @@
+//! Server-wide state, shared by every handler through axum's `State`.
+//!
+//! Locks that handlers take inside async code are `tokio::sync` or
+//! sharded (`DashMap`), so a slow holder never blocks a runtime thread.
+//! The config stays behind a std `RwLock`: it is read from synchronous
+//! code (the CORS predicate, the panic hook) and never held across `.await`.
+
+use crate::access_log::AccessLog;
+use crate::config::Config;
+use crate::events::{EventKind, ServerEvent};
+use crate::idempotency::Stored;
+use crate::plugin::{valid_name, Plugin};
+use crate::quota::Usage;
+use crate::jobs::Job;
+use crate::repl::ReplSession;
+use crate::search::SearchIndex;
+use crate::session::PtySession;
+use crate::throttle::Throttle;
+use dashmap::{DashMap, DashSet};
+use portable_pty::{native_pty_system, PtySystem};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+
+pub(crate) fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Runtime state behind every `AppState` clone
+pub(crate) struct Shared {
+    pub(crate) config: RwLock<Arc<Config>>,
+    pub(crate) public_url: tokio::sync::RwLock<Option<String>>,
+    pub(crate) sessions: DashMap<String, Arc<Mutex<PtySession>>>,
+    /// Entries in `sessions`, kept beside the map so the panic hook can
+    /// report it without taking shard locks
+    pub(crate) session_count: AtomicUsize,
+    pub(crate) repls: DashMap<String, Arc<ReplSession>>,
+    pub(crate) jobs: DashMap<String, Arc<Job>>,
+    /// Requests made with an `Idempotency-Key`, by caller, route and key
+    pub(crate) idempotency_keys: DashMap<String, Arc<Stored>>,
+    /// PIDs of running `/execute` commands, signalled on shutdown
+    pub(crate) child_pids: DashSet<u32>,
+    pub(crate) started_at: Instant,
+    pub(crate) shutdown: watch::Sender<bool>,
+    /// Set once `terminate_children` has run
+    pub(crate) children_terminated: tokio::sync::OnceCell<()>,
+    pub(crate) events: broadcast::Sender<ServerEvent>,
+    pub(crate) tunnel_enabled: AtomicBool,
+    pub(crate) commands_executed: AtomicU64,
+    pub(crate) running_jobs: AtomicUsize,
+    /// API requests currently being handled, for `max_in_flight_requests`
+    pub(crate) in_flight_requests: AtomicUsize,
+    /// Usage charged to each API token, by token name
+    pub(crate) quota_usage: DashMap<String, Usage>,
+    /// Shared by every session, for `total_output_bytes_per_sec`
+    pub(crate) output_throttle: Throttle,
+    /// Opened on first use, `None` when `logging.access_log` isn't set
+    pub(crate) access_log: OnceLock<Option<AccessLog>>,
+    /// Opened on first use, `None` when search is disabled
+    pub(crate) search: OnceLock<Option<Arc<SearchIndex>>>,
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Shared {
+            config: RwLock::new(Arc::new(Config::default())),
+            public_url: tokio::sync::RwLock::new(None),
+            sessions: DashMap::new(),
+            session_count: AtomicUsize::new(0),
+            repls: DashMap::new(),
+            jobs: DashMap::new(),
+            idempotency_keys: DashMap::new(),
+            child_pids: DashSet::new(),
+            started_at: Instant::now(),
+            shutdown: watch::channel(false).0,
+            children_terminated: tokio::sync::OnceCell::new(),
+            events: broadcast::channel(1024).0,
+            tunnel_enabled: AtomicBool::new(false),
+            commands_executed: AtomicU64::new(0),
+            running_jobs: AtomicUsize::new(0),
+            in_flight_requests: AtomicUsize::new(0),
+            quota_usage: DashMap::new(),
+            output_throttle: Throttle::default(),
+            access_log: OnceLock::new(),
+            search: OnceLock::new(),
+        }
+    }
+}
+
+/// Produces a freshly loaded config when a reload is requested
+pub type ConfigLoader = Arc<dyn Fn() -> anyhow::Result<Config> + Send + Sync>;
+
+/// Opens the PTY system new sessions are created on
+pub type PtySystemFactory = Arc<dyn Fn() -> Box<dyn PtySystem + Send> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct AppState {
+    config_loader: Option<ConfigLoader>,
+    pty_system: Option<PtySystemFactory>,
+    plugins: Vec<Arc<dyn Plugin>>,
+    pub(crate) shared: Arc<Shared>,
+}
+
+impl AppState {
+    pub fn new() -> Self {
+        AppState::default()
+    }
+
+    /// Source for SIGHUP and `/admin/reload`; without one, reloads fail
+    pub fn with_config_loader<F>(mut self, loader: F) -> Self
+    where
+        F: Fn() -> anyhow::Result<Config> + Send + Sync + 'static,
+    {
+        self.config_loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Create sessions on another PTY implementation instead of the host's,
+    /// such as the scripted one in `test_support`
+    pub fn with_pty_system<F>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Box<dyn PtySystem + Send> + Send + Sync + 'static,
+    {
+        self.pty_system = Some(Arc::new(factory));
+        self
+    }
+
+    /// Register a plugin to be mounted at `/plugins/<name>`.
+    ///
+    /// Panics if the name is not URL-safe or is already registered.
+    pub fn with_plugin<P: Plugin>(mut self, plugin: P) -> Self {
+        let name = plugin.name();
+        assert!(valid_name(name), "invalid plugin name {:?}", name);
+        assert!(
+            self.plugins.iter().all(|p| p.name() != name),
+            "plugin {:?} registered twice",
+            name
+        );
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn plugins(&self) -> &[Arc<dyn Plugin>] {
+        &self.plugins
+    }
+
+    /// Snapshot of the effective configuration
+    pub fn config(&self) -> Arc<Config> {
+        self.shared.config.read().unwrap().clone()
+    }
+
+    pub(crate) fn pty_system(&self) -> Box<dyn PtySystem + Send> {
+        match &self.pty_system {
+            Some(factory) => factory(),
+            None => native_pty_system(),
+        }
+    }
+
+    pub(crate) fn set_config(&self, config: Config) {
+        *self.shared.config.write().unwrap() = Arc::new(config);
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.shared.started_at.elapsed()
+    }
+
+    /// IDs of the current sessions
+    pub fn session_ids(&self) -> Vec<String> {
+        self.shared.sessions.iter().map(|entry| entry.key().clone()).collect()
+    }
+
+    /// Number of current sessions, read without locking the session map,
+    /// so it is safe to call from a panic hook
+    pub fn session_count(&self) -> usize {
+        self.shared.session_count.load(Ordering::Relaxed)
+    }
+
+    /// Publish an event; a no-op when nobody is subscribed
+    pub(crate) fn emit(&self, kind: EventKind) {
+        let _ = self.shared.events.send(ServerEvent {
+            timestamp_ms: unix_millis(),
+            kind,
+        });
+    }
+
+    /// Reload the config through the loader and apply the sections that are
+    /// safe to change at runtime: auth, limits, policy, CORS, shell,
+    /// websocket, http_compression, system, repl, jobs and profiles.
+    /// Returns the names of sections that changed but only take effect
+    /// after a restart.
+    pub fn reload_config(&self) -> anyhow::Result<Vec<String>> {
+        let loader = self
+            .config_loader
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No config source to reload from"))?;
+        let fresh = loader()?;
+
+        let mut current = self.shared.config.write().unwrap();
+
+        let mut restart_required = Vec::new();
+        if fresh.server != current.server {
+            restart_required.push("server".to_string());
+        }
+        if fresh.tunnel != current.tunnel {
+            restart_required.push("tunnel".to_string());
+        }
+        if fresh.tls != current.tls {
+            restart_required.push("tls".to_string());
+        }
+        if fresh.daemon != current.daemon {
+            restart_required.push("daemon".to_string());
+        }
+        if fresh.logging != current.logging {
+            restart_required.push("logging".to_string());
+        }
+        if fresh.crash != current.crash {
+            restart_required.push("crash".to_string());
+        }
+        if fresh.search != current.search {
+            restart_required.push("search".to_string());
+        }
+
+        let next = Config {
+            auth: fresh.auth,
+            limits: fresh.limits,
+            policy: fresh.policy,
+            cors: fresh.cors,
+            shell: fresh.shell,
+            websocket: fresh.websocket,
+            http_compression: fresh.http_compression,
+            system: fresh.system,
+            repl: fresh.repl,
+            jobs: fresh.jobs,
+            profiles: fresh.profiles,
+            ..(**current).clone()
+        };
+        *current = Arc::new(next);
+        drop(current);
+
+        self.emit(EventKind::ConfigReloaded {
+            restart_required: restart_required.clone(),
+        });
+        Ok(restart_required)
+    }
+}
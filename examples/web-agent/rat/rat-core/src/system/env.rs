@@ -0,0 +1,38 @@
+//! `GET /system/env`: the agent's environment with secrets masked.
+
+use crate::encoding::{Encoded, Format};
+use crate::state::AppState;
+use axum::extract::State;
+use std::collections::BTreeMap;
+
+const MASK: &str = "********";
+
+/// Environment variables of the agent process. Values of variables whose
+/// name contains one of the configured `system.env_redact` patterns
+/// (case-insensitive) are masked.
+#[utoipa::path(get, path = "/system/env", tag = "system",
+    responses((status = 200, description = "Variable name to value", body = Object)))]
+pub(crate) async fn environment(State(state): State<AppState>, format: Format) -> Encoded<BTreeMap<String, String>> {
+    let patterns: Vec<String> = state
+        .config()
+        .system
+        .env_redact
+        .iter()
+        .map(|p| p.to_ascii_uppercase())
+        .collect();
+
+    let variables = std::env::vars_os()
+        .map(|(name, value)| {
+            let name = name.to_string_lossy().into_owned();
+            let upper = name.to_ascii_uppercase();
+            let value = if patterns.iter().any(|p| upper.contains(p.as_str())) {
+                MASK.to_string()
+            } else {
+                value.to_string_lossy().into_owned()
+            };
+            (name, value)
+        })
+        .collect();
+
+    Encoded(format, variables)
+}
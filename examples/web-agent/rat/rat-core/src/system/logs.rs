@@ -0,0 +1,282 @@
+//! `GET /system/logs`: recent and followed log entries over SSE.
+//!
+//! journald is read through `journalctl --output=json`, syslog and plain
+//! files by tailing them. Files are limited to the configured
+//! `system.log_dirs`.
+
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::{stream::BoxStream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+use tokio::process::Command;
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_LINES: usize = 100;
+const MAX_LINES: usize = 10_000;
+/// How often a followed file is checked for new data
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Bytes read back from the end of a file per requested line
+const BYTES_PER_LINE: u64 = 512;
+const SYSLOG_PATHS: [&str; 2] = ["/var/log/syslog", "/var/log/messages"];
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct LogEntry {
+    /// Unix milliseconds, when the source records it in a parseable form
+    timestamp_ms: Option<u64>,
+    /// journald unit or syslog tag
+    unit: Option<String>,
+    /// Syslog priority, 0 (emerg) to 7 (debug)
+    priority: Option<u8>,
+    message: String,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogSource {
+    #[default]
+    Journal,
+    Syslog,
+    File,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct LogsQuery {
+    #[serde(default)]
+    #[param(inline)]
+    source: LogSource,
+    /// journald unit, or syslog tag (a `.service` suffix is ignored)
+    unit: Option<String>,
+    /// File to read when `source=file`; must be under `system.log_dirs`
+    path: Option<String>,
+    /// Number of recent lines to send first (default 100, at most 10000)
+    lines: Option<usize>,
+    /// Keep the stream open and send new entries as they are written
+    #[serde(default)]
+    follow: bool,
+}
+
+/// Parse one line of `journalctl --output=json`
+fn journal_entry(line: &str) -> Option<LogEntry> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let field = |name: &str| value.get(name).and_then(Value::as_str);
+
+    let message = match value.get("MESSAGE")? {
+        Value::String(message) => message.clone(),
+        // Messages that aren't valid UTF-8 are exported as byte arrays
+        Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes.iter().filter_map(|b| b.as_u64().map(|b| b as u8)).collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        _ => return None,
+    };
+
+    Some(LogEntry {
+        timestamp_ms: field("__REALTIME_TIMESTAMP")
+            .and_then(|micros| micros.parse::<u64>().ok())
+            .map(|micros| micros / 1000),
+        unit: field("_SYSTEMD_UNIT").or(field("SYSLOG_IDENTIFIER")).map(String::from),
+        priority: field("PRIORITY").and_then(|p| p.parse().ok()),
+        message,
+    })
+}
+
+fn journal(
+    unit: Option<String>,
+    lines: usize,
+    follow: bool,
+) -> Result<BoxStream<'static, LogEntry>, ApiError> {
+    let mut cmd = Command::new("journalctl");
+    cmd.args(["--output=json", "--no-pager", "--lines"]).arg(lines.to_string());
+    if let Some(unit) = &unit {
+        cmd.arg("--unit").arg(unit);
+    }
+    if follow {
+        cmd.arg("--follow");
+    }
+    cmd.stdout(Stdio::piped()).stderr(Stdio::null()).kill_on_drop(true);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| ApiError::new(ErrorCode::LogSourceUnavailable, format!("journalctl is not available: {}", e)))?;
+    let stdout = child.stdout.take().unwrap();
+
+    Ok(async_stream::stream! {
+        // Dropping the stream (client gone) kills journalctl
+        let _child = child;
+        let mut lines = BufReader::new(stdout).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(entry) = journal_entry(&line) {
+                yield entry;
+            }
+        }
+    }
+    .boxed())
+}
+
+/// Parse a traditional syslog line, `<timestamp> <host> <tag>[<pid>]: <message>`
+fn syslog_entry(line: String) -> LogEntry {
+    let parsed = line.split_once(": ").and_then(|(header, message)| {
+        let tag = header.rsplit(' ').next()?;
+        let tag = tag.split('[').next().unwrap_or(tag);
+        Some((tag.to_string(), message.to_string()))
+    });
+    match parsed {
+        Some((unit, message)) => LogEntry { timestamp_ms: None, unit: Some(unit), priority: None, message },
+        None => LogEntry { timestamp_ms: None, unit: None, priority: None, message: line },
+    }
+}
+
+/// Split complete lines off the front of `pending`, leaving any
+/// unterminated tail in place
+fn take_lines(pending: &mut Vec<u8>) -> Vec<String> {
+    let Some(end) = pending.iter().rposition(|b| *b == b'\n') else {
+        return Vec::new();
+    };
+    let rest = pending.split_off(end + 1);
+    let complete = std::mem::replace(pending, rest);
+    String::from_utf8_lossy(&complete).lines().map(String::from).collect()
+}
+
+/// The last `lines` lines of a file and, when following, every line
+/// appended after that. The file is reopened on each poll, so rotation and
+/// truncation are picked up.
+fn tail(path: PathBuf, lines: usize, follow: bool) -> impl Stream<Item = String> + Send + 'static {
+    async_stream::stream! {
+        let Ok(mut file) = tokio::fs::File::open(&path).await else { return };
+        let Ok(len) = file.metadata().await.map(|m| m.len()) else { return };
+        let start = len.saturating_sub(lines as u64 * BYTES_PER_LINE);
+        if file.seek(SeekFrom::Start(start)).await.is_err() {
+            return;
+        }
+        let mut pending = Vec::new();
+        if file.read_to_end(&mut pending).await.is_err() {
+            return;
+        }
+        let mut pos = start + pending.len() as u64;
+
+        let mut backlog = take_lines(&mut pending);
+        // Reading from mid-file almost certainly starts mid-line
+        if start > 0 && !backlog.is_empty() {
+            backlog.remove(0);
+        }
+        if !follow && !pending.is_empty() {
+            backlog.push(String::from_utf8_lossy(&pending).into_owned());
+        }
+        let skip = backlog.len().saturating_sub(lines);
+        for line in backlog.into_iter().skip(skip) {
+            yield line;
+        }
+        if !follow {
+            return;
+        }
+
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let Ok(len) = tokio::fs::metadata(&path).await.map(|m| m.len()) else { continue };
+            if len < pos {
+                pos = 0;
+                pending.clear();
+            }
+            if len == pos {
+                continue;
+            }
+            let Ok(mut file) = tokio::fs::File::open(&path).await else { continue };
+            if file.seek(SeekFrom::Start(pos)).await.is_err() {
+                continue;
+            }
+            let mut chunk = Vec::new();
+            if file.read_to_end(&mut chunk).await.is_err() {
+                continue;
+            }
+            pos += chunk.len() as u64;
+            pending.extend_from_slice(&chunk);
+            for line in take_lines(&mut pending) {
+                yield line;
+            }
+        }
+    }
+}
+
+/// Resolve `path` and check it lies inside one of the configured log dirs
+fn allowed_file(state: &AppState, path: &str) -> Result<PathBuf, ApiError> {
+    let resolved = std::fs::canonicalize(path).map_err(|e| ApiError::new(ErrorCode::NotFound, format!("{}: {}", path, e)))?;
+    let allowed = state
+        .config()
+        .system
+        .log_dirs
+        .iter()
+        .filter_map(|dir| std::fs::canonicalize(dir).ok())
+        .any(|dir| resolved.starts_with(dir));
+    if allowed {
+        Ok(resolved)
+    } else {
+        Err(ApiError::new(ErrorCode::Forbidden, format!("{} is outside the configured log_dirs", resolved.display())))
+    }
+}
+
+/// Stream log entries from journald, syslog or a log file
+#[utoipa::path(get, path = "/system/logs", tag = "system",
+    params(LogsQuery),
+    responses(
+        (status = 200, description = "Server-sent events, one JSON LogEntry each", content_type = "text/event-stream"),
+        (status = 400, description = "Missing path for source=file", body = ErrorBody),
+        (status = 403, description = "File outside system.log_dirs", body = ErrorBody),
+        (status = 404, description = "Log file not found", body = ErrorBody),
+        (status = 501, description = "journalctl not available", body = ErrorBody),
+    ))]
+pub(crate) async fn logs(State(state): State<AppState>, Query(query): Query<LogsQuery>) -> Response {
+    let lines = query.lines.unwrap_or(DEFAULT_LINES).min(MAX_LINES);
+    let mut shutdown = state.shared.shutdown.subscribe();
+
+    let entries: Result<BoxStream<'static, LogEntry>, ApiError> = match query.source {
+        LogSource::Journal => journal(query.unit, lines, query.follow),
+        LogSource::Syslog => match SYSLOG_PATHS.iter().find(|p| Path::new(p).exists()) {
+            Some(path) => {
+                let tag = query.unit.map(|unit| unit.trim_end_matches(".service").to_string());
+                Ok(tail(PathBuf::from(path), lines, query.follow)
+                    .map(syslog_entry)
+                    .filter(move |entry| {
+                        let keep = tag.is_none() || entry.unit == tag;
+                        async move { keep }
+                    })
+                    .boxed())
+            }
+            None => Err(ApiError::new(ErrorCode::NotFound, "No syslog file found")),
+        },
+        LogSource::File => match query.path.as_deref() {
+            Some(path) => allowed_file(&state, path).map(|path| {
+                tail(path, lines, query.follow)
+                    .map(|line| LogEntry { timestamp_ms: None, unit: None, priority: None, message: line })
+                    .boxed()
+            }),
+            None => Err(ApiError::new(ErrorCode::BadRequest, "source=file needs a path")),
+        },
+    };
+
+    match entries {
+        Ok(entries) => {
+            // Followed streams never end on their own; end them on shutdown
+            // so they don't hold up the graceful drain
+            let shutting_down = async move {
+                let _ = shutdown.changed().await;
+            };
+            let events = entries.take_until(shutting_down).map(|entry| Ok::<_, Infallible>(Event::default().json_data(entry).unwrap_or_default()));
+            Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
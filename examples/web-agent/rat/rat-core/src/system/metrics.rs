@@ -0,0 +1,231 @@
+//! `WS /system/metrics`: periodic CPU, memory, disk I/O and network samples.
+
+use crate::compression::{self, Deflater, DEFLATE_PROTOCOL};
+use crate::encoding::Format;
+use crate::state::{unix_millis, AppState};
+use axum::{
+    extract::{ws::{Message, WebSocket}, Query, State, WebSocketUpgrade},
+    response::Response,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use sysinfo::{Networks, System};
+use tracing::info;
+use utoipa::ToSchema;
+
+/// Shortest sampling interval a client may ask for
+const MIN_INTERVAL_MS: u64 = 250;
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct MetricsSample {
+    timestamp_ms: u64,
+    /// Average over all cores
+    cpu_percent: f32,
+    per_cpu_percent: Vec<f32>,
+    memory: MemorySample,
+    /// Whole block devices; empty on platforms other than Linux
+    disks: Vec<DiskSample>,
+    network: Vec<NetworkSample>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct MemorySample {
+    total_bytes: u64,
+    used_bytes: u64,
+    swap_total_bytes: u64,
+    swap_used_bytes: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DiskSample {
+    device: String,
+    read_bytes_per_sec: u64,
+    write_bytes_per_sec: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct NetworkSample {
+    interface: String,
+    rx_bytes_per_sec: u64,
+    tx_bytes_per_sec: u64,
+}
+
+/// Cumulative sectors read and written per whole block device, from
+/// /proc/diskstats. Sectors there are always 512 bytes.
+#[cfg(target_os = "linux")]
+fn disk_counters() -> HashMap<String, (u64, u64)> {
+    let Ok(stats) = std::fs::read_to_string("/proc/diskstats") else {
+        return HashMap::new();
+    };
+    stats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let device = *fields.get(2)?;
+            // Partitions have no entry of their own under /sys/block
+            if !std::path::Path::new("/sys/block").join(device).exists() {
+                return None;
+            }
+            let read: u64 = fields.get(5)?.parse().ok()?;
+            let written: u64 = fields.get(9)?.parse().ok()?;
+            Some((device.to_string(), (read * 512, written * 512)))
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disk_counters() -> HashMap<String, (u64, u64)> {
+    HashMap::new()
+}
+
+/// Keeps the previous readings so each sample reports rates since the last
+struct Sampler {
+    system: System,
+    networks: Networks,
+    disks: HashMap<String, (u64, u64)>,
+    last: Instant,
+}
+
+impl Sampler {
+    fn new() -> Self {
+        let mut system = System::new();
+        system.refresh_cpu();
+        Sampler {
+            system,
+            networks: Networks::new_with_refreshed_list(),
+            disks: disk_counters(),
+            last: Instant::now(),
+        }
+    }
+
+    fn sample(&mut self) -> MetricsSample {
+        let elapsed = self.last.elapsed().as_secs_f64().max(0.001);
+        self.last = Instant::now();
+        let per_sec = |bytes: u64| (bytes as f64 / elapsed) as u64;
+
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+        self.networks.refresh();
+
+        let disks = disk_counters();
+        let mut disk_samples: Vec<DiskSample> = disks
+            .iter()
+            .map(|(device, (read, written))| {
+                let (last_read, last_written) = self.disks.get(device).copied().unwrap_or((*read, *written));
+                DiskSample {
+                    device: device.clone(),
+                    read_bytes_per_sec: per_sec(read.saturating_sub(last_read)),
+                    write_bytes_per_sec: per_sec(written.saturating_sub(last_written)),
+                }
+            })
+            .collect();
+        disk_samples.sort_by(|a, b| a.device.cmp(&b.device));
+        self.disks = disks;
+
+        let mut network: Vec<NetworkSample> = self
+            .networks
+            .iter()
+            .map(|(name, data)| NetworkSample {
+                interface: name.clone(),
+                rx_bytes_per_sec: per_sec(data.received()),
+                tx_bytes_per_sec: per_sec(data.transmitted()),
+            })
+            .collect();
+        network.sort_by(|a, b| a.interface.cmp(&b.interface));
+
+        MetricsSample {
+            timestamp_ms: unix_millis(),
+            cpu_percent: self.system.global_cpu_info().cpu_usage(),
+            per_cpu_percent: self.system.cpus().iter().map(|cpu| cpu.cpu_usage()).collect(),
+            memory: MemorySample {
+                total_bytes: self.system.total_memory(),
+                used_bytes: self.system.used_memory(),
+                swap_total_bytes: self.system.total_swap(),
+                swap_used_bytes: self.system.used_swap(),
+            },
+            disks: disk_samples,
+            network,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MetricsQuery {
+    /// Milliseconds between samples; defaults to `system.metrics_interval_ms`
+    interval_ms: Option<u64>,
+    #[serde(default)]
+    format: Format,
+}
+
+/// Stream host metrics samples
+#[utoipa::path(get, path = "/system/metrics", tag = "system",
+    params(
+        ("interval_ms" = Option<u64>, Query, description = "Milliseconds between samples, at least 250"),
+        ("format" = Option<String>, Query, description = "json (default), msgpack or cbor"),
+    ),
+    responses((status = 101, description = "WebSocket upgrade; each frame carries one MetricsSample")))]
+pub(crate) async fn metrics_ws_handler(
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+    Query(query): Query<MetricsQuery>,
+) -> Response {
+    let config = state.config();
+    let interval_ms = query
+        .interval_ms
+        .unwrap_or(config.system.metrics_interval_ms)
+        .max(MIN_INTERVAL_MS);
+    let ws = if config.websocket.compression {
+        ws.protocols([DEFLATE_PROTOCOL])
+    } else {
+        ws
+    };
+    let format = query.format;
+    ws.on_upgrade(move |socket| {
+        handle_metrics_socket(state, socket, Duration::from_millis(interval_ms), format)
+    })
+}
+
+async fn handle_metrics_socket(state: AppState, socket: WebSocket, interval: Duration, format: Format) {
+    info!("Metrics subscriber connected ({} ms interval)", interval.as_millis());
+    let mut deflater = compression::negotiated(socket.protocol()).then(Deflater::new);
+    let (mut ws_tx, mut ws_rx) = socket.split();
+    let mut shutdown_rx = state.shared.shutdown.subscribe();
+
+    let mut sampler = Sampler::new();
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick fires immediately; skip it so the first sample covers
+    // a full interval
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                // Sampling reads /proc, so it runs on the blocking pool
+                let Ok((current, sample)) = tokio::task::spawn_blocking(move || {
+                    let sample = sampler.sample();
+                    (sampler, sample)
+                })
+                .await else {
+                    break;
+                };
+                sampler = current;
+
+                let Ok(bytes) = format.encode(&sample) else { continue };
+                if ws_tx.send(compression::frame(deflater.as_mut(), format, bytes)).await.is_err() {
+                    break;
+                }
+            }
+            msg = ws_rx.next() => {
+                match msg {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    _ => {}
+                }
+            }
+            _ = shutdown_rx.changed() => break,
+        }
+    }
+    info!("Metrics subscriber disconnected");
+}
@@ -0,0 +1,9 @@
+//! Structured views of the host under `/system`, so common admin tasks
+//! don't need `/execute` and output parsing.
+
+pub(crate) mod env;
+pub(crate) mod logs;
+pub(crate) mod metrics;
+pub(crate) mod network;
+pub(crate) mod processes;
+pub(crate) mod users;
@@ -0,0 +1,171 @@
+//! `GET /system/network`: interfaces and listening sockets.
+
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorCode};
+use if_addrs::IfAddr;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct NetworkResponse {
+    interfaces: Vec<InterfaceInfo>,
+    /// Empty on platforms other than Linux
+    listening: Vec<ListeningSocket>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct InterfaceInfo {
+    name: String,
+    loopback: bool,
+    addresses: Vec<InterfaceAddress>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct InterfaceAddress {
+    #[schema(value_type = String)]
+    ip: IpAddr,
+    prefix_len: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ListeningSocket {
+    /// tcp, tcp6, udp or udp6
+    protocol: String,
+    #[schema(value_type = String)]
+    address: IpAddr,
+    port: u16,
+    /// Unknown when the socket belongs to a process the agent can't inspect
+    pid: Option<u32>,
+    process: Option<String>,
+}
+
+fn interfaces() -> std::io::Result<Vec<InterfaceInfo>> {
+    let mut by_name: BTreeMap<String, InterfaceInfo> = BTreeMap::new();
+    for iface in if_addrs::get_if_addrs()? {
+        let (ip, netmask) = match &iface.addr {
+            IfAddr::V4(addr) => (IpAddr::V4(addr.ip), u32::from(addr.netmask).count_ones()),
+            IfAddr::V6(addr) => (IpAddr::V6(addr.ip), u128::from(addr.netmask).count_ones()),
+        };
+        let loopback = iface.is_loopback();
+        let entry = by_name.entry(iface.name.clone()).or_insert_with(|| InterfaceInfo {
+            name: iface.name,
+            loopback,
+            addresses: Vec::new(),
+        });
+        entry.addresses.push(InterfaceAddress { ip, prefix_len: netmask });
+    }
+    Ok(by_name.into_values().collect())
+}
+
+/// Decode a `/proc/net` address such as `0100007F:0035`. Each 32-bit word
+/// of the address is in host (little-endian) order; the port is big-endian.
+#[cfg(target_os = "linux")]
+fn parse_proc_address(field: &str) -> Option<(IpAddr, u16)> {
+    let (addr, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    let mut bytes = Vec::with_capacity(16);
+    for chunk in addr.as_bytes().chunks(8) {
+        let word = u32::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    let ip = match bytes.len() {
+        4 => IpAddr::from(<[u8; 4]>::try_from(bytes.as_slice()).ok()?),
+        16 => IpAddr::from(<[u8; 16]>::try_from(bytes.as_slice()).ok()?),
+        _ => return None,
+    };
+    Some((ip, port))
+}
+
+/// Socket inode → owning PID, for every process whose fds we may read
+#[cfg(target_os = "linux")]
+fn socket_owners() -> std::collections::HashMap<u64, u32> {
+    let mut owners = std::collections::HashMap::new();
+    let Ok(procs) = std::fs::read_dir("/proc") else {
+        return owners;
+    };
+    for entry in procs.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target
+                .to_str()
+                .and_then(|t| t.strip_prefix("socket:["))
+                .and_then(|t| t.strip_suffix(']'))
+                .and_then(|t| t.parse::<u64>().ok());
+            if let Some(inode) = inode {
+                owners.insert(inode, pid);
+            }
+        }
+    }
+    owners
+}
+
+#[cfg(target_os = "linux")]
+fn listening_sockets() -> Vec<ListeningSocket> {
+    // TCP sockets in LISTEN; UDP sockets that are bound but unconnected
+    const TABLES: [(&str, &str); 4] = [("tcp", "0A"), ("tcp6", "0A"), ("udp", "07"), ("udp6", "07")];
+
+    let owners = socket_owners();
+    let mut sockets = Vec::new();
+    for (protocol, listen_state) in TABLES {
+        let Ok(table) = std::fs::read_to_string(format!("/proc/net/{}", protocol)) else {
+            continue;
+        };
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != listen_state {
+                continue;
+            }
+            let Some((address, port)) = parse_proc_address(fields[1]) else {
+                continue;
+            };
+            let pid = fields[9].parse::<u64>().ok().and_then(|inode| owners.get(&inode).copied());
+            let process = pid.and_then(|pid| {
+                std::fs::read_to_string(format!("/proc/{}/comm", pid))
+                    .ok()
+                    .map(|comm| comm.trim_end().to_string())
+            });
+            sockets.push(ListeningSocket {
+                protocol: protocol.to_string(),
+                address,
+                port,
+                pid,
+                process,
+            });
+        }
+    }
+    sockets.sort_by(|a, b| (a.port, &a.protocol).cmp(&(b.port, &b.protocol)));
+    sockets
+}
+
+#[cfg(not(target_os = "linux"))]
+fn listening_sockets() -> Vec<ListeningSocket> {
+    Vec::new()
+}
+
+/// Network interfaces with their addresses, and listening sockets
+#[utoipa::path(get, path = "/system/network", tag = "system",
+    responses((status = 200, body = NetworkResponse)))]
+pub(crate) async fn network(format: Format) -> Result<Encoded<NetworkResponse>, ApiError> {
+    let response = tokio::task::spawn_blocking(|| {
+        interfaces().map(|interfaces| NetworkResponse {
+            interfaces,
+            listening: listening_sockets(),
+        })
+    })
+    .await
+    .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Network scan failed: {}", e)))?
+    .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Failed to list interfaces: {}", e)))?;
+
+    Ok(Encoded(format, response))
+}
@@ -0,0 +1,190 @@
+//! `GET /system/processes` and `POST /system/processes/:pid/signal`.
+
+use crate::encoding::{Decoded, Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::events::EventKind;
+use crate::query::SortOrder;
+use crate::state::AppState;
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use sysinfo::{System, Users};
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ProcessInfo {
+    pid: u32,
+    parent_pid: Option<u32>,
+    name: String,
+    command: Vec<String>,
+    status: String,
+    user: Option<String>,
+    /// Percent of one core, sampled over a short window
+    cpu_percent: f32,
+    memory_bytes: u64,
+    /// Unix seconds
+    start_time: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ProcessSort {
+    #[default]
+    Pid,
+    Name,
+    Cpu,
+    Memory,
+    StartTime,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct ProcessQuery {
+    /// Case-insensitive substring of the process name
+    name: Option<String>,
+    /// Exact owning user name
+    user: Option<String>,
+    #[serde(default)]
+    #[param(inline)]
+    sort: ProcessSort,
+    #[serde(default)]
+    #[param(inline)]
+    order: SortOrder,
+    limit: Option<usize>,
+}
+
+/// Snapshot every process; CPU usage needs two samples, so this blocks for
+/// sysinfo's minimum update interval
+fn snapshot() -> Vec<ProcessInfo> {
+    let mut system = System::new();
+    system.refresh_processes();
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    system.refresh_processes();
+    let users = Users::new_with_refreshed_list();
+
+    system
+        .processes()
+        .values()
+        .map(|process| ProcessInfo {
+            pid: process.pid().as_u32(),
+            parent_pid: process.parent().map(|pid| pid.as_u32()),
+            name: process.name().to_string(),
+            command: process.cmd().to_vec(),
+            status: process.status().to_string(),
+            user: process
+                .user_id()
+                .and_then(|uid| users.get_user_by_id(uid))
+                .map(|user| user.name().to_string()),
+            cpu_percent: process.cpu_usage(),
+            memory_bytes: process.memory(),
+            start_time: process.start_time(),
+        })
+        .collect()
+}
+
+/// List processes on the host
+#[utoipa::path(get, path = "/system/processes", tag = "system",
+    params(ProcessQuery),
+    responses((status = 200, body = [ProcessInfo])))]
+pub(crate) async fn list_processes(
+    format: Format,
+    Query(query): Query<ProcessQuery>,
+) -> Result<Encoded<Vec<ProcessInfo>>, ApiError> {
+    let mut processes = tokio::task::spawn_blocking(snapshot)
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Process scan failed: {}", e)))?;
+
+    if let Some(name) = &query.name {
+        let name = name.to_lowercase();
+        processes.retain(|p| p.name.to_lowercase().contains(&name));
+    }
+    if let Some(user) = &query.user {
+        processes.retain(|p| p.user.as_deref() == Some(user.as_str()));
+    }
+
+    processes.sort_by(|a, b| {
+        let ordering = match query.sort {
+            ProcessSort::Pid => a.pid.cmp(&b.pid),
+            ProcessSort::Name => a.name.cmp(&b.name),
+            ProcessSort::Cpu => a.cpu_percent.partial_cmp(&b.cpu_percent).unwrap_or(Ordering::Equal),
+            ProcessSort::Memory => a.memory_bytes.cmp(&b.memory_bytes),
+            ProcessSort::StartTime => a.start_time.cmp(&b.start_time),
+        };
+        query.order.apply(ordering)
+    });
+    if let Some(limit) = query.limit {
+        processes.truncate(limit);
+    }
+
+    Ok(Encoded(format, processes))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SignalRequest {
+    /// Signal name with or without the SIG prefix (`TERM`, `SIGKILL`), or
+    /// its number
+    signal: String,
+}
+
+fn parse_signal(name: &str) -> Option<libc::c_int> {
+    if let Ok(number) = name.parse::<libc::c_int>() {
+        return (number > 0).then_some(number);
+    }
+    let upper = name.to_ascii_uppercase();
+    let signal = match upper.strip_prefix("SIG").unwrap_or(&upper) {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "TERM" => libc::SIGTERM,
+        "CONT" => libc::SIGCONT,
+        "STOP" => libc::SIGSTOP,
+        _ => return None,
+    };
+    Some(signal)
+}
+
+/// Send a signal to a process
+#[utoipa::path(post, path = "/system/processes/{pid}/signal", tag = "system",
+    params(("pid" = u32, Path)),
+    request_body = SignalRequest,
+    responses(
+        (status = 200, description = "Signal delivered"),
+        (status = 400, description = "Unknown signal or invalid PID", body = ErrorBody),
+        (status = 403, description = "Not permitted to signal this process", body = ErrorBody),
+        (status = 404, description = "No such process", body = ErrorBody),
+    ))]
+pub(crate) async fn signal_process(
+    State(state): State<AppState>,
+    format: Format,
+    Path(pid): Path<u32>,
+    Decoded(request): Decoded<SignalRequest>,
+) -> Result<Encoded<serde_json::Value>, ApiError> {
+    let signal = parse_signal(&request.signal)
+        .ok_or_else(|| ApiError::new(ErrorCode::UnknownSignal, format!("Unknown signal {:?}", request.signal)))?;
+    // 0 and values past i32::MAX would address process groups
+    let target = libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|pid| *pid > 0)
+        .ok_or_else(|| ApiError::new(ErrorCode::BadRequest, format!("Invalid PID {}", pid)))?;
+    if pid == std::process::id() {
+        return Err(ApiError::new(ErrorCode::BadRequest, "Refusing to signal the agent itself"));
+    }
+
+    info!("Sending signal {} to PID {}", signal, pid);
+    // SAFETY: kill(2) has no memory-safety preconditions
+    if unsafe { libc::kill(target, signal) } != 0 {
+        let error = std::io::Error::last_os_error();
+        let code = match error.raw_os_error() {
+            Some(libc::ESRCH) => ErrorCode::ProcessNotFound,
+            Some(libc::EPERM) => ErrorCode::Forbidden,
+            _ => ErrorCode::BadRequest,
+        };
+        return Err(ApiError::new(code, format!("Failed to signal PID {}: {}", pid, error)));
+    }
+
+    state.emit(EventKind::ProcessSignalled { pid, signal });
+    Ok(Encoded(format, serde_json::json!({"status": "signalled", "pid": pid, "signal": signal})))
+}
@@ -0,0 +1,173 @@
+//! `GET /system/users`: logged-in users and recent logins from utmp/wtmp.
+
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorCode};
+use axum::extract::Query;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_RECENT: usize = 20;
+const MAX_RECENT: usize = 1000;
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct LoginRecord {
+    user: String,
+    tty: String,
+    /// Remote host, for network logins
+    host: Option<String>,
+    pid: i32,
+    login_ms: u64,
+    /// Unset while the login is still open, or if the machine went down
+    /// before it was closed
+    logout_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct UsersResponse {
+    /// Empty on platforms other than Linux
+    logged_in: Vec<LoginRecord>,
+    /// Newest first
+    recent: Vec<LoginRecord>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct UsersQuery {
+    /// Number of recent logins to return (default 20, at most 1000)
+    recent: Option<usize>,
+}
+
+#[cfg(target_os = "linux")]
+mod utmp {
+    use super::LoginRecord;
+
+    /// `struct utmp` on Linux, the same size on 32- and 64-bit
+    const RECORD_SIZE: usize = 384;
+    const BOOT_TIME: i16 = 2;
+    const USER_PROCESS: i16 = 7;
+    const DEAD_PROCESS: i16 = 8;
+
+    pub(super) const CURRENT: [&str; 2] = ["/run/utmp", "/var/run/utmp"];
+    pub(super) const HISTORY: &str = "/var/log/wtmp";
+
+    pub(super) struct Record {
+        pub(super) kind: i16,
+        pub(super) pid: i32,
+        pub(super) line: String,
+        pub(super) user: String,
+        pub(super) host: String,
+        pub(super) time_ms: u64,
+    }
+
+    fn text(bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }
+
+    fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+        i32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn parse(bytes: &[u8]) -> Record {
+        // ut_type, pad, ut_pid, ut_line[32], ut_id[4], ut_user[32],
+        // ut_host[256], ut_exit, ut_session, ut_tv { sec, usec }, ...
+        let seconds = i32_at(bytes, 340).max(0) as u64;
+        let micros = i32_at(bytes, 344).max(0) as u64;
+        Record {
+            kind: i16::from_ne_bytes([bytes[0], bytes[1]]),
+            pid: i32_at(bytes, 4),
+            line: text(&bytes[8..40]),
+            user: text(&bytes[44..76]),
+            host: text(&bytes[76..332]),
+            time_ms: seconds * 1000 + micros / 1000,
+        }
+    }
+
+    pub(super) fn read(path: &str) -> std::io::Result<Vec<Record>> {
+        let data = std::fs::read(path)?;
+        Ok(data.chunks_exact(RECORD_SIZE).map(parse).collect())
+    }
+
+    fn login(record: &Record) -> LoginRecord {
+        LoginRecord {
+            user: record.user.clone(),
+            tty: record.line.clone(),
+            host: (!record.host.is_empty()).then(|| record.host.clone()),
+            pid: record.pid,
+            login_ms: record.time_ms,
+            logout_ms: None,
+        }
+    }
+
+    pub(super) fn logged_in(records: &[Record]) -> Vec<LoginRecord> {
+        records
+            .iter()
+            .filter(|r| r.kind == USER_PROCESS && !r.user.is_empty())
+            .map(login)
+            .collect()
+    }
+
+    /// Pair each login in wtmp with the logout on the same tty, if any
+    pub(super) fn history(records: &[Record]) -> Vec<LoginRecord> {
+        let mut logins: Vec<LoginRecord> = Vec::new();
+        let mut open: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+        for record in records {
+            match record.kind {
+                USER_PROCESS if !record.user.is_empty() => {
+                    open.insert(&record.line, logins.len());
+                    logins.push(login(record));
+                }
+                DEAD_PROCESS => {
+                    if let Some(index) = open.remove(record.line.as_str()) {
+                        logins[index].logout_ms = Some(record.time_ms);
+                    }
+                }
+                // Logins still open at a reboot never got a logout record
+                BOOT_TIME => open.clear(),
+                _ => {}
+            }
+        }
+        logins
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn collect(recent: usize) -> UsersResponse {
+    let current = utmp::CURRENT
+        .iter()
+        .find_map(|path| utmp::read(path).ok())
+        .unwrap_or_default();
+    // wtmp is often missing in containers; that's not an error
+    let history = utmp::read(utmp::HISTORY).unwrap_or_default();
+
+    let mut recent_logins = utmp::history(&history);
+    recent_logins.reverse();
+    recent_logins.truncate(recent);
+
+    UsersResponse {
+        logged_in: utmp::logged_in(&current),
+        recent: recent_logins,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn collect(_recent: usize) -> UsersResponse {
+    UsersResponse {
+        logged_in: Vec::new(),
+        recent: Vec::new(),
+    }
+}
+
+/// Users logged in now and the most recent logins
+#[utoipa::path(get, path = "/system/users", tag = "system",
+    params(UsersQuery),
+    responses((status = 200, body = UsersResponse)))]
+pub(crate) async fn users(
+    format: Format,
+    Query(query): Query<UsersQuery>,
+) -> Result<Encoded<UsersResponse>, ApiError> {
+    let recent = query.recent.unwrap_or(DEFAULT_RECENT).min(MAX_RECENT);
+    let response = tokio::task::spawn_blocking(move || collect(recent))
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::Internal, format!("Login scan failed: {}", e)))?;
+    Ok(Encoded(format, response))
+}
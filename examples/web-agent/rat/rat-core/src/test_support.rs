@@ -0,0 +1,298 @@
+//! In-process server and a scripted PTY for integration tests, enabled by
+//! the `test-support` feature.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use rat_core::test_support::{ScriptedPty, TestServer};
+//!
+//! let server = TestServer::start(ScriptedPty::echo(), Default::default()).await;
+//! let health = reqwest::get(server.url("/v1/health")).await.unwrap();
+//! assert!(health.status().is_success());
+//! # }
+//! ```
+
+use crate::config::Config;
+use crate::recording::{Direction, Recording};
+use crate::state::AppState;
+use portable_pty::{
+    Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, PtySystem, SlavePty,
+};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Turns one chunk of terminal input into the "shell's" output
+type Script = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// A PTY system with no process behind it: every write to the master is
+/// handed to a script, and whatever the script returns becomes readable
+/// output. Output travels through a real pipe, so the PTY pumps poll and
+/// cancel exactly as they do on a real PTY.
+#[derive(Clone)]
+pub struct ScriptedPty {
+    script: Script,
+    banner: Vec<u8>,
+    resizes: Arc<Mutex<Vec<PtySize>>>,
+}
+
+impl ScriptedPty {
+    pub fn new<F>(script: F) -> Self
+    where
+        F: Fn(&[u8]) -> Vec<u8> + Send + Sync + 'static,
+    {
+        ScriptedPty {
+            script: Arc::new(script),
+            banner: Vec::new(),
+            resizes: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Echo input back, like a terminal with nothing running in it
+    pub fn echo() -> Self {
+        ScriptedPty::new(|input| input.to_vec())
+    }
+
+    /// Replay a recorded attachment (see `rat_core::recording`): output
+    /// recorded before the first input becomes the banner, and each input
+    /// written gets the output that was recorded after the matching input.
+    /// Control messages never reach the PTY, so they don't count as input.
+    pub fn replay(recording: &Recording) -> Self {
+        let mut banner = Vec::new();
+        let mut replies: VecDeque<Vec<u8>> = VecDeque::new();
+        for frame in &recording.frames {
+            match frame.direction {
+                Direction::Input if !frame.is_control() => replies.push_back(Vec::new()),
+                Direction::Input => {}
+                Direction::Output => match replies.back_mut() {
+                    Some(reply) => reply.extend_from_slice(&frame.data),
+                    None => banner.extend_from_slice(&frame.data),
+                },
+            }
+        }
+        let replies = Mutex::new(replies);
+        ScriptedPty::new(move |_| replies.lock().unwrap().pop_front().unwrap_or_default()).with_banner(banner)
+    }
+
+    /// Output waiting in every new session before any input, like a prompt
+    pub fn with_banner(mut self, banner: impl Into<Vec<u8>>) -> Self {
+        self.banner = banner.into();
+        self
+    }
+
+    /// Every size any of this system's PTYs was resized to, in order
+    pub fn resizes(&self) -> Vec<PtySize> {
+        self.resizes.lock().unwrap().clone()
+    }
+}
+
+fn pipe() -> std::io::Result<(File, File)> {
+    let mut fds: [libc::c_int; 2] = [0; 2];
+    // SAFETY: fds has room for the two descriptors pipe(2) writes
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: both descriptors were just created and nothing else owns them
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+impl PtySystem for ScriptedPty {
+    fn openpty(&self, size: PtySize) -> anyhow::Result<PtyPair> {
+        let (output_reader, mut output_writer) = pipe()?;
+        output_writer.write_all(&self.banner)?;
+        Ok(PtyPair {
+            slave: Box::new(ScriptedSlave),
+            master: Box::new(ScriptedMaster {
+                script: self.script.clone(),
+                output_reader,
+                output_writer: Mutex::new(Some(output_writer)),
+                size: Mutex::new(size),
+                resizes: self.resizes.clone(),
+            }),
+        })
+    }
+}
+
+struct ScriptedMaster {
+    script: Script,
+    output_reader: File,
+    output_writer: Mutex<Option<File>>,
+    size: Mutex<PtySize>,
+    resizes: Arc<Mutex<Vec<PtySize>>>,
+}
+
+impl MasterPty for ScriptedMaster {
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        *self.size.lock().unwrap() = size;
+        self.resizes.lock().unwrap().push(size);
+        Ok(())
+    }
+
+    fn get_size(&self) -> anyhow::Result<PtySize> {
+        Ok(*self.size.lock().unwrap())
+    }
+
+    fn try_clone_reader(&self) -> anyhow::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(self.output_reader.try_clone()?))
+    }
+
+    fn take_writer(&self) -> anyhow::Result<Box<dyn Write + Send>> {
+        let output = self
+            .output_writer
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Writer already taken"))?;
+        Ok(Box::new(ScriptWriter {
+            script: self.script.clone(),
+            output,
+        }))
+    }
+
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        None
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        Some(self.output_reader.as_raw_fd())
+    }
+
+    fn tty_name(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+}
+
+/// Runs the script on each write and queues its reply as PTY output
+struct ScriptWriter {
+    script: Script,
+    output: File,
+}
+
+impl Write for ScriptWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let reply = (self.script)(buf);
+        self.output.write_all(&reply)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+struct ScriptedSlave;
+
+impl SlavePty for ScriptedSlave {
+    fn spawn_command(&self, _cmd: CommandBuilder) -> anyhow::Result<Box<dyn Child + Send + Sync>> {
+        Ok(Box::new(ScriptedChild {
+            exited: Arc::new(AtomicBool::new(false)),
+        }))
+    }
+}
+
+/// Stands in for the shell process; it "exits" when killed
+#[derive(Debug, Clone)]
+struct ScriptedChild {
+    exited: Arc<AtomicBool>,
+}
+
+impl ChildKiller for ScriptedChild {
+    fn kill(&mut self) -> std::io::Result<()> {
+        self.exited.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+impl Child for ScriptedChild {
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        Ok(self
+            .exited
+            .load(Ordering::SeqCst)
+            .then(|| ExitStatus::with_exit_code(0)))
+    }
+
+    fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        while !self.exited.load(Ordering::SeqCst) {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(ExitStatus::with_exit_code(0))
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// The agent's router served on an ephemeral localhost port. The server
+/// task is aborted when this is dropped.
+pub struct TestServer {
+    pub state: AppState,
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Serve with sessions backed by `pty`
+    pub async fn start(pty: ScriptedPty, config: Config) -> TestServer {
+        let state = AppState::new()
+            .with_pty_system(move || Box::new(pty.clone()) as Box<dyn PtySystem + Send>);
+        TestServer::with_state(state, config).await
+    }
+
+    /// Serve an already configured state, e.g. one with plugins registered
+    pub async fn with_state(state: AppState, config: Config) -> TestServer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind test listener");
+        let addr = listener.local_addr().expect("test listener has no address");
+        let header_read_timeout = Duration::from_secs(config.server.header_read_timeout_secs);
+        let app = crate::build_router(state.clone(), config);
+        let task = tokio::spawn(crate::serve(listener, app, header_read_timeout, std::future::pending()));
+        TestServer { state, addr, task }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn ws_url(&self, path: &str) -> String {
+        format!("ws://{}{}", self.addr, path)
+    }
+
+    /// Create a session through the API and return its ID
+    pub async fn create_session(&self) -> String {
+        let response: serde_json::Value = reqwest::Client::new()
+            .post(self.url("/v1/session/create"))
+            .send()
+            .await
+            .expect("session create request failed")
+            .json()
+            .await
+            .expect("session create returned no JSON");
+        response["session_id"].as_str().expect("no session_id in response").to_string()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Body for `/execute` and `/jobs` that runs `script` with `sh -c`
+pub fn shell_command(script: &str) -> serde_json::Value {
+    serde_json::json!({"command": "sh", "args": ["-c", script]})
+}
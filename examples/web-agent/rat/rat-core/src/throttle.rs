@@ -0,0 +1,72 @@
+//! Bandwidth limits on shell output, so one session dumping a huge file
+//! can't saturate a shared tunnel and starve every other connection.
+//!
+//! Each attachment has its own bucket for `limits.session_output_bytes_per_sec`
+//! and all of them share one for `limits.total_output_bytes_per_sec`. A
+//! frame is sent once both buckets have refilled enough to cover it; the
+//! PTY pumps' bounded queues then push back on the shell. Rates are read
+//! from the live config, so reloads apply to attached sessions.
+
+use crate::state::AppState;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket holding up to one second's worth of bytes. Sends may
+/// overdraw it; the debt is paid off by waiting.
+pub(crate) struct Throttle {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle {
+            bucket: Mutex::new(Bucket {
+                // Full; capped to one second's worth on first use
+                available: f64::INFINITY,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl Throttle {
+    /// Take `bytes` from the bucket and return how long to wait before
+    /// sending them at `rate` bytes per second
+    fn reserve(&self, bytes: usize, rate: u64) -> Duration {
+        let rate = rate.max(1) as f64;
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.available = (bucket.available + elapsed * rate).min(rate) - bytes as f64;
+        bucket.refilled_at = now;
+        if bucket.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.available / rate)
+        }
+    }
+}
+
+/// Wait until `bytes` more of a session's output may be sent, under the
+/// session's own limit and the server-wide one
+pub(crate) async fn throttle_output(state: &AppState, session: &Throttle, bytes: usize) {
+    let (per_session, total) = {
+        let config = state.config();
+        (config.limits.session_output_bytes_per_sec, config.limits.total_output_bytes_per_sec)
+    };
+    let mut wait = Duration::ZERO;
+    if let Some(rate) = per_session {
+        wait = wait.max(session.reserve(bytes, rate));
+    }
+    if let Some(rate) = total {
+        wait = wait.max(state.shared.output_throttle.reserve(bytes, rate));
+    }
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
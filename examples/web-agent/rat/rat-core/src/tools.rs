@@ -0,0 +1,419 @@
+//! The agent's operations as tools for LLM agents: a name, a description
+//! and a JSON Schema for the arguments of each, generated from the same
+//! types the HTTP handlers decode, plus a dispatcher that runs them.
+//! Served over MCP by `mcp`, and as a function-calling manifest at
+//! `GET /tools` with `POST /tools/:name` to run one. Plugin tools are
+//! listed after the built-ins as `<plugin>_<tool>` and run by sending their
+//! request to the plugin's router.
+
+use crate::auth::Caller;
+use crate::encoding::{Decoded, Encoded, Format};
+use crate::error::{ApiError, ErrorBody, ErrorCode};
+use crate::exec::{run_command, CommandRequest};
+use crate::plugin::{Plugin, ToolSpec};
+use crate::session::{base_url, remove_session, shell_ws_url, spawn_session, SessionEnd, SessionInfo, SessionOptions};
+use crate::state::AppState;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, Method, Request};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tower::ServiceExt;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+/// Bytes `read_file` returns when the caller doesn't set `max_bytes`
+const DEFAULT_READ_LIMIT: u64 = 1024 * 1024;
+
+/// Bytes of a plugin tool's response passed on to the model
+const PLUGIN_OUTPUT_LIMIT: usize = 1024 * 1024;
+
+pub(crate) struct Tool {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    /// JSON Schema for the arguments object
+    pub(crate) input_schema: Value,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct ReadFileArgs {
+    /// Absolute path, or relative to the agent's working directory
+    path: String,
+    /// Stop reading after this many bytes [default: 1048576]
+    max_bytes: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct WriteFileArgs {
+    /// Absolute path, or relative to the agent's working directory
+    path: String,
+    content: String,
+    /// Append to the file instead of replacing it
+    append: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(crate) struct SessionArgs {
+    session_id: String,
+}
+
+/// Result of a tool call: text for the model, and whether it describes a
+/// failure the model should react to
+pub(crate) struct ToolOutput {
+    pub(crate) text: String,
+    pub(crate) is_error: bool,
+}
+
+impl ToolOutput {
+    fn json(value: Value) -> Self {
+        ToolOutput {
+            text: serde_json::to_string(&value).unwrap_or_default(),
+            is_error: false,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        ToolOutput {
+            text: message.into(),
+            is_error: true,
+        }
+    }
+}
+
+fn schema_of<T: ToSchema<'static>>() -> Value {
+    serde_json::to_value(T::schema().1).unwrap_or_else(|_| json!({"type": "object"}))
+}
+
+fn no_arguments() -> Value {
+    json!({"type": "object", "properties": {}})
+}
+
+/// Every tool, in the order they are listed to clients: the built-ins,
+/// then each plugin's
+pub(crate) fn tools(state: &AppState) -> Vec<Tool> {
+    let mut tools = builtin_tools();
+    for plugin in state.plugins() {
+        tools.extend(plugin.tools().into_iter().map(|spec| Tool {
+            name: plugin_tool_name(plugin.name(), &spec),
+            description: spec.description,
+            input_schema: spec.input_schema,
+        }));
+    }
+    tools
+}
+
+/// A plugin tool's name, as in its OpenAPI operation ID
+fn plugin_tool_name(plugin: &str, spec: &ToolSpec) -> String {
+    format!("{}_{}", plugin, spec.name)
+}
+
+fn builtin_tools() -> Vec<Tool> {
+    vec![
+        Tool {
+            name: "execute".into(),
+            description: "Run a command on the agent's host without a shell and wait for it to exit. \
+                Returns the exit code, stdout and stderr.".into(),
+            input_schema: schema_of::<CommandRequest>(),
+        },
+        Tool {
+            name: "read_file".into(),
+            description: "Read a file on the agent's host as UTF-8 text; invalid bytes are replaced. \
+                Returns the content, the file's size and whether the content was truncated.".into(),
+            input_schema: schema_of::<ReadFileArgs>(),
+        },
+        Tool {
+            name: "write_file".into(),
+            description: "Write text to a file on the agent's host, creating it if needed.".into(),
+            input_schema: schema_of::<WriteFileArgs>(),
+        },
+        Tool {
+            name: "list_sessions".into(),
+            description: "List the agent's interactive PTY sessions with their traffic counters.".into(),
+            input_schema: no_arguments(),
+        },
+        Tool {
+            name: "create_session".into(),
+            description: "Start an interactive bash session in a PTY. Returns its id and WebSocket URL.".into(),
+            input_schema: no_arguments(),
+        },
+        Tool {
+            name: "attach_session".into(),
+            description: "Get the URLs a human or terminal client uses to attach to a PTY session: \
+                the shell WebSocket and the browser terminal. One client can be attached at a time. \
+                For non-interactive work, prefer execute.".into(),
+            input_schema: schema_of::<SessionArgs>(),
+        },
+        Tool {
+            name: "stop_session".into(),
+            description: "Stop a PTY session and the shell running in it.".into(),
+            input_schema: schema_of::<SessionArgs>(),
+        },
+    ]
+}
+
+fn arguments<T: DeserializeOwned>(arguments: Value) -> Result<T, String> {
+    serde_json::from_value(arguments).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+/// Run a tool on behalf of `caller`. `Err` means the call itself was
+/// invalid (unknown tool, bad arguments); a tool that ran and failed
+/// returns an error `ToolOutput`.
+pub(crate) async fn call(state: &AppState, caller: &Caller, name: &str, args: Value) -> Result<ToolOutput, String> {
+    info!("Tool call: {}", name);
+    let output = match name {
+        "execute" => {
+            let request: CommandRequest = arguments(args)?;
+            match run_command(state, caller, &request).await {
+                Ok(output) => ToolOutput {
+                    is_error: !output.status.success(),
+                    ..ToolOutput::json(json!({
+                        "exit_code": output.status.code(),
+                        "stdout": String::from_utf8_lossy(&output.stdout),
+                        "stderr": String::from_utf8_lossy(&output.stderr),
+                    }))
+                },
+                Err(e) => ToolOutput::error(e.message),
+            }
+        }
+        "read_file" => match read_file(arguments(args)?).await {
+            Ok(value) => ToolOutput::json(value),
+            Err(e) => ToolOutput::error(e),
+        },
+        "write_file" => match write_file(arguments(args)?).await {
+            Ok(value) => ToolOutput::json(value),
+            Err(e) => ToolOutput::error(e),
+        },
+        "list_sessions" => {
+            let list: Vec<SessionInfo> = state
+                .shared
+                .sessions
+                .iter()
+                .map(|entry| SessionInfo::from_session(&entry.value().lock().unwrap()))
+                .collect();
+            ToolOutput::json(serde_json::to_value(list).unwrap_or_default())
+        }
+        "create_session" => match spawn_session(state, caller, &SessionOptions::default()) {
+            Ok(session_id) => {
+                let ws_url = shell_ws_url(state, &session_id).await;
+                ToolOutput::json(json!({"session_id": session_id, "ws_url": ws_url}))
+            }
+            Err(e) => ToolOutput::error(e.message),
+        },
+        "attach_session" => {
+            let SessionArgs { session_id } = arguments(args)?;
+            let attached = state
+                .shared
+                .sessions
+                .get(&session_id)
+                .map(|entry| entry.value().lock().unwrap().attached());
+            match attached {
+                None => ToolOutput::error("Session not found"),
+                Some(true) => ToolOutput::error("Session is already attached"),
+                Some(false) => {
+                    ToolOutput::json(json!({
+                        "ws_url": shell_ws_url(state, &session_id).await,
+                        "terminal_url": format!("{}/terminal/{}", base_url(state).await, session_id),
+                        "session_id": session_id,
+                    }))
+                }
+            }
+        }
+        "stop_session" => {
+            let SessionArgs { session_id } = arguments(args)?;
+            if remove_session(state, &session_id, SessionEnd::Stopped) {
+                ToolOutput::json(json!({"status": "stopped"}))
+            } else {
+                ToolOutput::error("Session not found")
+            }
+        }
+        _ => {
+            let (plugin, spec) = plugin_tool(state, name).ok_or_else(|| format!("Unknown tool: {}", name))?;
+            call_plugin(&*plugin, caller, &spec, args).await?
+        }
+    };
+    Ok(output)
+}
+
+/// The plugin and spec behind a `<plugin>_<tool>` name
+fn plugin_tool(state: &AppState, name: &str) -> Option<(Arc<dyn Plugin>, ToolSpec)> {
+    state.plugins().iter().find_map(|plugin| {
+        let spec = plugin.tools().into_iter().find(|spec| plugin_tool_name(plugin.name(), spec) == name)?;
+        Some((plugin.clone(), spec))
+    })
+}
+
+/// Run a plugin tool through the plugin's router: the arguments go in the
+/// query string for GET and DELETE, and as a JSON body otherwise. A non-2xx
+/// response is a failed call; its body is passed on either way.
+async fn call_plugin(plugin: &dyn Plugin, caller: &Caller, spec: &ToolSpec, args: Value) -> Result<ToolOutput, String> {
+    let Ok(method) = Method::from_bytes(spec.method.to_ascii_uppercase().as_bytes()) else {
+        return Ok(ToolOutput::error(format!("Plugin tool has an invalid method: {}", spec.method)));
+    };
+    let builder = Request::builder().extension(caller.clone());
+    let request = if method == Method::GET || method == Method::DELETE {
+        let query = serde_urlencoded::to_string(&args).map_err(|e| format!("Invalid arguments: {}", e))?;
+        let uri = if query.is_empty() { spec.path.clone() } else { format!("{}?{}", spec.path, query) };
+        builder.method(method).uri(uri).body(Body::empty())
+    } else {
+        builder
+            .method(method)
+            .uri(&spec.path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(args.to_string()))
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return Ok(ToolOutput::error(format!("Invalid plugin request: {}", e))),
+    };
+
+    let response = match plugin.router().oneshot(request).await {
+        Ok(response) => response,
+        Err(e) => match e {},
+    };
+    let status = response.status();
+    Ok(match axum::body::to_bytes(response.into_body(), PLUGIN_OUTPUT_LIMIT).await {
+        Ok(body) => ToolOutput {
+            text: String::from_utf8_lossy(&body).into_owned(),
+            is_error: !status.is_success(),
+        },
+        Err(e) => ToolOutput::error(format!("Failed to read the plugin's response: {}", e)),
+    })
+}
+
+async fn read_file(args: ReadFileArgs) -> Result<Value, String> {
+    let limit = args.max_bytes.unwrap_or(DEFAULT_READ_LIMIT);
+    let read = async {
+        let file = tokio::fs::File::open(&args.path).await?;
+        let size = file.metadata().await?.len();
+        let mut data = Vec::new();
+        file.take(limit).read_to_end(&mut data).await?;
+        Ok::<_, std::io::Error>((size, data))
+    };
+    let (size, data) = read
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", args.path, e))?;
+
+    Ok(json!({
+        "path": args.path,
+        "size": size,
+        "truncated": (data.len() as u64) < size,
+        "content": String::from_utf8_lossy(&data),
+    }))
+}
+
+async fn write_file(args: WriteFileArgs) -> Result<Value, String> {
+    let append = args.append.unwrap_or(false);
+    let write = async {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&args.path)
+            .await?;
+        file.write_all(args.content.as_bytes()).await?;
+        file.flush().await
+    };
+    write
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", args.path, e))?;
+
+    info!("Wrote {} bytes to {}", args.content.len(), args.path);
+    Ok(json!({"path": args.path, "bytes_written": args.content.len()}))
+}
+
+/// Shape of the tool definitions in `GET /tools`
+#[derive(Deserialize, Clone, Copy, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ToolFormat {
+    /// OpenAI function calling: `{"type": "function", "function": {...}}`
+    #[default]
+    Openai,
+    /// Anthropic tool use: `{"name", "description", "input_schema"}`
+    Anthropic,
+    /// MCP `tools/list` entries: `{"name", "description", "inputSchema"}`
+    Mcp,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub(crate) struct ToolsQuery {
+    #[serde(default)]
+    #[param(inline)]
+    format: ToolFormat,
+}
+
+impl Tool {
+    pub(crate) fn definition(&self, format: ToolFormat) -> Value {
+        match format {
+            ToolFormat::Openai => json!({
+                "type": "function",
+                "function": {
+                    "name": self.name,
+                    "description": self.description,
+                    "parameters": self.input_schema,
+                },
+            }),
+            ToolFormat::Anthropic => json!({
+                "name": self.name,
+                "description": self.description,
+                "input_schema": self.input_schema,
+            }),
+            ToolFormat::Mcp => json!({
+                "name": self.name,
+                "description": self.description,
+                "inputSchema": self.input_schema,
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ToolCallResponse {
+    /// The tool's result, JSON text for most tools; an error message when
+    /// `is_error` is set
+    output: String,
+    is_error: bool,
+}
+
+/// Tool definitions for function-calling LLMs
+#[utoipa::path(get, path = "/tools", tag = "tools",
+    params(ToolsQuery),
+    responses((status = 200, description = "One definition per tool, ready to pass to the model; run a call with POST /tools/{name}", body = [Object])))]
+pub(crate) async fn list_tools(
+    State(state): State<AppState>,
+    format: Format,
+    Query(query): Query<ToolsQuery>,
+) -> Encoded<Vec<Value>> {
+    Encoded(format, tools(&state).iter().map(|tool| tool.definition(query.format)).collect())
+}
+
+/// Run a tool call the model made
+#[utoipa::path(post, path = "/tools/{name}", tag = "tools",
+    params(("name" = String, Path)),
+    request_body(content = Object, description = "The call's arguments; `{}` for tools without any"),
+    responses(
+        (status = 200, description = "The tool ran; failures are reported in `is_error`", body = ToolCallResponse),
+        (status = 404, description = "No such tool", body = ErrorBody),
+        (status = 422, description = "Arguments don't match the tool's schema", body = ErrorBody),
+    ))]
+pub(crate) async fn call_tool(
+    State(state): State<AppState>,
+    format: Format,
+    caller: Caller,
+    Path(name): Path<String>,
+    Decoded(args): Decoded<Value>,
+) -> Result<Encoded<ToolCallResponse>, ApiError> {
+    if !tools(&state).iter().any(|tool| tool.name == name) {
+        return Err(ApiError::new(ErrorCode::ToolNotFound, format!("Unknown tool: {}", name)));
+    }
+    let output = call(&state, &caller, &name, args)
+        .await
+        .map_err(|e| ApiError::new(ErrorCode::InvalidArguments, e))?;
+    Ok(Encoded(format, ToolCallResponse {
+        output: output.text,
+        is_error: output.is_error,
+    }))
+}
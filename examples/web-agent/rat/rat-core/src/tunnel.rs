@@ -0,0 +1,80 @@
+//! ngrok tunnel management.
+
+use crate::events::EventKind;
+use crate::state::AppState;
+use serde::Deserialize;
+use std::process::Stdio;
+use std::sync::atomic::Ordering;
+use tokio::process::Command;
+use tracing::info;
+
+#[derive(Deserialize)]
+struct NgrokTunnel {
+    public_url: String,
+}
+
+#[derive(Deserialize)]
+struct NgrokApiResponse {
+    tunnels: Vec<NgrokTunnel>,
+}
+
+/// Start ngrok tunnel and return public URL. `tls` says the agent serves
+/// HTTPS on `port`.
+pub async fn start_ngrok(state: AppState, port: u16, tls: bool) -> anyhow::Result<String> {
+    info!("Starting ngrok tunnel on port {}", port);
+    state.shared.tunnel_enabled.store(true, Ordering::Relaxed);
+
+    // Configure ngrok with auth token from env
+    if let Ok(token) = std::env::var("NGROK_AUTHTOKEN") {
+        Command::new("ngrok")
+            .args(&["config", "add-authtoken", &token])
+            .output()
+            .await?;
+    }
+
+    // Spawn ngrok process; with TLS on, it has to speak HTTPS upstream
+    let upstream = if tls {
+        format!("https://localhost:{}", port)
+    } else {
+        port.to_string()
+    };
+    let mut child = Command::new("ngrok")
+        .args(&["http", &upstream, "--log", "stdout"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    // Give ngrok time to start
+    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+
+    // Query ngrok API to get public URL
+    let client = reqwest::Client::new();
+    for _ in 0..10 {
+        match client.get("http://127.0.0.1:4040/api/tunnels").send().await {
+            Ok(resp) => {
+                if let Ok(data) = resp.json::<NgrokApiResponse>().await {
+                    if let Some(tunnel) = data.tunnels.first() {
+                        let url = tunnel.public_url.clone();
+                        info!("🌍 PUBLIC URL: {}", url);
+                        info!("🌍 Access your server from anywhere at: {}", url);
+
+                        *state.shared.public_url.write().await = Some(url.clone());
+                        state.emit(EventKind::TunnelUrlChanged { url: url.clone() });
+
+                        return Ok(url);
+                    }
+                }
+            }
+            Err(_) => {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            }
+        }
+    }
+
+    // Keep ngrok process alive in background
+    tokio::spawn(async move {
+        let _ = child.wait().await;
+    });
+
+    Err(anyhow::anyhow!("Failed to get ngrok URL"))
+}
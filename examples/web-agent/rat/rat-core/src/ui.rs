@@ -0,0 +1,58 @@
+//! Embedded browser UIs, served from the binary with rust-embed: an
+//! xterm.js terminal at `/terminal/:session_id` and the admin dashboard at
+//! `/dashboard`. Both talk to the server only through the public API.
+
+use crate::state::AppState;
+use axum::{
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "assets/terminal/"]
+struct TerminalAssets;
+
+#[derive(RustEmbed)]
+#[folder = "assets/dashboard/"]
+struct DashboardAssets;
+
+/// Page and asset routes; mounted at the root, outside the versioned API
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/terminal/:session_id", get(terminal_page))
+        .route("/terminal/assets/*path", get(terminal_asset))
+        .route("/dashboard", get(dashboard_page))
+        .route("/dashboard/assets/*path", get(dashboard_asset))
+}
+
+/// The page reads the session id from its own URL
+async fn terminal_page() -> Response {
+    embedded::<TerminalAssets>("index.html")
+}
+
+async fn terminal_asset(Path(path): Path<String>) -> Response {
+    embedded::<TerminalAssets>(&path)
+}
+
+async fn dashboard_page() -> Response {
+    embedded::<DashboardAssets>("index.html")
+}
+
+async fn dashboard_asset(Path(path): Path<String>) -> Response {
+    embedded::<DashboardAssets>(&path)
+}
+
+fn embedded<A: RustEmbed>(path: &str) -> Response {
+    match A::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,176 @@
+//! API versioning: the `/v1` prefix, legacy unprefixed aliases and
+//! protocol negotiation through the `X-Rat-Protocol` header.
+//!
+//! A client may send `X-Rat-Protocol: <n>` on any request. Unsupported
+//! versions are rejected with 400 and the list of supported ones; every
+//! response carries the version the server spoke.
+//!
+//! `/capabilities` goes further and says which optional features this
+//! server has turned on, so a client can use what is there and skip what
+//! isn't instead of finding out from failed requests. Fields are only ever
+//! added; a client treats one it doesn't know as absent.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::compression::DEFLATE_PROTOCOL;
+use crate::encoding::{Encoded, Format};
+use crate::error::{ApiError, ErrorCode};
+use crate::state::AppState;
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+use utoipa::ToSchema;
+
+pub(crate) const PROTOCOL_HEADER: &str = "x-rat-protocol";
+
+/// Protocol versions this server speaks, oldest first
+pub(crate) const SUPPORTED_PROTOCOLS: &[u32] = &[1];
+
+/// Path prefix of the current API version
+pub(crate) const API_PREFIX: &str = "/v1";
+
+fn latest_protocol() -> u32 {
+    *SUPPORTED_PROTOCOLS.last().unwrap()
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct VersionResponse {
+    server_version: String,
+    protocols: Vec<u32>,
+    latest: u32,
+    api_prefix: String,
+}
+
+/// Server and protocol versions, for clients to pick a protocol before
+/// talking to the rest of the API
+#[utoipa::path(get, path = "/version", tag = "health",
+    responses((status = 200, body = VersionResponse)))]
+pub(crate) async fn version(format: Format) -> Encoded<VersionResponse> {
+    Encoded(format, VersionResponse {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocols: SUPPORTED_PROTOCOLS.to_vec(),
+        latest: latest_protocol(),
+        api_prefix: API_PREFIX.to_string(),
+    })
+}
+
+/// What a shell socket understands besides terminal input and output
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Framing {
+    /// `{"type": "resize", "cols": .., "rows": ..}` text frames resize the PTY
+    resize: bool,
+    /// Signal control frames; signal processes with
+    /// `/system/processes/:pid/signal` instead
+    signals: bool,
+    /// Output compression subprotocols offered in `Sec-WebSocket-Protocol`
+    compression: Vec<String>,
+    /// `/shell/new` creates a session and attaches in one step
+    create_and_attach: bool,
+}
+
+/// Optional parts of the API and whether this server has them enabled
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Subsystems {
+    /// `/jobs` and detached `/execute/stream`
+    jobs: bool,
+    /// File transfer; not implemented
+    files: bool,
+    /// Reachable through an ngrok tunnel
+    tunnel: bool,
+    repl: bool,
+    /// `/search`; needs `search.db_path`
+    search: bool,
+    /// `/admin/*` and `/events`; needs an admin token
+    admin: bool,
+    /// `/admin/access-log`; needs `logging.access_log`
+    access_log: bool,
+    /// `Idempotency-Key` on `/execute` and `/jobs`
+    idempotency: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CapabilitiesResponse {
+    server_version: String,
+    protocols: Vec<u32>,
+    latest: u32,
+    api_prefix: String,
+    /// Ways to reach the API: `http`, `websocket`, `sse`, `mcp`, and `grpc`
+    /// when built with it
+    transports: Vec<String>,
+    framing: Framing,
+    subsystems: Subsystems,
+}
+
+/// Features this server supports and has enabled, for clients to negotiate
+/// at connect time
+#[utoipa::path(get, path = "/capabilities", tag = "health",
+    responses((status = 200, body = CapabilitiesResponse)))]
+pub(crate) async fn capabilities(State(state): State<AppState>, format: Format) -> Encoded<CapabilitiesResponse> {
+    let config = state.config();
+    let mut transports = vec!["http", "websocket", "sse", "mcp"];
+    if cfg!(feature = "grpc") {
+        transports.push("grpc");
+    }
+    let compression = if config.websocket.compression { vec![DEFLATE_PROTOCOL.to_string()] } else { Vec::new() };
+
+    Encoded(format, CapabilitiesResponse {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocols: SUPPORTED_PROTOCOLS.to_vec(),
+        latest: latest_protocol(),
+        api_prefix: API_PREFIX.to_string(),
+        transports: transports.into_iter().map(String::from).collect(),
+        framing: Framing {
+            resize: true,
+            signals: false,
+            compression,
+            create_and_attach: true,
+        },
+        subsystems: Subsystems {
+            jobs: true,
+            files: false,
+            tunnel: state.shared.tunnel_enabled.load(Ordering::Relaxed),
+            repl: true,
+            search: config.search.db_path.is_some(),
+            admin: config.auth.admin_token.is_some(),
+            access_log: config.logging.access_log.is_some(),
+            idempotency: true,
+        },
+    })
+}
+
+/// Reject unsupported `X-Rat-Protocol` values and echo the negotiated one
+pub(crate) async fn negotiate(request: Request, next: Next) -> Response {
+    let requested = match request.headers().get(PROTOCOL_HEADER) {
+        None => latest_protocol(),
+        Some(value) => match value.to_str().ok().and_then(|v| v.trim().parse::<u32>().ok()) {
+            Some(v) if SUPPORTED_PROTOCOLS.contains(&v) => v,
+            _ => {
+                return ApiError::new(ErrorCode::UnsupportedProtocol, "Unsupported protocol version")
+                    .with_details(serde_json::json!({"supported": SUPPORTED_PROTOCOLS}))
+                    .into_response();
+            }
+        },
+    };
+
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .insert(PROTOCOL_HEADER, HeaderValue::from(requested));
+    response
+}
+
+/// Mark unprefixed legacy routes as deprecated and point at the `/v1` path
+pub(crate) async fn deprecate_legacy(request: Request, next: Next) -> Response {
+    let successor = format!("<{}{}>; rel=\"successor-version\"", API_PREFIX, request.uri().path());
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert("link", link);
+    }
+    response
+}
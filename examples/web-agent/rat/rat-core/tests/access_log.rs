@@ -0,0 +1,89 @@
+use rat_core::access_log::AccessEntry;
+use rat_core::config::{Config, TokenConfig};
+use rat_core::test_support::{ScriptedPty, TestServer};
+use std::time::Duration;
+
+async fn export(server: &TestServer, query: &str) -> String {
+    reqwest::Client::new()
+        .get(server.url(&format!("/v1/admin/access-log?{}", query)))
+        .bearer_auth("admin")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+/// Export until a `path` line turns up; entries are written in the background
+async fn export_until(server: &TestServer, query: &str, path: &str) -> String {
+    for _ in 0..50 {
+        let text = export(server, query).await;
+        if text.contains(path) {
+            return text;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("{} never reached the access log", path);
+}
+
+#[tokio::test]
+async fn requests_are_exported_as_json_and_clf() {
+    let log = std::env::temp_dir().join(format!("rat-access-{}.jsonl", uuid::Uuid::new_v4()));
+    let mut config = Config::default();
+    config.logging.access_log = Some(log.clone());
+    config.auth.admin_token = Some("admin".to_string());
+    config.auth.tokens = vec![TokenConfig {
+        name: "ci".to_string(),
+        token: "ci-token".to_string(),
+        max_sessions: None,
+        max_jobs_per_hour: None,
+        max_output_bytes_per_day: None,
+    }];
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    reqwest::Client::new()
+        .get(server.url("/v1/sessions?token=ci-token"))
+        .header("User-Agent", "access-test")
+        .send()
+        .await
+        .unwrap();
+
+    let json = export_until(&server, "format=json", "/v1/sessions").await;
+    let entry: AccessEntry = json
+        .lines()
+        .map(|line| serde_json::from_str::<AccessEntry>(line).unwrap())
+        .find(|entry| entry.path == "/v1/sessions")
+        .unwrap();
+    assert_eq!(entry.method, "GET");
+    assert_eq!(entry.status, 200);
+    assert_eq!(entry.token.as_deref(), Some("ci"));
+    assert_eq!(entry.remote_addr.as_deref(), Some("127.0.0.1"));
+    assert_eq!(entry.user_agent.as_deref(), Some("access-test"));
+
+    let clf = export(&server, "format=clf").await;
+    let line = clf.lines().find(|line| line.contains("/v1/sessions")).unwrap();
+    assert!(line.starts_with("127.0.0.1 - ci ["), "{}", line);
+    assert!(line.contains("] \"GET /v1/sessions HTTP/1.1\" 200 "), "{}", line);
+    assert!(!line.contains("ci-token"));
+
+    let later = export(&server, &format!("since_ms={}", entry.timestamp_ms + 1)).await;
+    assert!(!later.contains("/v1/sessions"));
+    let earlier = export(&server, &format!("until_ms={}", entry.timestamp_ms)).await;
+    assert!(!earlier.contains("/v1/sessions"));
+    let _ = std::fs::remove_file(log);
+}
+
+#[tokio::test]
+async fn export_needs_an_access_log() {
+    let mut config = Config::default();
+    config.auth.admin_token = Some("admin".to_string());
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let response = reqwest::Client::new()
+        .get(server.url("/v1/admin/access-log"))
+        .bearer_auth("admin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 501);
+}
@@ -0,0 +1,28 @@
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::Value;
+
+async fn capabilities(config: Config) -> Value {
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    reqwest::get(server.url("/v1/capabilities")).await.unwrap().json().await.unwrap()
+}
+
+#[tokio::test]
+async fn capabilities_follow_the_config() {
+    let defaults = capabilities(Config::default()).await;
+    assert_eq!(defaults["protocols"], serde_json::json!([1]));
+    assert_eq!(defaults["api_prefix"], "/v1");
+    assert_eq!(defaults["framing"]["resize"], true);
+    assert_eq!(defaults["framing"]["compression"], serde_json::json!(["rat.deflate"]));
+    assert_eq!(defaults["subsystems"]["jobs"], true);
+    assert_eq!(defaults["subsystems"]["files"], false);
+    assert_eq!(defaults["subsystems"]["admin"], false);
+    assert!(defaults["transports"].as_array().unwrap().contains(&Value::from("websocket")));
+
+    let mut config = Config::default();
+    config.websocket.compression = false;
+    config.auth.admin_token = Some("admin".to_string());
+    let configured = capabilities(config).await;
+    assert_eq!(configured["framing"]["compression"], serde_json::json!([]));
+    assert_eq!(configured["subsystems"]["admin"], true);
+}
@@ -0,0 +1,48 @@
+use rat_core::chaos;
+use rat_core::config::{parse_probability, ChaosConfig, Config};
+
+fn chaos_config(probability: f64) -> ChaosConfig {
+    ChaosConfig { probability, max_delay_ms: 0 }
+}
+
+#[tokio::test]
+async fn no_faults_at_zero() {
+    let config = chaos_config(0.0);
+    for _ in 0..200 {
+        let frames = chaos::apply(&config, "session", b"output".to_vec()).await;
+        assert_eq!(frames, Some(vec![b"output".to_vec()]));
+    }
+}
+
+#[tokio::test]
+async fn every_frame_is_faulted_at_one() {
+    let config = chaos_config(1.0);
+    let (mut dropped, mut split, mut killed) = (0, 0, 0);
+    for _ in 0..200 {
+        match chaos::apply(&config, "session", b"output".to_vec()).await {
+            None => killed += 1,
+            Some(frames) if frames.is_empty() => dropped += 1,
+            Some(frames) if frames.len() == 2 => {
+                assert_eq!(frames.concat(), b"output");
+                split += 1;
+            }
+            // Delayed, by up to max_delay_ms
+            Some(frames) => assert_eq!(frames, vec![b"output".to_vec()]),
+        }
+    }
+    assert!(dropped > 0 && split > 0 && killed > 0, "{} dropped, {} split, {} killed", dropped, split, killed);
+}
+
+#[test]
+fn probability_outside_zero_to_one_is_rejected() {
+    for probability in ["1.5", "-0.1", "nan"] {
+        let path = std::env::temp_dir().join(format!("rat-chaos-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, format!("[chaos]\nprobability = {}\n", probability)).unwrap();
+        let loaded = Config::load(Some(&path));
+        let _ = std::fs::remove_file(&path);
+        let error = loaded.expect_err(probability).to_string();
+        assert!(error.contains("between 0 and 1"), "{}", error);
+    }
+    assert_eq!(parse_probability("0.25"), Ok(0.25));
+    assert!(parse_probability("NaN").is_err());
+}
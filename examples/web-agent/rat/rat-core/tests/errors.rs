@@ -0,0 +1,63 @@
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::Value;
+
+async fn error_of(response: reqwest::Response) -> Value {
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("application/json"));
+    let body: Value = response.json().await.unwrap();
+    body["error"].clone()
+}
+
+#[tokio::test]
+async fn handler_errors_carry_a_code() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::Client::new()
+        .post(server.url("/v1/session/no-such-session/stop"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let error = error_of(response).await;
+    assert_eq!(error["code"], "SESSION_NOT_FOUND");
+    assert_eq!(error["message"], "Session not found");
+}
+
+#[tokio::test]
+async fn rejections_and_unknown_routes_are_wrapped() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(server.url("/v1/execute"))
+        .header("Content-Type", "text/plain")
+        .body("echo")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 415);
+    assert_eq!(error_of(response).await["code"], "UNSUPPORTED_MEDIA_TYPE");
+
+    let response = client.get(server.url("/v1/nope")).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+    assert_eq!(error_of(response).await["code"], "NOT_FOUND");
+
+    let response = client.get(server.url("/v1/execute")).send().await.unwrap();
+    assert_eq!(response.status(), 405);
+    assert!(response.headers().contains_key("allow"));
+    assert_eq!(error_of(response).await["code"], "METHOD_NOT_ALLOWED");
+}
+
+#[tokio::test]
+async fn unsupported_protocol_lists_supported_versions() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::Client::new()
+        .get(server.url("/v1/health"))
+        .header("X-Rat-Protocol", "99")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let error = error_of(response).await;
+    assert_eq!(error["code"], "UNSUPPORTED_PROTOCOL");
+    assert_eq!(error["details"]["supported"][0], 1);
+}
@@ -0,0 +1,189 @@
+use rat_core::config::{Config, PolicyConfig, ProfileConfig};
+use rat_core::test_support::{shell_command, ScriptedPty, TestServer};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn execute_returns_output() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response: Value = reqwest::Client::new()
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "echo", "args": ["hello"]}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["success"], true);
+    assert_eq!(response["output"], "hello\n");
+}
+
+#[tokio::test]
+async fn execute_stream_sends_lines_then_exit_code() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let body = reqwest::Client::new()
+        .post(server.url("/v1/execute/stream"))
+        .json(&json!({"command": "echo", "args": ["hi"]}))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let stdout = body.find("data: stdout: hi").expect("no stdout event");
+    let exit = body.find("data: exit_code: 0").expect("no exit event");
+    assert!(stdout < exit);
+    assert!(body.contains("data: stdout: hi\nid: 0\n"), "lines should carry their sequence number: {}", body);
+}
+
+#[tokio::test]
+async fn last_event_id_is_refused_where_the_stream_cant_resume() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    for path in ["/v1/execute/stream", "/v1/execute/stream?detach=true"] {
+        let response = reqwest::Client::new()
+            .post(server.url(path))
+            .header("Last-Event-ID", "3")
+            .json(&json!({"command": "echo", "args": ["hi"]}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400, "{}", path);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "BAD_REQUEST");
+    }
+}
+
+#[tokio::test]
+async fn unknown_command_fails() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::Client::new()
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "rat-test-no-such-command"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 500);
+}
+
+#[tokio::test]
+async fn unsupported_content_type_is_rejected() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::Client::new()
+        .post(server.url("/v1/execute"))
+        .header("content-type", "text/plain")
+        .body("echo hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 415);
+}
+
+#[tokio::test]
+async fn unsupported_protocol_is_rejected() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::Client::new()
+        .get(server.url("/v1/version"))
+        .header("x-rat-protocol", "999")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn readiness_uses_the_injected_pty() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::get(server.url("/v1/health/ready")).await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn profiles_set_env_cwd_and_rc() {
+    let dir = std::env::temp_dir().join(format!("rat-profile-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let dir = dir.canonicalize().unwrap();
+    let rc = dir.join("rc");
+    std::fs::write(&rc, "export FROM_RC=sourced\n").unwrap();
+
+    let mut config = Config::default();
+    config.profiles.insert(
+        "deploy".to_string(),
+        ProfileConfig {
+            env: [("DEPLOY_ENV".to_string(), "production".to_string())].into(),
+            cwd: Some(dir.clone()),
+            shell: None,
+            rc: Some(rc),
+        },
+    );
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let client = reqwest::Client::new();
+
+    let response: Value = client
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "sh", "args": ["-c", "echo $DEPLOY_ENV $FROM_RC; pwd"], "profile": "deploy"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["output"], format!("production sourced\n{}\n", dir.display()));
+
+    let response = client
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "true", "profile": "nope"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "PROFILE_NOT_FOUND");
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn large_responses_are_compressed_on_request() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    // Decompression stays off so the encoding can be checked
+    let client = reqwest::Client::builder().no_gzip().build().unwrap();
+    let execute = |script: &str| {
+        client
+            .post(server.url("/v1/execute"))
+            .header("Accept-Encoding", "gzip")
+            .json(&shell_command(script))
+            .send()
+    };
+
+    let response = execute("yes rat | head -n 2000").await.unwrap();
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    let compressed = response.bytes().await.unwrap();
+    let mut body = String::new();
+    std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&compressed[..]), &mut body).unwrap();
+    let body: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["output"], "rat\n".repeat(2000));
+    assert!(compressed.len() < 1000);
+
+    let small = execute("echo hi").await.unwrap();
+    assert!(small.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn policy_refuses_commands_it_doesnt_allow() {
+    let mut config = Config::default();
+    config.policy = PolicyConfig {
+        allowed_commands: vec!["echo".to_string(), "rm".to_string()],
+        denied_commands: vec!["rm".to_string()],
+    };
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let execute = |body: Value| reqwest::Client::new().post(server.url("/v1/execute")).json(&body).send();
+
+    let allowed = execute(json!({"command": "/bin/echo", "args": ["hi"]})).await.unwrap();
+    assert_eq!(allowed.status(), 200);
+
+    for command in ["ls", "rm"] {
+        let refused = execute(json!({"command": command})).await.unwrap();
+        assert_eq!(refused.status(), 403);
+        let body: Value = refused.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "COMMAND_DENIED");
+    }
+}
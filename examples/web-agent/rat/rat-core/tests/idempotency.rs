@@ -0,0 +1,77 @@
+use rat_core::config::Config;
+use rat_core::test_support::{shell_command, ScriptedPty, TestServer};
+use serde_json::Value;
+
+async fn post(server: &TestServer, path: &str, key: &str, body: &Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(server.url(path))
+        .header("Idempotency-Key", key)
+        .json(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn retried_execute_returns_the_first_result() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    // Prints how many times it has run
+    let counter = std::env::temp_dir().join(format!("rat-idempotency-{}", uuid::Uuid::new_v4()));
+    let request = shell_command(&format!("echo x >> {0}; wc -l < {0}", counter.display()));
+
+    let first = post(&server, "/v1/execute", "deploy-1", &request).await;
+    assert!(first.headers().get("idempotent-replayed").is_none());
+    let first: Value = first.json().await.unwrap();
+    assert_eq!(first["output"].as_str().unwrap().trim(), "1");
+
+    let retry = post(&server, "/v1/execute", "deploy-1", &request).await;
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    assert_eq!(retry.json::<Value>().await.unwrap(), first);
+
+    let other = post(&server, "/v1/execute", "deploy-2", &request).await;
+    assert_eq!(other.json::<Value>().await.unwrap()["output"].as_str().unwrap().trim(), "2");
+    let _ = std::fs::remove_file(counter);
+}
+
+#[tokio::test]
+async fn concurrent_retry_waits_for_the_original() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let counter = std::env::temp_dir().join(format!("rat-idempotency-{}", uuid::Uuid::new_v4()));
+    let request = shell_command(&format!("echo x >> {0}; sleep 1; wc -l < {0}", counter.display()));
+
+    let (first, retry) = tokio::join!(
+        post(&server, "/v1/execute", "slow", &request),
+        post(&server, "/v1/execute", "slow", &request),
+    );
+    let first: Value = first.json().await.unwrap();
+    let retry: Value = retry.json().await.unwrap();
+    assert_eq!(first, retry);
+    assert_eq!(first["output"].as_str().unwrap().trim(), "1");
+    let _ = std::fs::remove_file(counter);
+}
+
+#[tokio::test]
+async fn reused_key_with_another_body_is_refused() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    post(&server, "/v1/execute", "k", &shell_command("echo one")).await;
+
+    let response = post(&server, "/v1/execute", "k", &shell_command("echo two")).await;
+    assert_eq!(response.status(), 422);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "IDEMPOTENCY_KEY_REUSED");
+}
+
+#[tokio::test]
+async fn retried_job_returns_the_same_job() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let request = shell_command("sleep 5");
+
+    let first: Value = post(&server, "/v1/jobs", "job-1", &request).await.json().await.unwrap();
+    let retry = post(&server, "/v1/jobs", "job-1", &request).await;
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    let retry: Value = retry.json().await.unwrap();
+    assert_eq!(retry["id"], first["id"]);
+
+    let jobs: Vec<Value> = reqwest::get(server.url("/v1/jobs")).await.unwrap().json().await.unwrap();
+    assert_eq!(jobs.len(), 1);
+}
@@ -0,0 +1,154 @@
+use rat_core::config::Config;
+use rat_core::test_support::{shell_command, ScriptedPty, TestServer};
+use serde_json::Value;
+use std::time::Duration;
+
+async fn output(server: &TestServer, job_id: &str, from_seq: u64) -> Value {
+    reqwest::get(server.url(&format!("/v1/jobs/{}/output?from_seq={}", job_id, from_seq)))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+/// Poll until the job has finished and return its full output
+async fn finished_output(server: &TestServer, job_id: &str) -> Value {
+    for _ in 0..100 {
+        let page = output(server, job_id, 0).await;
+        if page["job"]["running"] == false {
+            return page;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("job {} did not finish", job_id);
+}
+
+fn texts(page: &Value) -> Vec<&str> {
+    page["lines"].as_array().unwrap().iter().map(|line| line["text"].as_str().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn job_output_is_spooled_by_sequence() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let job: Value = reqwest::Client::new()
+        .post(server.url("/v1/jobs"))
+        .json(&shell_command("echo one; echo two >&2; echo three"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job_id = job["id"].as_str().unwrap();
+
+    let page = finished_output(&server, job_id).await;
+    assert_eq!(page["job"]["exit_code"], 0);
+    assert_eq!(page["next_seq"], 3);
+    let mut lines = texts(&page);
+    lines.sort();
+    assert_eq!(lines, ["one", "three", "two"]);
+
+    let rest = output(&server, job_id, 2).await;
+    assert_eq!(rest["lines"].as_array().unwrap().len(), 1);
+    assert_eq!(rest["lines"][0]["seq"], 2);
+
+    let jobs: Vec<Value> = reqwest::get(server.url("/v1/jobs")).await.unwrap().json().await.unwrap();
+    assert!(jobs.iter().any(|job| job["id"] == job_id));
+}
+
+#[tokio::test]
+async fn detached_stream_survives_disconnect() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let mut response = reqwest::Client::new()
+        .post(server.url("/v1/execute/stream?detach=true"))
+        .json(&shell_command("echo start; sleep 1; echo done"))
+        .send()
+        .await
+        .unwrap();
+    let job_id = response.headers()["x-rat-job-id"].to_str().unwrap().to_string();
+    let first = response.chunk().await.unwrap().unwrap();
+    assert!(String::from_utf8_lossy(&first).contains(&format!("job_id: {}", job_id)));
+    drop(response);
+
+    let page = finished_output(&server, &job_id).await;
+    assert_eq!(texts(&page), ["start", "done"]);
+    assert_eq!(page["job"]["exit_code"], 0);
+}
+
+#[tokio::test]
+async fn stopped_job_keeps_its_output() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+    let job: Value = client
+        .post(server.url("/v1/jobs"))
+        .json(&shell_command("echo started; sleep 30"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job_id = job["id"].as_str().unwrap();
+
+    for _ in 0..50 {
+        if output(&server, job_id, 0).await["next_seq"] == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let stopped: Value = client
+        .post(server.url(&format!("/v1/jobs/{}/stop", job_id)))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stopped["running"], false);
+    assert_eq!(stopped["stopped"], true);
+    assert_eq!(texts(&output(&server, job_id, 0).await), ["started"]);
+
+    let missing = client.post(server.url("/v1/jobs/nope/stop")).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test]
+async fn job_stream_resumes_after_last_event_id() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+    let job: Value = client
+        .post(server.url("/v1/jobs"))
+        .json(&shell_command("echo a; echo b; echo c"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job_id = job["id"].as_str().unwrap();
+    finished_output(&server, job_id).await;
+
+    let body = client
+        .get(server.url(&format!("/v1/jobs/{}/stream", job_id)))
+        .header("Last-Event-ID", "0")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains(&format!("data: job_id: {}", job_id)));
+    assert!(!body.contains("stdout: a"), "line 0 was replayed: {}", body);
+    assert!(body.contains("data: stdout: b\nid: 1\n"));
+    assert!(body.contains("data: stdout: c\nid: 2\n"));
+    assert!(body.contains("data: exit_code: 0"));
+
+    let bad = client
+        .get(server.url(&format!("/v1/jobs/{}/stream", job_id)))
+        .header("Last-Event-ID", "nope")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(bad.status(), 400);
+}
@@ -0,0 +1,89 @@
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn sleep_request(secs: u32) -> Value {
+    json!({"command": "sleep", "args": [secs.to_string()]})
+}
+
+#[tokio::test]
+async fn slow_requests_time_out() {
+    let mut config = Config::default();
+    config.limits.route_timeouts.insert("/execute".to_string(), 1);
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    let started = Instant::now();
+    let response = reqwest::Client::new()
+        .post(server.url("/v1/execute"))
+        .json(&sleep_request(10))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 504);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "TIMEOUT");
+    assert_eq!(body["error"]["message"], "Command timed out after 1s and was killed");
+}
+
+#[tokio::test]
+async fn commands_are_killed_at_their_own_timeout() {
+    let mut config = Config::default();
+    // The command's timeout wins over the route's, in both directions
+    config.limits.route_timeouts.insert("/execute".to_string(), 1);
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "sleep", "args": ["2"], "timeout_secs": 5}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .post(server.url("/v1/execute/stream"))
+        .json(&json!({"command": "sh", "args": ["-c", "echo started; sleep 10"], "timeout_secs": 1}))
+        .send()
+        .await
+        .unwrap();
+    let body = tokio::time::timeout(Duration::from_secs(5), response.text()).await.unwrap().unwrap();
+    assert!(body.contains("data: stdout: started"), "{}", body);
+    assert!(body.contains("data: error: Command timed out after 1s; command killed"), "{}", body);
+}
+
+#[tokio::test]
+async fn in_flight_requests_are_capped() {
+    let mut config = Config::default();
+    config.limits.max_in_flight_requests = Some(1);
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let client = reqwest::Client::new();
+
+    let slow = tokio::spawn(client.post(server.url("/v1/execute")).json(&sleep_request(2)).send());
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let response = client.get(server.url("/v1/sessions")).send().await.unwrap();
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["retry-after"], "1");
+
+    assert_eq!(slow.await.unwrap().unwrap().status(), 200);
+    let response = client.get(server.url("/v1/sessions")).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+}
+
+#[tokio::test]
+async fn stalled_headers_close_the_connection() {
+    let mut config = Config::default();
+    config.server.header_read_timeout_secs = 1;
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    let mut stream = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+    // Never finish the headers
+    stream.write_all(b"GET /v1/health HTTP/1.1\r\nHost: localhost\r\n").await.unwrap();
+    let mut rest = Vec::new();
+    let closed = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut rest)).await;
+    assert!(closed.is_ok(), "connection was still open after the header read timeout");
+}
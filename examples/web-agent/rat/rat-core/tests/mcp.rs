@@ -0,0 +1,131 @@
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::{json, Value};
+
+async fn rpc(server: &TestServer, message: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(server.url("/v1/mcp"))
+        .json(&message)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn call(server: &TestServer, method: &str, params: Value) -> Value {
+    let response = rpc(server, json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params})).await;
+    assert_eq!(response.status(), 200);
+    let reply: Value = response.json().await.unwrap();
+    assert_eq!(reply["id"], 1);
+    reply
+}
+
+#[tokio::test]
+async fn initialize_then_list_tools() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let reply = call(
+        &server,
+        "initialize",
+        json!({"protocolVersion": "2025-03-26", "capabilities": {}, "clientInfo": {"name": "test", "version": "0"}}),
+    )
+    .await;
+    assert_eq!(reply["result"]["protocolVersion"], "2025-03-26");
+    assert!(reply["result"]["capabilities"]["tools"].is_object());
+
+    let initialized = rpc(&server, json!({"jsonrpc": "2.0", "method": "notifications/initialized"})).await;
+    assert_eq!(initialized.status(), 202);
+
+    let reply = call(&server, "tools/list", json!({})).await;
+    let tools = reply["result"]["tools"].as_array().unwrap();
+    let execute = tools.iter().find(|tool| tool["name"] == "execute").expect("no execute tool");
+    assert_eq!(execute["inputSchema"]["type"], "object");
+    assert!(execute["inputSchema"]["properties"]["command"].is_object());
+    assert!(tools.iter().any(|tool| tool["name"] == "attach_session"));
+}
+
+#[tokio::test]
+async fn execute_tool_returns_output() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let reply = call(
+        &server,
+        "tools/call",
+        json!({"name": "execute", "arguments": {"command": "echo", "args": ["hello"]}}),
+    )
+    .await;
+    assert_eq!(reply["result"]["isError"], false);
+    let text = reply["result"]["content"][0]["text"].as_str().unwrap();
+    let output: Value = serde_json::from_str(text).unwrap();
+    assert_eq!(output["exit_code"], 0);
+    assert_eq!(output["stdout"], "hello\n");
+}
+
+#[tokio::test]
+async fn file_tools_round_trip() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let path = std::env::temp_dir().join(format!("rat-mcp-{}.txt", uuid::Uuid::new_v4()));
+    let path = path.to_str().unwrap();
+
+    for (content, append) in [("one\n", false), ("two\n", true)] {
+        let reply = call(
+            &server,
+            "tools/call",
+            json!({"name": "write_file", "arguments": {"path": path, "content": content, "append": append}}),
+        )
+        .await;
+        assert_eq!(reply["result"]["isError"], false);
+    }
+
+    let reply = call(
+        &server,
+        "tools/call",
+        json!({"name": "read_file", "arguments": {"path": path, "max_bytes": 6}}),
+    )
+    .await;
+    let _ = std::fs::remove_file(path);
+    let text = reply["result"]["content"][0]["text"].as_str().unwrap();
+    let file: Value = serde_json::from_str(text).unwrap();
+    assert_eq!(file["content"], "one\ntw");
+    assert_eq!(file["size"], 8);
+    assert_eq!(file["truncated"], true);
+}
+
+#[tokio::test]
+async fn session_tools_manage_sessions() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let reply = call(&server, "tools/call", json!({"name": "create_session"})).await;
+    let text = reply["result"]["content"][0]["text"].as_str().unwrap();
+    let session_id = serde_json::from_str::<Value>(text).unwrap()["session_id"].as_str().unwrap().to_string();
+
+    let reply = call(&server, "tools/call", json!({"name": "attach_session", "arguments": {"session_id": session_id}})).await;
+    let text = reply["result"]["content"][0]["text"].as_str().unwrap();
+    let urls: Value = serde_json::from_str(text).unwrap();
+    assert!(urls["ws_url"].as_str().unwrap().ends_with(&format!("/v1/shell/{}", session_id)));
+    assert!(urls["terminal_url"].as_str().unwrap().ends_with(&format!("/terminal/{}", session_id)));
+
+    let reply = call(&server, "tools/call", json!({"name": "stop_session", "arguments": {"session_id": session_id}})).await;
+    assert_eq!(reply["result"]["isError"], false);
+    let reply = call(&server, "tools/call", json!({"name": "stop_session", "arguments": {"session_id": session_id}})).await;
+    assert_eq!(reply["result"]["isError"], true);
+}
+
+#[tokio::test]
+async fn protocol_errors() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let reply = call(&server, "no/such/method", json!({})).await;
+    assert_eq!(reply["error"]["code"], -32601);
+
+    let reply = call(&server, "tools/call", json!({"name": "no_such_tool"})).await;
+    assert_eq!(reply["error"]["code"], -32602);
+
+    let reply = call(&server, "tools/call", json!({"name": "execute", "arguments": {}})).await;
+    assert_eq!(reply["error"]["code"], -32602);
+
+    let response = reqwest::Client::new()
+        .post(server.url("/v1/mcp"))
+        .header("Content-Type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+    let reply: Value = response.json().await.unwrap();
+    assert_eq!(reply["error"]["code"], -32700);
+}
@@ -0,0 +1,120 @@
+use rat_core::config::{Config, TokenConfig};
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::{json, Value};
+
+fn config_with_token(token: TokenConfig) -> Config {
+    let mut config = Config::default();
+    config.auth.admin_token = Some("admin".to_string());
+    config.auth.tokens.push(token);
+    config
+}
+
+fn token(name: &str) -> TokenConfig {
+    TokenConfig {
+        name: name.to_string(),
+        token: format!("{}-secret", name),
+        max_sessions: None,
+        max_jobs_per_hour: None,
+        max_output_bytes_per_day: None,
+    }
+}
+
+async fn execute(server: &TestServer, token: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "echo", "args": ["hello"]}));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn session_quota_is_per_token() {
+    let config = config_with_token(TokenConfig { max_sessions: Some(1), ..token("agent") });
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let client = reqwest::Client::new();
+    let create = |token: Option<&'static str>| {
+        let mut request = client.post(server.url("/v1/session/create"));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send()
+    };
+
+    assert_eq!(create(Some("agent-secret")).await.unwrap().status(), 200);
+    assert_eq!(create(Some("agent-secret")).await.unwrap().status(), 429);
+    // Anonymous requests aren't charged to the token
+    assert_eq!(create(None).await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn job_quota_refuses_extra_commands() {
+    let config = config_with_token(TokenConfig { max_jobs_per_hour: Some(2), ..token("agent") });
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 200);
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 200);
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 429);
+    assert_eq!(execute(&server, Some("admin")).await.status(), 200);
+
+    let quotas: Vec<Value> = reqwest::Client::new()
+        .get(server.url("/v1/admin/quotas"))
+        .bearer_auth("admin")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(quotas[0]["name"], "agent");
+    assert_eq!(quotas[0]["jobs_last_hour"], 2);
+    assert_eq!(quotas[0]["max_jobs_per_hour"], 2);
+    assert_eq!(quotas[0]["output_bytes_today"], 12);
+}
+
+#[tokio::test]
+async fn commands_that_fail_to_spawn_are_not_charged() {
+    let config = config_with_token(TokenConfig { max_jobs_per_hour: Some(1), ..token("agent") });
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let missing = json!({"command": "rat-test-no-such-command"});
+
+    for path in ["/v1/execute", "/v1/execute/stream"] {
+        let response = reqwest::Client::new()
+            .post(server.url(path))
+            .bearer_auth("agent-secret")
+            .json(&missing)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 500, "{}", path);
+    }
+    // The one job allowed this hour is still there
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 200);
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 429);
+}
+
+#[tokio::test]
+async fn output_quota_stops_new_commands() {
+    let config = config_with_token(TokenConfig { max_output_bytes_per_day: Some(4), ..token("agent") });
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    // The first command runs and goes over; the next is refused
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 200);
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 429);
+}
+
+#[tokio::test]
+async fn require_token_rejects_anonymous_requests() {
+    let mut config = config_with_token(token("agent"));
+    config.auth.require_token = true;
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    assert_eq!(execute(&server, None).await.status(), 401);
+    assert_eq!(execute(&server, Some("wrong")).await.status(), 401);
+    assert_eq!(execute(&server, Some("agent-secret")).await.status(), 200);
+    assert_eq!(execute(&server, Some("admin")).await.status(), 200);
+
+    let health = reqwest::get(server.url("/v1/health")).await.unwrap();
+    assert_eq!(health.status(), 200);
+}
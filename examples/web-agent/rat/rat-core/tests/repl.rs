@@ -0,0 +1,70 @@
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::{json, Value};
+
+fn have(program: &str) -> bool {
+    std::process::Command::new(program)
+        .arg("--version")
+        .output()
+        .map_or(false, |output| output.status.success())
+}
+
+async fn post(server: &TestServer, path: &str, body: Value) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(server.url(path))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn python_eval_keeps_state_and_separates_output() {
+    if !have("python3") {
+        eprintln!("python3 not installed, skipping");
+        return;
+    }
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let (status, created) = post(&server, "/v1/repl/create", json!({"language": "python3"})).await;
+    assert_eq!(status, 200);
+    let eval = format!("/v1/repl/{}/eval", created["repl_id"].as_str().unwrap());
+
+    let (_, response) = post(&server, &eval, json!({"code": "x = 40\nprint('hello')\nx + 2"})).await;
+    assert_eq!(response["result"], "42");
+    assert_eq!(response["stdout"], "hello\n");
+    assert_eq!(response["error"], Value::Null);
+
+    let (_, response) = post(&server, &eval, json!({"code": "x / 0"})).await;
+    assert_eq!(response["result"], Value::Null);
+    assert!(response["error"].as_str().unwrap().contains("ZeroDivisionError"));
+
+    let repls: Vec<Value> = reqwest::get(server.url("/v1/repls")).await.unwrap().json().await.unwrap();
+    assert_eq!(repls[0]["evals"], 2);
+}
+
+#[tokio::test]
+async fn timed_out_eval_stops_the_repl() {
+    if !have("python3") {
+        eprintln!("python3 not installed, skipping");
+        return;
+    }
+    let mut config = Config::default();
+    config.repl.eval_timeout_secs = 1;
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let (_, created) = post(&server, "/v1/repl/create", json!({"language": "python3"})).await;
+    let id = created["repl_id"].as_str().unwrap();
+
+    let (status, _) = post(&server, &format!("/v1/repl/{}/eval", id), json!({"code": "while True: pass"})).await;
+    assert_eq!(status, 504);
+    let (status, _) = post(&server, &format!("/v1/repl/{}/stop", id), json!({})).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn unknown_language_is_rejected() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let (status, _) = post(&server, "/v1/repl/create", json!({"language": "cobol"})).await;
+    assert_eq!(status, 422);
+}
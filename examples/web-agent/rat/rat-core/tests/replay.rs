@@ -0,0 +1,98 @@
+use futures::{SinkExt, StreamExt};
+use rat_core::config::Config;
+use rat_core::recording::{Direction, FrameKind, Recording};
+use rat_core::test_support::{ScriptedPty, TestServer};
+use std::time::Duration;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A prompt, `ls` typed in two frames, a resize, and the listing
+const FIELD_REPORT: &str = r#"
+{"at_ms":0,"direction":"output","kind":"binary","data":"JCA="}
+{"at_ms":900,"direction":"input","kind":"binary","data":"bA=="}
+{"at_ms":901,"direction":"output","kind":"binary","data":"bA=="}
+{"at_ms":1000,"direction":"input","kind":"text","data":"eyJ0eXBlIjoicmVzaXplIiwiY29scyI6MTAwLCJyb3dzIjozMH0="}
+{"at_ms":1200,"direction":"input","kind":"binary","data":"cw0="}
+{"at_ms":1201,"direction":"output","kind":"binary","data":"cw0K"}
+{"at_ms":1210,"direction":"output","kind":"binary","data":"G1swMW0bWzM0bXNyYxtbMG0NCiQg"}
+"#;
+
+#[tokio::test]
+async fn replayed_session_produces_recorded_output() {
+    let recording = Recording::parse(FIELD_REPORT).unwrap();
+    let server = TestServer::start(ScriptedPty::replay(&recording), Config::default()).await;
+    let id = server.create_session().await;
+    let (mut socket, _) = connect_async(server.ws_url(&format!("/v1/shell/{}", id))).await.unwrap();
+
+    for frame in recording.frames(Direction::Input) {
+        let message = match frame.kind {
+            FrameKind::Binary => Message::Binary(frame.data.clone()),
+            FrameKind::Text => Message::Text(String::from_utf8(frame.data.clone()).unwrap()),
+        };
+        socket.send(message).await.unwrap();
+    }
+
+    let expected = recording.bytes(Direction::Output);
+    let mut received = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while received.len() < expected.len() {
+            if let Some(Ok(Message::Binary(data))) = socket.next().await {
+                received.extend_from_slice(&data);
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the recorded output");
+    assert_eq!(String::from_utf8_lossy(&received), String::from_utf8_lossy(&expected));
+}
+
+#[tokio::test]
+async fn attachments_are_recorded() {
+    let dir = std::env::temp_dir().join(format!("rat-recording-{}", uuid::Uuid::new_v4()));
+    let mut config = Config::default();
+    config.shell.record_dir = Some(dir.clone());
+    let server = TestServer::start(ScriptedPty::echo().with_banner("$ "), config).await;
+    let id = server.create_session().await;
+
+    let (mut socket, _) = connect_async(server.ws_url(&format!("/v1/shell/{}", id))).await.unwrap();
+    socket.send(Message::Text(r#"{"type":"resize","cols":90,"rows":20}"#.to_string())).await.unwrap();
+    socket.send(Message::Binary(b"hi\n".to_vec())).await.unwrap();
+    let mut received = Vec::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while !received.ends_with(b"hi\n") {
+            if let Some(Ok(Message::Binary(data))) = socket.next().await {
+                received.extend_from_slice(&data);
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the echo");
+    socket.close(None).await.unwrap();
+
+    // The recording is written in the background; wait for the last frame
+    let recording = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let file = std::fs::read_dir(&dir).ok().and_then(|mut entries| entries.next());
+            if let Some(Ok(entry)) = file {
+                // A line may be half written; try again later
+                if let Ok(recording) = Recording::load(&entry.path()) {
+                    if recording.bytes(Direction::Output).ends_with(b"hi\n") {
+                        return recording;
+                    }
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("recording was not written");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let inputs: Vec<_> = recording.frames(Direction::Input).collect();
+    assert_eq!(inputs.len(), 2);
+    assert!(inputs[0].is_control());
+    assert_eq!(inputs[1].data, b"hi\n");
+    assert_eq!(recording.bytes(Direction::Output), b"$ hi\n");
+    assert!(recording.frames.windows(2).all(|pair| pair[0].at_ms <= pair[1].at_ms));
+}
@@ -0,0 +1,72 @@
+use futures::{SinkExt, StreamExt};
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+fn search_config() -> (Config, std::path::PathBuf) {
+    let db = std::env::temp_dir().join(format!("rat-search-{}.db", uuid::Uuid::new_v4()));
+    let mut config = Config::default();
+    config.search.db_path = Some(db.clone());
+    (config, db)
+}
+
+/// Search until a hit turns up; entries are indexed in the background
+async fn search_for(server: &TestServer, query: &str) -> Vec<Value> {
+    for _ in 0..50 {
+        let response = reqwest::get(server.url(&format!("/v1/search?{}", query))).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let hits: Vec<Value> = response.json().await.unwrap();
+        if !hits.is_empty() {
+            return hits;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("nothing found for {:?}", query);
+}
+
+#[tokio::test]
+async fn commands_are_searchable() {
+    let (config, db) = search_config();
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    reqwest::Client::new()
+        .post(server.url("/v1/execute"))
+        .json(&json!({"command": "echo", "args": ["migrate-users"]}))
+        .send()
+        .await
+        .unwrap();
+
+    let hits = search_for(&server, "q=migrate-users&kind=command").await;
+    assert_eq!(hits[0]["kind"], "command");
+    assert_eq!(hits[0]["snippet"], "echo **migrate-users**");
+    assert!(hits[0]["session_id"].is_null());
+    let _ = std::fs::remove_file(db);
+}
+
+#[tokio::test]
+async fn typed_shell_input_is_searchable() {
+    let (config, db) = search_config();
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let id = server.create_session().await;
+
+    let (mut socket, _) = connect_async(server.ws_url(&format!("/v1/shell/{}", id))).await.unwrap();
+    // Backspace corrects the typo before the line is indexed
+    socket.send(Message::Binary(b"deploy \x1b[Aprodx\x7f\r".to_vec())).await.unwrap();
+    let _ = tokio::time::timeout(Duration::from_secs(1), socket.next()).await;
+
+    let hits = search_for(&server, &format!("q=deploy+prod&kind=input&session_id={}", id)).await;
+    assert_eq!(hits[0]["kind"], "input");
+    assert_eq!(hits[0]["session_id"], id);
+    assert_eq!(hits[0]["snippet"], "**deploy** **prod**");
+    let _ = std::fs::remove_file(db);
+}
+
+#[tokio::test]
+async fn search_is_off_without_a_database() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::get(server.url("/v1/search?q=anything")).await.unwrap();
+    assert_eq!(response.status(), 501);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "SEARCH_DISABLED");
+}
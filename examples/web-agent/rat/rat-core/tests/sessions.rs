@@ -0,0 +1,120 @@
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn create_list_and_stop() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+
+    let id = server.create_session().await;
+    let page: Value = client.get(server.url("/v1/sessions")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["sessions"][0]["id"], id.as_str());
+    assert_eq!(page["sessions"][0]["attached"], false);
+
+    let stop = client
+        .post(server.url(&format!("/v1/session/{}/stop", id)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stop.status(), 200);
+
+    let page: Value = client.get(server.url("/v1/sessions")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(page["total"], 0);
+    assert_eq!(page["sessions"], json!([]));
+}
+
+#[tokio::test]
+async fn list_pages_filters_and_sorts() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+    let mut ids = Vec::new();
+    for _ in 0..3 {
+        ids.push(server.create_session().await);
+        // Distinct creation times, so the default sort is the creation order
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+    let list = |query: &str| {
+        let request = client.get(server.url(&format!("/v1/sessions{}", query)));
+        async move { request.send().await.unwrap().json::<Value>().await.unwrap() }
+    };
+
+    let page = list("?limit=2").await;
+    assert_eq!(page["total"], 3);
+    assert_eq!(page["sessions"].as_array().unwrap().len(), 2);
+    assert_eq!(page["sessions"][0]["id"], ids[0].as_str());
+    let page = list("?limit=2&offset=2").await;
+    assert_eq!(page["sessions"][0]["id"], ids[2].as_str());
+    assert_eq!(page["sessions"].as_array().unwrap().len(), 1);
+
+    let page = list("?order=desc").await;
+    assert_eq!(page["sessions"][0]["id"], ids[2].as_str());
+
+    // Attachment state changes just after the socket opens or closes
+    let wait_for_attached = |expected: usize| {
+        let list = &list;
+        async move {
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                loop {
+                    let page = list("?state=attached").await;
+                    if page["total"] == expected {
+                        return page;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                }
+            })
+            .await
+            .expect("attachment state never changed")
+        }
+    };
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(server.ws_url(&format!("/v1/shell/{}", ids[1])))
+        .await
+        .unwrap();
+    let page = wait_for_attached(1).await;
+    assert_eq!(page["sessions"][0]["id"], ids[1].as_str());
+    let page = list("?state=detached").await;
+    assert_eq!(page["total"], 2);
+
+    // A client that disconnects leaves its session detached
+    socket.close(None).await.unwrap();
+    drop(socket);
+    wait_for_attached(0).await;
+    let page = list("?state=detached").await;
+    assert_eq!(page["total"], 3);
+}
+
+#[tokio::test]
+async fn stopping_an_unknown_or_stopped_session_is_not_found() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+
+    let unknown = server.url("/v1/session/no-such-session/stop");
+    assert_eq!(client.post(&unknown).send().await.unwrap().status(), 404);
+
+    let id = server.create_session().await;
+    let url = server.url(&format!("/v1/session/{}/stop", id));
+    assert_eq!(client.post(&url).send().await.unwrap().status(), 200);
+    assert_eq!(client.post(&url).send().await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn session_limit_is_enforced() {
+    let mut config = Config::default();
+    config.limits.max_sessions = Some(1);
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let client = reqwest::Client::new();
+
+    server.create_session().await;
+    let refused = client.post(server.url("/v1/session/create")).send().await.unwrap();
+    assert_eq!(refused.status(), 429);
+}
+
+#[tokio::test]
+async fn legacy_routes_are_marked_deprecated() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response = reqwest::get(server.url("/sessions")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("deprecation"));
+}
@@ -0,0 +1,310 @@
+use futures::{SinkExt, StreamExt};
+use rat_core::config::Config;
+use rat_core::test_support::{ScriptedPty, TestServer};
+use serde_json::Value;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn attach(server: &TestServer, id: &str) -> Socket {
+    let (socket, _) = connect_async(server.ws_url(&format!("/v1/shell/{}", id))).await.unwrap();
+    socket
+}
+
+/// Read output frames until everything received so far contains `expected`
+async fn read_until(socket: &mut Socket, expected: &str) -> String {
+    let mut received = String::new();
+    tokio::time::timeout(TIMEOUT, async {
+        while !received.contains(expected) {
+            match socket.next().await {
+                Some(Ok(Message::Binary(data))) => received.push_str(&String::from_utf8_lossy(&data)),
+                Some(Ok(_)) => {}
+                other => panic!("socket ended before {:?} arrived: {:?}", expected, other),
+            }
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("timed out waiting for {:?}, got {:?}", expected, received));
+    received
+}
+
+#[tokio::test]
+async fn attach_sees_banner_and_echo() {
+    let pty = ScriptedPty::echo().with_banner("$ ");
+    let server = TestServer::start(pty, Config::default()).await;
+    let id = server.create_session().await;
+
+    let mut socket = attach(&server, &id).await;
+    read_until(&mut socket, "$ ").await;
+
+    socket.send(Message::Binary(b"ls\n".to_vec())).await.unwrap();
+    read_until(&mut socket, "ls\n").await;
+
+    // Text that isn't a control message is typed like binary input
+    socket.send(Message::Text("pwd\n".to_string())).await.unwrap();
+    read_until(&mut socket, "pwd\n").await;
+}
+
+#[tokio::test]
+async fn scripted_replies() {
+    let pty = ScriptedPty::new(|input| match input {
+        b"whoami\n" => b"tester\n".to_vec(),
+        _ => b"command not found\n".to_vec(),
+    });
+    let server = TestServer::start(pty, Config::default()).await;
+    let id = server.create_session().await;
+
+    let mut socket = attach(&server, &id).await;
+    socket.send(Message::Binary(b"whoami\n".to_vec())).await.unwrap();
+    read_until(&mut socket, "tester\n").await;
+    socket.send(Message::Binary(b"nope\n".to_vec())).await.unwrap();
+    read_until(&mut socket, "command not found\n").await;
+}
+
+#[tokio::test]
+async fn resize_control_message() {
+    let pty = ScriptedPty::echo();
+    let server = TestServer::start(pty.clone(), Config::default()).await;
+    let id = server.create_session().await;
+
+    let mut socket = attach(&server, &id).await;
+    socket
+        .send(Message::Text(r#"{"type":"resize","cols":132,"rows":43}"#.to_string()))
+        .await
+        .unwrap();
+    // Input after the resize is echoed only once the resize was handled
+    socket.send(Message::Binary(b"after\n".to_vec())).await.unwrap();
+    let received = read_until(&mut socket, "after\n").await;
+
+    assert!(!received.contains("resize"), "control message was typed into the shell");
+    let resizes = pty.resizes();
+    assert_eq!(resizes.len(), 1);
+    assert_eq!((resizes[0].cols, resizes[0].rows), (132, 43));
+}
+
+#[tokio::test]
+async fn second_attach_is_rejected() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let id = server.create_session().await;
+
+    let mut first = attach(&server, &id).await;
+    first.send(Message::Binary(b"one\n".to_vec())).await.unwrap();
+    read_until(&mut first, "one\n").await;
+
+    let mut second = attach(&server, &id).await;
+    let next = tokio::time::timeout(TIMEOUT, second.next()).await.expect("second attachment was not closed");
+    let Some(Ok(Message::Close(Some(frame)))) = next else {
+        panic!("second attachment wasn't closed with a reason: {:?}", next);
+    };
+    assert_eq!(u16::from(frame.code), 4005);
+    let reason: Value = serde_json::from_str(&frame.reason).unwrap();
+    assert_eq!(reason["reason"], "already_attached");
+
+    // The first attachment is unaffected
+    first.send(Message::Binary(b"two\n".to_vec())).await.unwrap();
+    read_until(&mut first, "two\n").await;
+}
+
+#[tokio::test]
+async fn detached_session_can_be_attached_again() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let id = server.create_session().await;
+
+    let mut first = attach(&server, &id).await;
+    first.send(Message::Binary(b"one\n".to_vec())).await.unwrap();
+    read_until(&mut first, "one\n").await;
+    first.close(None).await.unwrap();
+    drop(first);
+
+    // Reattach once the server has seen the first client go
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let page: Value = reqwest::get(server.url("/v1/sessions?state=detached"))
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            if page["total"] == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("session never detached");
+
+    let mut second = attach(&server, &id).await;
+    second.send(Message::Binary(b"two\n".to_vec())).await.unwrap();
+    read_until(&mut second, "two\n").await;
+}
+
+#[tokio::test]
+async fn attach_to_unknown_session_is_closed() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let mut socket = attach(&server, "no-such-session").await;
+    let (code, reason) = close_reason(&mut socket).await;
+    assert_eq!(code, 4004);
+    assert_eq!(reason["reason"], "not_found");
+}
+
+/// Wait for the next event about `session_id` and return its type
+async fn next_event(events: &mut Socket, session_id: &str) -> String {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match events.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    let event: Value = serde_json::from_str(&text).unwrap();
+                    if event["session_id"] == session_id {
+                        return event["type"].as_str().unwrap().to_string();
+                    }
+                }
+                Some(Ok(_)) => {}
+                other => panic!("event stream ended: {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for an event")
+}
+
+#[tokio::test]
+async fn lifecycle_events_are_announced() {
+    let mut config = Config::default();
+    config.auth.admin_token = Some("secret".to_string());
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    let (mut events, _) = connect_async(server.ws_url("/v1/events?token=secret")).await.unwrap();
+    let id = server.create_session().await;
+    assert_eq!(next_event(&mut events, &id).await, "session_created");
+
+    let socket = attach(&server, &id).await;
+    assert_eq!(next_event(&mut events, &id).await, "session_attached");
+    drop(socket);
+    assert_eq!(next_event(&mut events, &id).await, "session_detached");
+
+    reqwest::Client::new()
+        .post(server.url(&format!("/v1/session/{}/stop", id)))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(next_event(&mut events, &id).await, "session_stopped");
+}
+
+#[tokio::test]
+async fn events_require_admin_token() {
+    let mut config = Config::default();
+    config.auth.admin_token = Some("secret".to_string());
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    match connect_async(server.ws_url("/v1/events?token=wrong")).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+        other => panic!("expected a 401, got {:?}", other.map(|(_, response)| response.status())),
+    }
+}
+
+/// Wait for the close frame and return its code and JSON reason
+async fn close_reason(socket: &mut Socket) -> (u16, Value) {
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match socket.next().await {
+                Some(Ok(Message::Close(Some(frame)))) => {
+                    return (u16::from(frame.code), serde_json::from_str(&frame.reason).unwrap());
+                }
+                Some(Ok(_)) => {}
+                other => panic!("socket ended without a close frame: {:?}", other),
+            }
+        }
+    })
+    .await
+    .expect("timed out waiting for the close frame")
+}
+
+#[tokio::test]
+async fn stopping_a_session_closes_its_socket_with_a_reason() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let id = server.create_session().await;
+    let mut socket = attach(&server, &id).await;
+    socket.send(Message::Binary(b"hi\n".to_vec())).await.unwrap();
+    read_until(&mut socket, "hi\n").await;
+
+    reqwest::Client::new()
+        .post(server.url(&format!("/v1/session/{}/stop", id)))
+        .send()
+        .await
+        .unwrap();
+    let (code, reason) = close_reason(&mut socket).await;
+    assert_eq!(code, 4001);
+    assert_eq!(reason["reason"], "stopped");
+    assert_eq!(reason["message"], "session stopped");
+}
+
+#[tokio::test]
+async fn idle_sessions_are_reaped() {
+    let mut config = Config::default();
+    config.shell.idle_timeout_secs = 1;
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let id = server.create_session().await;
+    let mut socket = attach(&server, &id).await;
+
+    let (code, reason) = close_reason(&mut socket).await;
+    assert_eq!(code, 4002);
+    assert_eq!(reason["reason"], "idle");
+    assert!(reason["message"].as_str().unwrap().starts_with("session reaped after"));
+}
+
+#[tokio::test]
+async fn shell_new_creates_and_attaches() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let (mut socket, _) = connect_async(server.ws_url("/v1/shell/new?name=work&cols=120&rows=40")).await.unwrap();
+
+    let hello = match tokio::time::timeout(TIMEOUT, socket.next()).await.unwrap() {
+        Some(Ok(Message::Text(text))) => serde_json::from_str::<Value>(&text).unwrap(),
+        other => panic!("expected the session frame, got {:?}", other),
+    };
+    assert_eq!(hello["type"], "session");
+    let id = hello["session_id"].as_str().unwrap().to_string();
+
+    socket.send(Message::Binary(b"hi\n".to_vec())).await.unwrap();
+    read_until(&mut socket, "hi\n").await;
+
+    let page: Value = reqwest::get(server.url("/v1/sessions")).await.unwrap().json().await.unwrap();
+    let session = &page["sessions"][0];
+    assert_eq!(session["id"], id);
+    assert_eq!(session["name"], "work");
+    assert_eq!(session["attached"], true);
+}
+
+#[tokio::test]
+async fn shell_new_fails_before_upgrading() {
+    let mut config = Config::default();
+    config.limits.max_sessions = Some(0);
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+
+    match connect_async(server.ws_url("/v1/shell/new")).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), 429),
+        other => panic!("expected an HTTP error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn session_output_is_throttled() {
+    let mut config = Config::default();
+    config.limits.session_output_bytes_per_sec = Some(1000);
+    let server = TestServer::start(ScriptedPty::echo(), config).await;
+    let id = server.create_session().await;
+    let mut socket = attach(&server, &id).await;
+
+    // A burst of one second's worth is allowed, so 3000 bytes take about 2s
+    let started = std::time::Instant::now();
+    let mut input = vec![b'x'; 2999];
+    input.push(b'!');
+    socket.send(Message::Binary(input)).await.unwrap();
+    let output = read_until(&mut socket, "!").await;
+    assert_eq!(output.len(), 3000);
+    assert!(started.elapsed() >= Duration::from_millis(1500), "took {:?}", started.elapsed());
+}
@@ -0,0 +1,117 @@
+use axum::extract::Query;
+use axum::{routing::get, Router};
+use rat_core::config::Config;
+use rat_core::plugin::{Plugin, ToolSpec};
+use rat_core::test_support::{ScriptedPty, TestServer};
+use rat_core::AppState;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+async fn manifest(server: &TestServer, query: &str) -> Vec<Value> {
+    reqwest::get(server.url(&format!("/v1/tools{}", query)))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn manifest_in_openai_and_anthropic_formats() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+
+    let openai = manifest(&server, "").await;
+    let execute = openai
+        .iter()
+        .find(|tool| tool["function"]["name"] == "execute")
+        .expect("no execute tool");
+    assert_eq!(execute["type"], "function");
+    assert_eq!(execute["function"]["parameters"]["type"], "object");
+    assert_eq!(execute["function"]["parameters"]["required"], json!(["command"]));
+
+    let anthropic = manifest(&server, "?format=anthropic").await;
+    assert_eq!(anthropic.len(), openai.len());
+    let execute = anthropic.iter().find(|tool| tool["name"] == "execute").expect("no execute tool");
+    assert!(execute["input_schema"]["properties"]["command"].is_object());
+}
+
+#[tokio::test]
+async fn call_tool_runs_it() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let response: Value = reqwest::Client::new()
+        .post(server.url("/v1/tools/execute"))
+        .json(&json!({"command": "echo", "args": ["hello"]}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["is_error"], false);
+    let output: Value = serde_json::from_str(response["output"].as_str().unwrap()).unwrap();
+    assert_eq!(output["stdout"], "hello\n");
+}
+
+#[tokio::test]
+async fn call_tool_rejects_unknown_tools_and_bad_arguments() {
+    let server = TestServer::start(ScriptedPty::echo(), Config::default()).await;
+    let client = reqwest::Client::new();
+
+    let response = client.post(server.url("/v1/tools/nope")).json(&json!({})).send().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .post(server.url("/v1/tools/execute"))
+        .json(&json!({"args": ["no command"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 422);
+}
+
+struct Greeter;
+
+impl Plugin for Greeter {
+    fn name(&self) -> &str {
+        "greeter"
+    }
+
+    fn router(&self) -> Router {
+        Router::new().route(
+            "/hello",
+            get(|Query(query): Query<HashMap<String, String>>| async move { format!("hello {}", query["name"]) }),
+        )
+    }
+
+    fn tools(&self) -> Vec<ToolSpec> {
+        vec![ToolSpec {
+            name: "hello".to_string(),
+            description: "Greet someone".to_string(),
+            method: "GET".to_string(),
+            path: "/hello".to_string(),
+            input_schema: json!({"type": "object", "properties": {"name": {"type": "string"}}}),
+        }]
+    }
+}
+
+#[tokio::test]
+async fn plugin_tools_are_listed_and_run() {
+    let server = TestServer::with_state(AppState::new().with_plugin(Greeter), Config::default()).await;
+
+    let anthropic = manifest(&server, "?format=anthropic").await;
+    let hello = anthropic.iter().find(|tool| tool["name"] == "greeter_hello").expect("no plugin tool");
+    assert_eq!(hello["description"], "Greet someone");
+    assert!(hello["input_schema"]["properties"]["name"].is_object());
+
+    let response: Value = reqwest::Client::new()
+        .post(server.url("/v1/tools/greeter_hello"))
+        .json(&json!({"name": "ada"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(response["is_error"], false);
+    assert_eq!(response["output"], "hello ada");
+}
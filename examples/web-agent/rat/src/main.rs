@@ -1,519 +1,466 @@
-use axum::{
-    extract::{Json, Path, WebSocketUpgrade, ws::{WebSocket, Message}},
-    http::StatusCode,
-    response::{IntoResponse, Response, sse::Event},
-    routing::{get, post},
-    Router,
-};
-use clap::Parser;
-use daemonize::Daemonize;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::process::Stdio;
-use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tokio::sync::mpsc;
-use tower_http::cors::CorsLayer;
-use tracing::{info, error, warn};
-use tracing_subscriber;
-use uuid::Uuid;
-use portable_pty::{PtySize, CommandBuilder, native_pty_system, PtyPair};
-use futures::{StreamExt, SinkExt};
-
-lazy_static::lazy_static! {
-    static ref PUBLIC_URL: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
-    static ref SESSIONS: Arc<Mutex<HashMap<String, Arc<Mutex<PtySession>>>>> = Arc::new(Mutex::new(HashMap::new()));
-}
+mod docs;
 
-struct PtySession {
-    id: String,
-    pty_pair: PtyPair,
-    master_taken: bool,
-}
+use clap::builder::BoolishValueParser;
+use clap::{Parser, Subcommand};
+use clap_complete::Shell;
+use daemonize::Daemonize;
+use rat_core::access_log::{self, AccessFormat};
+use rat_core::config::{parse_probability, Config, LogRotation};
+use rat_core::AppState;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Run as daemon (background process)
-    #[arg(short, long)]
-    daemon: bool,
-
-    /// Port to bind to
-    #[arg(short, long, default_value = "3000")]
-    port: u16,
-
-    /// Host to bind to
-    #[arg(long, default_value = "0.0.0.0")]
-    host: String,
-
-    /// Enable ngrok tunnel for internet access
-    #[arg(short, long)]
-    ngrok: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML config file. Flags override RAT_* environment variables, which
+    /// override the file
+    #[arg(short, long, env = "RAT_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Run as daemon (background process); `--daemon=false` overrides the
+    /// config file
+    #[arg(short, long, env = "RAT_DAEMON", num_args = 0..=1, require_equals = true,
+          default_missing_value = "true", value_parser = BoolishValueParser::new())]
+    daemon: Option<bool>,
+
+    /// PID file written when running as a daemon [default: /tmp/rat.pid]
+    #[arg(long, env = "RAT_PID_FILE")]
+    pid_file: Option<PathBuf>,
+
+    /// Working directory the daemon changes into [default: /tmp]
+    #[arg(long, env = "RAT_WORKING_DIR")]
+    working_dir: Option<PathBuf>,
+
+    /// Log file for tracing output (defaults to /tmp/rat.log when daemonized).
+    /// Rotated files get a date suffix; the daemon's raw stdout/stderr go to
+    /// the unsuffixed path
+    #[arg(long, env = "RAT_LOG_FILE")]
+    log_file: Option<PathBuf>,
+
+    /// How often the log file is rotated [default: daily]
+    #[arg(long, env = "RAT_LOG_ROTATION")]
+    log_rotation: Option<LogRotation>,
+
+    /// Append every API request to this file as JSON Lines, for export with
+    /// `rat access-log` or /admin/access-log
+    #[arg(long, env = "RAT_ACCESS_LOG")]
+    access_log: Option<PathBuf>,
+
+    /// Port to bind to [default: 3000]
+    #[arg(short, long, env = "RAT_PORT")]
+    port: Option<u16>,
+
+    /// Host to bind to [default: 0.0.0.0]
+    #[arg(long, env = "RAT_HOST")]
+    host: Option<String>,
+
+    /// Enable ngrok tunnel for internet access; `--ngrok=false` overrides
+    /// the config file
+    #[arg(short, long, env = "RAT_NGROK", num_args = 0..=1, require_equals = true,
+          default_missing_value = "true", value_parser = BoolishValueParser::new())]
+    ngrok: Option<bool>,
+
+    /// Bearer token for admin endpoints such as /events. Admin endpoints are
+    /// disabled when unset
+    #[arg(long, env = "RAT_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+
+    /// PEM certificate chain; with --tls-key, serves HTTPS and WSS
+    #[arg(long, env = "RAT_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "RAT_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Directory crash reports are written to when the server panics
+    /// [default: /tmp/rat-crashes]
+    #[arg(long, env = "RAT_CRASH_DIR")]
+    crash_dir: Option<PathBuf>,
+
+    /// URL to POST crash reports to, in addition to writing them to disk
+    #[arg(long, env = "RAT_CRASH_WEBHOOK")]
+    crash_webhook: Option<String>,
+
+    /// Seconds to wait for child processes to exit after SIGHUP (shells) or
+    /// SIGTERM (commands) on shutdown [default: 10]
+    #[arg(long, env = "RAT_SHUTDOWN_GRACE")]
+    shutdown_grace: Option<u64>,
+
+    /// Maximum number of concurrent PTY sessions [default: unlimited]
+    #[arg(long, env = "RAT_MAX_SESSIONS")]
+    max_sessions: Option<usize>,
+
+    /// Don't offer compressed WebSocket output, even to clients that ask
+    #[arg(long, env = "RAT_NO_WS_COMPRESSION")]
+    no_ws_compression: bool,
+
+    /// Serve the Model Context Protocol on stdin/stdout instead of HTTP, for
+    /// MCP clients that launch the agent themselves. Logs go to stderr
+    #[arg(long, conflicts_with = "daemon")]
+    mcp_stdio: bool,
+
+    /// Inject faults into shell sockets with this probability per frame
+    #[arg(long, env = "RAT_CHAOS", value_name = "PROBABILITY", value_parser = parse_probability, hide = true)]
+    chaos: Option<f64>,
 }
 
-#[derive(Deserialize, Serialize)]
-struct CommandRequest {
-    command: String,
-    args: Option<Vec<String>>,
-    working_dir: Option<String>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a completion script for your shell to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Write man pages and completion scripts into a directory, for packaging
+    #[command(hide = true)]
+    GenerateDocs { out_dir: PathBuf },
+    /// Export the access log over a time range, for log pipelines
+    AccessLog {
+        /// json (JSON Lines) or clf (Common Log Format)
+        #[arg(long, default_value = "json")]
+        format: AccessFormat,
+        /// Earliest request to include, Unix milliseconds
+        #[arg(long)]
+        since_ms: Option<u64>,
+        /// Only requests before this time, Unix milliseconds
+        #[arg(long)]
+        until_ms: Option<u64>,
+    },
 }
 
-#[derive(Serialize)]
-struct CommandResponse {
-    success: bool,
-    output: String,
-    error: Option<String>,
-}
+impl Args {
+    /// Layer flags and environment variables over the config file
+    fn apply_to(&self, config: &mut Config) {
+        if let Some(v) = self.daemon {
+            config.daemon.enabled = v;
+        }
+        if let Some(v) = self.ngrok {
+            config.tunnel.ngrok = v;
+        }
+        if self.no_ws_compression {
+            config.websocket.compression = false;
+        }
 
-#[derive(Serialize)]
-struct HealthResponse {
-    status: String,
-    version: String,
-    public_url: Option<String>,
+        if let Some(v) = &self.pid_file {
+            config.daemon.pid_file = v.clone();
+        }
+        if let Some(v) = &self.working_dir {
+            config.daemon.working_dir = v.clone();
+        }
+        if let Some(v) = &self.log_file {
+            config.logging.file = Some(v.clone());
+        }
+        if let Some(v) = self.log_rotation {
+            config.logging.rotation = v;
+        }
+        if let Some(v) = &self.access_log {
+            config.logging.access_log = Some(v.clone());
+        }
+        if let Some(v) = self.port {
+            config.server.port = v;
+        }
+        if let Some(v) = &self.host {
+            config.server.host = v.clone();
+        }
+        if let Some(v) = &self.admin_token {
+            config.auth.admin_token = Some(v.clone());
+        }
+        if let Some(v) = &self.tls_cert {
+            config.tls.cert = Some(v.clone());
+        }
+        if let Some(v) = &self.tls_key {
+            config.tls.key = Some(v.clone());
+        }
+        if let Some(v) = &self.crash_dir {
+            config.crash.dir = v.clone();
+        }
+        if let Some(v) = &self.crash_webhook {
+            config.crash.webhook = Some(v.clone());
+        }
+        if let Some(v) = self.shutdown_grace {
+            config.server.shutdown_grace_secs = v;
+        }
+        if let Some(v) = self.max_sessions {
+            config.limits.max_sessions = Some(v);
+        }
+        if let Some(v) = self.chaos {
+            config.chaos.probability = v;
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct SessionCreateResponse {
-    session_id: String,
-    ws_url: String,
+/// Make file paths absolute, since the daemon changes directory before use
+fn resolve_paths(config: &mut Config) -> anyhow::Result<()> {
+    config.daemon.pid_file = absolute_path(&config.daemon.pid_file)?;
+    config.crash.dir = absolute_path(&config.crash.dir)?;
+    if let Some(file) = &config.logging.file {
+        config.logging.file = Some(absolute_path(file)?);
+    }
+    if let Some(file) = &config.logging.access_log {
+        config.logging.access_log = Some(absolute_path(file)?);
+    }
+    for profile in config.profiles.values_mut() {
+        if let Some(cwd) = &profile.cwd {
+            profile.cwd = Some(absolute_path(cwd)?);
+        }
+        if let Some(rc) = &profile.rc {
+            profile.rc = Some(absolute_path(rc)?);
+        }
+    }
+    if let Some(cert) = &config.tls.cert {
+        config.tls.cert = Some(absolute_path(cert)?);
+    }
+    if let Some(key) = &config.tls.key {
+        config.tls.key = Some(absolute_path(key)?);
+    }
+    if let Some(db) = &config.search.db_path {
+        config.search.db_path = Some(absolute_path(db)?);
+    }
+    Ok(())
 }
 
 #[derive(Serialize)]
-struct SessionInfo {
-    id: String,
-    active: bool,
+struct CrashReport {
+    version: String,
+    timestamp: u64,
+    pid: u32,
+    thread: Option<String>,
+    message: String,
+    location: Option<String>,
+    backtrace: String,
+    uptime_secs: u64,
+    active_sessions: usize,
 }
 
-#[derive(Deserialize)]
-struct NgrokTunnel {
-    public_url: String,
+/// Resolve relative paths against the launch directory, since the daemon
+/// changes into its working directory before they are used
+fn absolute_path(path: &std::path::Path) -> anyhow::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
 }
 
-#[derive(Deserialize)]
-struct NgrokApiResponse {
-    tunnels: Vec<NgrokTunnel>,
-}
+/// Fork into the background. Must run before the tokio runtime and the
+/// tracing writer thread exist, as neither survives a fork.
+fn daemonize(config: &Config, log_file: Option<&std::path::Path>) -> anyhow::Result<()> {
+    let mut daemonize = Daemonize::new()
+        .pid_file(&config.daemon.pid_file)
+        .working_directory(&config.daemon.working_dir);
+
+    if let Some(path) = log_file {
+        let stdout = OpenOptions::new().create(true).append(true).open(path)?;
+        let stderr = stdout.try_clone()?;
+        daemonize = daemonize.stdout(stdout).stderr(stderr);
+    }
 
-/// Health check endpoint
-async fn health() -> Json<HealthResponse> {
-    let public_url = PUBLIC_URL.lock().unwrap().clone();
-    Json(HealthResponse {
-        status: "ok".to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
-        public_url,
-    })
+    daemonize
+        .start()
+        .map_err(|e| anyhow::anyhow!("Failed to daemonize: {}", e))
 }
 
-/// Create a new PTY session
-async fn create_session() -> Result<Json<SessionCreateResponse>, (StatusCode, String)> {
-    info!("Creating new PTY session");
-
-    let session_id = Uuid::new_v4().to_string();
-
-    // Create PTY
-    let pty_system = native_pty_system();
-    let pty_pair = pty_system
-        .openpty(PtySize {
-            rows: 24,
-            cols: 80,
-            pixel_width: 0,
-            pixel_height: 0,
-        })
-        .map_err(|e| {
-            error!("Failed to create PTY: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create PTY: {}", e))
-        })?;
-
-    // Spawn shell in PTY
-    let mut cmd = CommandBuilder::new("bash");
-    cmd.env("TERM", "xterm-256color");
-
-    let _child = pty_pair.slave.spawn_command(cmd).map_err(|e| {
-        error!("Failed to spawn shell: {}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to spawn shell: {}", e))
-    })?;
-
-    let session = PtySession {
-        id: session_id.clone(),
-        pty_pair,
-        master_taken: false,
+/// Initialize tracing to stdout (stderr when stdout carries a protocol), or
+/// to a rotating file when a log file is set. The returned guard must be
+/// held for the life of the process so buffered lines are flushed on exit.
+fn init_tracing(
+    log_file: Option<&std::path::Path>,
+    default_filter: &str,
+    rotation: LogRotation,
+    stdout_reserved: bool,
+) -> Option<WorkerGuard> {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| default_filter.into());
+
+    let path = match log_file {
+        Some(path) => path,
+        None if stdout_reserved => {
+            tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr).init();
+            return None;
+        }
+        None => {
+            tracing_subscriber::fmt().with_env_filter(filter).init();
+            return None;
+        }
     };
 
-    // Store session
-    SESSIONS.lock().unwrap().insert(session_id.clone(), Arc::new(Mutex::new(session)));
-
-    let public_url = PUBLIC_URL.lock().unwrap().clone();
-    let ws_url = if let Some(url) = public_url {
-        format!("{}/shell/{}", url.replace("http", "ws"), session_id)
-    } else {
-        format!("ws://localhost:3000/shell/{}", session_id)
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| std::path::Path::new("."));
+    let prefix = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(|| "rat.log".into());
+
+    let rotation = match rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
     };
+    let appender = RollingFileAppender::new(rotation, dir, prefix);
+    let (writer, guard) = tracing_appender::non_blocking(appender);
 
-    info!("Created session {} with WebSocket URL: {}", session_id, ws_url);
-
-    Ok(Json(SessionCreateResponse {
-        session_id,
-        ws_url,
-    }))
-}
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(false)
+        .init();
 
-/// List all sessions
-async fn list_sessions() -> Json<Vec<SessionInfo>> {
-    let sessions = SESSIONS.lock().unwrap();
-    let list: Vec<SessionInfo> = sessions
-        .keys()
-        .map(|id| SessionInfo {
-            id: id.clone(),
-            active: true,
-        })
-        .collect();
-    Json(list)
+    Some(guard)
 }
 
-/// Stop a session
-async fn stop_session(Path(session_id): Path<String>) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    info!("Stopping session {}", session_id);
-
-    let mut sessions = SESSIONS.lock().unwrap();
-    if sessions.remove(&session_id).is_some() {
-        Ok(Json(serde_json::json!({"status": "stopped"})))
+fn panic_message(info: &std::panic::PanicHookInfo) -> String {
+    if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
     } else {
-        Err((StatusCode::NOT_FOUND, "Session not found".to_string()))
+        "Box<dyn Any>".to_string()
     }
 }
 
-/// WebSocket handler for shell I/O
-async fn shell_ws_handler(ws: WebSocketUpgrade, Path(session_id): Path<String>) -> Response {
-    info!("WebSocket connection request for session {}", session_id);
-
-    ws.on_upgrade(move |socket| handle_shell_socket(socket, session_id))
-}
-
-async fn handle_shell_socket(socket: WebSocket, session_id: String) {
-    let session_id_log = session_id.clone();
-    info!("WebSocket connected for session {}", session_id_log);
-
-    // Get session
-    let session = {
-        let sessions = SESSIONS.lock().unwrap();
-        sessions.get(&session_id).cloned()
-    };
-
-    let session = match session {
-        Some(s) => s,
-        None => {
-            error!("Session {} not found", session_id);
-            return;
+/// Install a panic hook that writes a JSON crash report to `crash_dir` and
+/// optionally POSTs it to `webhook`, then defers to the default hook
+fn install_panic_hook(state: AppState, crash_dir: PathBuf, webhook: Option<String>) {
+    let default_hook = std::panic::take_hook();
+
+    std::panic::set_hook(Box::new(move |info| {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let report = CrashReport {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp,
+            pid: std::process::id(),
+            thread: std::thread::current().name().map(str::to_string),
+            message: panic_message(info),
+            location: info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            uptime_secs: state.uptime().as_secs(),
+            active_sessions: state.session_count(),
+        };
+
+        let body = serde_json::to_string_pretty(&report).unwrap_or_default();
+        let path = crash_dir.join(format!("crash-{}-{}.json", timestamp, report.pid));
+        let created = std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&crash_dir);
+        match created.and_then(|_| std::fs::write(&path, &body)) {
+            Ok(()) => error!("Panic: {} (crash report written to {})", report.message, path.display()),
+            Err(e) => error!("Panic: {} (failed to write crash report: {})", report.message, e),
         }
-    };
-
-    let (mut ws_tx, mut ws_rx) = socket.split();
 
-    // Get PTY master (can only be taken once per session) - must drop lock immediately
-    let (mut pty_reader, mut pty_master) = {
-        let mut session_lock = session.lock().unwrap();
-        if session_lock.master_taken {
-            error!("Session {} master already taken", session_id);
-            return;
-        }
-        session_lock.master_taken = true;
-
-        // Clone reader before taking writer
-        let reader = session_lock.pty_pair.master.try_clone_reader().unwrap();
-        let writer = session_lock.pty_pair.master.take_writer().unwrap();
-        (reader, writer)
-    }; // lock dropped here
-
-    // Channels for PTY I/O
-    let (pty_tx, mut pty_rx) = mpsc::channel::<Vec<u8>>(100);
-    let (ws_to_pty_tx, mut ws_to_pty_rx) = mpsc::channel::<Vec<u8>>(100);
-
-    // Task 1: PTY reader (blocking I/O in separate thread)
-    std::thread::spawn(move || {
-        use std::io::Read;
-        let mut buf = [0u8; 8192];
-        loop {
-            match pty_reader.read(&mut buf) {
-                Ok(n) if n > 0 => {
-                    let data = buf[..n].to_vec();
-                    if pty_tx.blocking_send(data).is_err() {
-                        break;
-                    }
+        // The blocking client can't run on a runtime thread, so post from a
+        // fresh thread and wait for it before the process unwinds
+        if let Some(url) = webhook.clone() {
+            let _ = std::thread::spawn(move || {
+                let client = reqwest::blocking::Client::builder()
+                    .timeout(Duration::from_secs(5))
+                    .build();
+                if let Ok(client) = client {
+                    let _ = client
+                        .post(&url)
+                        .header("Content-Type", "application/json")
+                        .body(body)
+                        .send();
                 }
-                _ => break,
-            }
+            })
+            .join();
         }
-    });
 
-    // Task 2: PTY writer (blocking I/O in separate thread)
-    std::thread::spawn(move || {
-        use std::io::Write;
-        while let Some(data) = ws_to_pty_rx.blocking_recv() {
-            if pty_master.write_all(&data).is_err() {
-                break;
-            }
-            if pty_master.flush().is_err() {
-                break;
-            }
-        }
-    });
-
-    // Task 3: PTY → WebSocket
-    let session_id_clone = session_id.clone();
-    let read_task = tokio::spawn(async move {
-        while let Some(data) = pty_rx.recv().await {
-            if ws_tx.send(Message::Binary(data)).await.is_err() {
-                break;
-            }
-        }
-        info!("PTY→WS task ended for session {}", session_id_clone);
-    });
-
-    // Task 4: WebSocket → PTY
-    let session_id_clone2 = session_id.clone();
-    let write_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = ws_rx.next().await {
-            match msg {
-                Message::Binary(data) => {
-                    if ws_to_pty_tx.send(data).await.is_err() {
-                        break;
-                    }
-                }
-                Message::Text(text) => {
-                    if ws_to_pty_tx.send(text.into_bytes()).await.is_err() {
-                        break;
-                    }
-                }
-                Message::Close(_) => break,
-                _ => {}
-            }
-        }
-        info!("WS→PTY task ended for session {}", session_id_clone2);
-    });
-
-    // Wait for both tasks to complete
-    let _ = tokio::join!(read_task, write_task);
-    info!("WebSocket disconnected for session {}", session_id_log);
+        default_hook(info);
+    }));
 }
 
-/// Start ngrok tunnel and return public URL
-async fn start_ngrok(port: u16) -> anyhow::Result<String> {
-    info!("Starting ngrok tunnel on port {}", port);
-
-    // Configure ngrok with auth token from env
-    if let Ok(token) = std::env::var("NGROK_AUTHTOKEN") {
-        Command::new("ngrok")
-            .args(&["config", "add-authtoken", &token])
-            .output()
-            .await?;
-    }
-
-    // Spawn ngrok process
-    let mut child = Command::new("ngrok")
-        .args(&["http", &port.to_string(), "--log", "stdout"])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .spawn()?;
-
-    // Give ngrok time to start
-    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-
-    // Query ngrok API to get public URL
-    let client = reqwest::Client::new();
-    for _ in 0..10 {
-        match client.get("http://127.0.0.1:4040/api/tunnels").send().await {
-            Ok(resp) => {
-                if let Ok(data) = resp.json::<NgrokApiResponse>().await {
-                    if let Some(tunnel) = data.tunnels.first() {
-                        let url = tunnel.public_url.clone();
-                        info!("🌍 PUBLIC URL: {}", url);
-                        info!("🌍 Access your server from anywhere at: {}", url);
-
-                        // Store the URL globally
-                        *PUBLIC_URL.lock().unwrap() = Some(url.clone());
-
-                        return Ok(url);
-                    }
-                }
-            }
-            Err(_) => {
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            }
+fn main() -> anyhow::Result<()> {
+    let mut args = Args::parse();
+    let command = args.command.take();
+    match &command {
+        Some(Command::Completions { shell }) => {
+            docs::print_completions::<Args>(*shell);
+            return Ok(());
         }
+        Some(Command::GenerateDocs { out_dir }) => return docs::generate_docs::<Args>(out_dir),
+        _ => {}
     }
-
-    // Keep ngrok process alive in background
-    tokio::spawn(async move {
-        let _ = child.wait().await;
-    });
-
-    Err(anyhow::anyhow!("Failed to get ngrok URL"))
-}
-
-/// Execute a command and return the output
-async fn execute_command(
-    Json(payload): Json<CommandRequest>,
-) -> Result<Json<CommandResponse>, (StatusCode, String)> {
-    info!("Executing command: {} with args: {:?}", payload.command, payload.args);
-
-    let mut cmd = Command::new(&payload.command);
-
-    if let Some(args) = &payload.args {
-        cmd.args(args);
+    args.config = args.config.as_deref().map(absolute_path).transpose()?;
+
+    let mut config = Config::load(args.config.as_deref())?;
+    args.apply_to(&mut config);
+    resolve_paths(&mut config)?;
+    if let Some(Command::AccessLog { format, since_ms, until_ms }) = command {
+        let path = config
+            .logging
+            .access_log
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No access log to export; set logging.access_log or --access-log"))?;
+        return access_log::export(path, format, since_ms, until_ms, &mut std::io::stdout().lock());
     }
+    let log_file = config.log_file();
 
-    if let Some(working_dir) = &payload.working_dir {
-        cmd.current_dir(working_dir);
+    // If daemon mode is requested, daemonize the process
+    if config.daemon.enabled {
+        eprintln!("Starting in daemon mode (pid file {})...", config.daemon.pid_file.display());
+        daemonize(&config, log_file.as_deref())?;
     }
 
-    let output = cmd
-        .output()
-        .await
-        .map_err(|e| {
-            error!("Failed to execute command: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to execute command: {}", e))
-        })?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-    let response = CommandResponse {
-        success: output.status.success(),
-        output: stdout,
-        error: if stderr.is_empty() { None } else { Some(stderr) },
-    };
-
-    Ok(Json(response))
-}
-
-/// Execute a command and stream output line by line
-async fn execute_command_stream(
-    Json(payload): Json<CommandRequest>,
-) -> Response {
-    info!("Streaming command: {} with args: {:?}", payload.command, payload.args);
-
-    let mut cmd = Command::new(&payload.command);
-
-    if let Some(args) = &payload.args {
-        cmd.args(args);
+    // Initialize tracing
+    let mcp_stdio = args.mcp_stdio;
+    let _log_guard = init_tracing(
+        log_file.as_deref(),
+        &config.logging.filter,
+        config.logging.rotation,
+        mcp_stdio,
+    );
+    if config.daemon.enabled {
+        info!("Daemonized successfully");
     }
-
-    if let Some(working_dir) = &payload.working_dir {
-        cmd.current_dir(working_dir);
+    if let Some(path) = &args.config {
+        info!("Loaded config from {}", path.display());
+    }
+    if config.chaos.probability > 0.0 {
+        warn!("Chaos mode: shell socket faults injected with probability {}", config.chaos.probability);
     }
 
-    cmd.stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-
-    let mut child = match cmd.spawn() {
-        Ok(child) => child,
-        Err(e) => {
-            error!("Failed to spawn command: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to spawn command: {}", e)
-            ).into_response();
-        }
-    };
-
-    let stdout = child.stdout.take().unwrap();
-    let stderr = child.stderr.take().unwrap();
-
-    let stdout_reader = BufReader::new(stdout);
-    let stderr_reader = BufReader::new(stderr);
-
-    let stream = async_stream::stream! {
-        let mut stdout_lines = stdout_reader.lines();
-        let mut stderr_lines = stderr_reader.lines();
-
-        loop {
-            tokio::select! {
-                result = stdout_lines.next_line() => {
-                    match result {
-                        Ok(Some(line)) => {
-                            yield Ok::<_, anyhow::Error>(Event::default().data(format!("stdout: {}", line)));
-                        }
-                        Ok(None) => {}
-                        Err(e) => {
-                            yield Ok(Event::default().data(format!("error: {}", e)));
-                            break;
-                        }
-                    }
-                }
-                result = stderr_lines.next_line() => {
-                    match result {
-                        Ok(Some(line)) => {
-                            yield Ok::<_, anyhow::Error>(Event::default().data(format!("stderr: {}", line)));
-                        }
-                        Ok(None) => {}
-                        Err(e) => {
-                            yield Ok(Event::default().data(format!("error: {}", e)));
-                            break;
-                        }
-                    }
-                }
-                else => break,
-            }
-        }
-
-        // Wait for the command to complete
-        match child.wait().await {
-            Ok(status) => {
-                yield Ok(Event::default().data(format!("exit_code: {}", status.code().unwrap_or(-1))));
-            }
-            Err(e) => {
-                yield Ok(Event::default().data(format!("error: {}", e)));
-            }
-        }
-    };
-
-    axum::response::sse::Sse::new(stream).into_response()
-}
+    let state = AppState::new().with_config_loader(move || {
+        let mut fresh = Config::load(args.config.as_deref())?;
+        args.apply_to(&mut fresh);
+        resolve_paths(&mut fresh)?;
+        Ok(fresh)
+    });
+    install_panic_hook(state.clone(), config.crash.dir.clone(), config.crash.webhook.clone());
 
-fn create_router() -> Router {
-    Router::new()
-        .route("/health", get(health))
-        .route("/execute", post(execute_command))
-        .route("/execute/stream", post(execute_command_stream))
-        .route("/session/create", post(create_session))
-        .route("/sessions", get(list_sessions))
-        .route("/session/:session_id/stop", post(stop_session))
-        .route("/shell/:session_id", get(shell_ws_handler))
-        .layer(CorsLayer::permissive())
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(state, config, mcp_stdio))
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "rat=info,tower_http=info".into()),
-        )
-        .init();
+async fn run(state: AppState, config: Config, mcp_stdio: bool) -> anyhow::Result<()> {
+    tokio::spawn(rat_core::reload_on_sighup(state.clone()));
 
-    // If daemon mode is requested, daemonize the process
-    if args.daemon {
-        info!("Starting in daemon mode...");
-        let daemonize = Daemonize::new()
-            .pid_file("/tmp/rat.pid")
-            .working_directory("/tmp");
-
-        match daemonize.start() {
-            Ok(_) => info!("Daemonized successfully"),
-            Err(e) => {
-                error!("Error daemonizing: {}", e);
-                return Err(anyhow::anyhow!("Failed to daemonize: {}", e));
-            }
-        }
+    if mcp_stdio {
+        let grace = Duration::from_secs(config.server.shutdown_grace_secs);
+        rat_core::serve_mcp_stdio(state.clone(), config).await?;
+        rat_core::terminate_children(state, grace).await;
+        return Ok(());
     }
 
     // Start ngrok if requested
-    if args.ngrok {
-        match start_ngrok(args.port).await {
+    if config.tunnel.ngrok {
+        match rat_core::start_ngrok(state.clone(), config.server.port, config.tls.enabled()).await {
             Ok(_) => {},
             Err(e) => {
                 warn!("Failed to start ngrok: {}. Continuing without public URL.", e);
@@ -521,24 +468,74 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    let app = create_router();
-
-    let addr = format!("{}:{}", args.host, args.port);
+    let addr = format!("{}:{}", config.server.host, config.server.port);
+    let grace = Duration::from_secs(config.server.shutdown_grace_secs);
+    let header_read_timeout = Duration::from_secs(config.server.header_read_timeout_secs);
+    let tls = rat_core::tls_acceptor(&config.tls)?;
+    let app = rat_core::build_router(state.clone(), config);
     info!("Starting server on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
 
-    info!("Server listening on {}", addr);
-    info!("Endpoints:");
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    info!("Server listening on {}://{}", scheme, addr);
+    info!("Endpoints (under /v1; unprefixed paths are deprecated aliases):");
+    info!("  GET  /version              - Server and protocol versions");
+    info!("  GET  /capabilities         - Supported protocol, framing features and subsystems");
     info!("  GET  /health               - Health check");
+    info!("  GET  /health/live          - Liveness probe");
+    info!("  GET  /health/ready         - Readiness probe");
+    info!("  GET  /stats                - Server statistics");
+    info!("  GET  /metrics              - Prometheus metrics");
     info!("  POST /execute              - Execute command and return full output");
-    info!("  POST /execute/stream       - Execute command and stream output");
+    info!("  POST /execute/stream       - Execute command and stream output (?detach=true keeps it running)");
+    info!("  POST /jobs                 - Start a detached job");
+    info!("  GET  /jobs                 - List jobs");
+    info!("  GET  /jobs/:id             - Job status");
+    info!("  GET  /jobs/:id/output      - Spooled job output (?from_seq=)");
+    info!("  GET  /jobs/:id/stream      - Follow job output as SSE (Last-Event-ID resumes)");
+    info!("  POST /jobs/:id/stop        - Stop a job");
     info!("  POST /session/create       - Create new shell session");
     info!("  GET  /sessions             - List active sessions");
     info!("  POST /session/:id/stop     - Stop a session");
     info!("  WS   /shell/:id            - WebSocket shell connection");
+    info!("  WS   /shell/new            - Create a session and connect in one step");
+    info!("  POST /repl/create          - Start a python3, node or ruby REPL");
+    info!("  GET  /repls                - List REPLs");
+    info!("  POST /repl/:id/eval        - Evaluate code in a REPL");
+    info!("  POST /repl/:id/stop        - Stop a REPL");
+    info!("  GET  /tools                - Tool definitions for function-calling LLMs");
+    info!("  POST /tools/:name          - Run a tool call");
+    info!("  POST /mcp                  - Model Context Protocol (streamable HTTP)");
+    info!("  GET  /search               - Search shell transcripts and command history");
+    info!("  WS   /events               - Admin event stream");
+    info!("  POST /admin/reload         - Reload configuration");
+    info!("  GET  /admin/quotas         - Per-token quota usage");
+    info!("  GET  /admin/access-log     - Export the access log as JSON Lines or CLF");
+    info!("  GET  /plugins              - Registered plugins and their tools");
+    info!("  GET  /system/env           - Agent environment, secrets masked");
+    info!("  GET  /system/logs          - Journal, syslog or file log stream (SSE)");
+    info!("  WS   /system/metrics       - Live host metrics stream");
+    info!("  GET  /system/network       - Interfaces and listening sockets");
+    info!("  GET  /system/processes     - Process list (filter/sort via query)");
+    info!("  POST /system/processes/:pid/signal - Signal a process");
+    info!("  GET  /system/users         - Logged-in users and recent logins");
+    info!("  GET  /openapi.json         - OpenAPI specification");
+    info!("  GET  /swagger-ui           - Interactive API docs");
+    info!("  GET  /terminal/:id         - Browser terminal for a session");
+    info!("  GET  /dashboard            - Admin dashboard");
+    #[cfg(feature = "grpc")]
+    info!("gRPC service rat.v1.Rat on the same port (proto: rat-core/proto/rat.proto)");
+
+    let signal = rat_core::shutdown_signal(state.clone(), grace);
+    match tls {
+        Some(acceptor) => rat_core::serve_tls(listener, acceptor, app, header_read_timeout, signal).await,
+        None => rat_core::serve(listener, app, header_read_timeout, signal).await,
+    }
 
-    axum::serve(listener, app).await?;
+    // Connections are drained; make sure no child outlives the server
+    rat_core::terminate_children(state, grace).await;
+    info!("Server stopped");
 
     Ok(())
 }